@@ -0,0 +1,74 @@
+//! Moves arbitrary input balances with arbitrary edicts and pointers through `TransactionCache` and checks that no rune is
+//! created or lost along the way.
+#![no_main]
+
+mod common;
+
+use std::collections::HashMap;
+
+use arbitrary::Arbitrary;
+use chainhook_sdk::utils::Context;
+use libfuzzer_sys::fuzz_target;
+use ordinals::{Edict, RuneId};
+use runehook::db::cache::transaction_cache::TransactionCache;
+
+use common::{assert_conserved, input_runes, location, owner_script, InputBalance};
+
+#[derive(Arbitrary, Debug)]
+struct FuzzEdict {
+    rune: u8,
+    amount: u128,
+    output: u8,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    balances: Vec<InputBalance>,
+    /// One entry per transaction output, `false` for OP_RETURNs.
+    outputs: Vec<bool>,
+    pointer: Option<u8>,
+    edicts: Vec<FuzzEdict>,
+}
+
+fuzz_target!(|input: Input| {
+    let ctx = Context::empty();
+    // `0:0` refers to a rune etched by the transaction, there is none here so those edicts must be ignored.
+    let rune_ids = [
+        RuneId::new(839000, 1).unwrap(),
+        RuneId::new(839000, 2).unwrap(),
+        RuneId::new(839001, 1).unwrap(),
+        RuneId::default(),
+    ];
+    let (input_runes, supplied) = input_runes(&input.balances, &rune_ids[..3]);
+    let eligible_outputs: HashMap<u32, _> = input
+        .outputs
+        .iter()
+        .enumerate()
+        .filter(|(_, eligible)| **eligible)
+        .map(|(i, _)| (i as u32, owner_script(i as u8)))
+        .collect();
+    let first_eligible_output = eligible_outputs.keys().min().cloned();
+    let mut cache = TransactionCache::new(
+        location(1713571767),
+        input_runes,
+        eligible_outputs,
+        first_eligible_output,
+        input.outputs.len() as u32,
+    );
+    if let Some(pointer) = input.pointer {
+        cache.output_pointer = Some(pointer as u32);
+    }
+    let mut entries = vec![];
+    for edict in input.edicts.iter() {
+        entries.extend(cache.apply_edict(
+            &Edict {
+                id: rune_ids[edict.rune as usize % rune_ids.len()],
+                amount: edict.amount,
+                output: edict.output as u32,
+            },
+            &ctx,
+        ));
+    }
+    entries.extend(cache.allocate_remaining_balances(&ctx));
+    assert_conserved(&supplied, &entries);
+});
@@ -0,0 +1,137 @@
+//! Helpers shared by the fuzz targets.
+
+use std::collections::{HashMap, VecDeque};
+
+use arbitrary::Arbitrary;
+use bitcoin::{Network, ScriptBuf};
+use ordinals::RuneId;
+use runehook::db::{
+    cache::{input_rune_balance::InputRuneBalance, transaction_location::TransactionLocation},
+    models::{db_ledger_entry::DbLedgerEntry, db_ledger_operation::DbLedgerOperation},
+};
+
+/// Output that held an input balance.
+#[derive(Arbitrary, Debug)]
+pub enum Owner {
+    /// The P2WPKH output of a test wallet.
+    Address(u8),
+    /// An output whose script has no address, so it is only identified by it.
+    Script(u8),
+}
+
+/// A rune balance spent by the fuzzed transaction. Amounts are `u64` so they can't overflow when added up, the balances of a
+/// rune can never exceed its `u128` supply on chain either.
+#[derive(Arbitrary, Debug)]
+pub struct InputBalance {
+    /// Index of the rune in the list of candidate rune ids given to `input_runes`.
+    pub rune: u8,
+    /// Output that held this balance, or `None` if it was minted or premined.
+    pub owner: Option<Owner>,
+    pub amount: u64,
+}
+
+pub fn location(timestamp: u32) -> TransactionLocation {
+    TransactionLocation {
+        network: Network::Bitcoin,
+        block_hash: "0".repeat(64),
+        block_height: 840000,
+        timestamp,
+        tx_index: 1,
+        tx_id: "1".repeat(64),
+    }
+}
+
+/// P2WPKH script of the test wallet `owner`.
+pub fn owner_script(owner: u8) -> ScriptBuf {
+    let mut bytes = vec![0x00, 0x14];
+    bytes.extend([owner; 20]);
+    ScriptBuf::from_bytes(bytes)
+}
+
+fn owner_address(owner: u8) -> String {
+    bitcoin::Address::from_script(&owner_script(owner), Network::Bitcoin)
+        .unwrap()
+        .to_string()
+}
+
+/// Non-standard script without an address, `<owner> OP_DROP`.
+fn addressless_script(owner: u8) -> ScriptBuf {
+    ScriptBuf::from_bytes(vec![0x01, owner, 0x75])
+}
+
+/// Groups `balances` by rune as `TransactionCache` expects them. Returns them along with the total supplied per rune.
+pub fn input_runes(
+    balances: &[InputBalance],
+    rune_ids: &[RuneId],
+) -> (
+    HashMap<RuneId, VecDeque<InputRuneBalance>>,
+    HashMap<String, u128>,
+) {
+    let mut input_runes: HashMap<RuneId, VecDeque<InputRuneBalance>> = HashMap::new();
+    let mut supplied = HashMap::new();
+    if rune_ids.is_empty() {
+        return (input_runes, supplied);
+    }
+    for balance in balances.iter() {
+        let rune_id = rune_ids[balance.rune as usize % rune_ids.len()];
+        *supplied.entry(rune_id.to_string()).or_default() += balance.amount as u128;
+        input_runes
+            .entry(rune_id)
+            .or_default()
+            .push_back(match balance.owner {
+                Some(Owner::Address(owner)) => InputRuneBalance {
+                    address: Some(owner_address(owner)),
+                    script_pubkey: Some(owner_script(owner).to_hex_string()),
+                    amount: balance.amount as u128,
+                },
+                Some(Owner::Script(owner)) => InputRuneBalance {
+                    address: None,
+                    script_pubkey: Some(addressless_script(owner).to_hex_string()),
+                    amount: balance.amount as u128,
+                },
+                None => InputRuneBalance {
+                    address: None,
+                    script_pubkey: None,
+                    amount: balance.amount as u128,
+                },
+            });
+    }
+    (input_runes, supplied)
+}
+
+fn amount(entry: &DbLedgerEntry) -> u128 {
+    entry.amount.as_ref().map(|a| a.0).unwrap_or(0)
+}
+
+/// Checks that every unit `supplied` to the transaction was either received by an output or burned, that event indexes are
+/// sequential and that every `Send` follows the `Receive` of the move it belongs to.
+pub fn assert_conserved(supplied: &HashMap<String, u128>, entries: &[DbLedgerEntry]) {
+    let mut allocated: HashMap<String, u128> = HashMap::new();
+    let mut receive: Option<(&DbLedgerEntry, u128)> = None;
+    for (i, entry) in entries.iter().enumerate() {
+        assert_eq!(entry.event_index.0, i as u32);
+        match entry.operation {
+            DbLedgerOperation::Receive => {
+                *allocated.entry(entry.rune_id.clone()).or_default() += amount(entry);
+                receive = Some((entry, amount(entry)));
+            }
+            DbLedgerOperation::Burn => {
+                *allocated.entry(entry.rune_id.clone()).or_default() += amount(entry);
+                receive = None;
+            }
+            DbLedgerOperation::Send => {
+                let (received, remaining) = receive.as_mut().expect("send without receive");
+                assert_eq!(entry.rune_id, received.rune_id);
+                assert_eq!(entry.receiver_address, received.address);
+                *remaining = remaining
+                    .checked_sub(amount(entry))
+                    .expect("sent more than received");
+            }
+            _ => receive = None,
+        }
+    }
+    let mut supplied = supplied.clone();
+    supplied.retain(|_, amount| *amount > 0);
+    allocated.retain(|_, amount| *amount > 0);
+    assert_eq!(supplied, allocated);
+}
@@ -0,0 +1,222 @@
+//! Feeds arbitrary outputs and OP_RETURN payloads through `bitcoin_tx_from_chainhook_tx` and `Runestone::decipher`, then
+//! applies the resulting artifact to `TransactionCache` the same way `index_block` does, mints included, and checks that no
+//! rune is created or lost along the way.
+#![no_main]
+
+mod common;
+
+use arbitrary::Arbitrary;
+use bitcoin::{opcodes::all::OP_RETURN, script::Builder, script::PushBytesBuf};
+use chainhook_sdk::{
+    types::{
+        bitcoin::TxOut, BitcoinBlockData, BitcoinBlockMetadata, BitcoinNetwork,
+        BitcoinTransactionData, BitcoinTransactionMetadata, BlockIdentifier, TransactionIdentifier,
+    },
+    utils::Context,
+};
+use libfuzzer_sys::fuzz_target;
+use ordinals::{varint, Artifact, RuneId, Runestone};
+use runehook::db::{
+    cache::transaction_cache::TransactionCache,
+    index::bitcoin_tx_from_chainhook_tx,
+    models::db_rune::DbRune,
+    types::{
+        pg_bigint_u32::PgBigIntU32, pg_numeric_u128::PgNumericU128, pg_numeric_u64::PgNumericU64,
+    },
+};
+
+use common::{assert_conserved, input_runes, location, InputBalance};
+
+#[derive(Arbitrary, Debug)]
+enum Output {
+    /// Any script.
+    Script(Vec<u8>),
+    /// A runestone whose payload is made of these integers.
+    Integers(Vec<u128>),
+    /// A runestone with these data pushes as its payload.
+    Pushes(Vec<Vec<u8>>),
+}
+
+impl Output {
+    fn script_pubkey(&self) -> Vec<u8> {
+        let runestone = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(Runestone::MAGIC_NUMBER);
+        match self {
+            Output::Script(bytes) => bytes.clone(),
+            Output::Integers(integers) => {
+                let mut payload = vec![];
+                for integer in integers.iter() {
+                    varint::encode_to_vec(*integer, &mut payload);
+                }
+                runestone
+                    .push_slice(PushBytesBuf::try_from(payload).unwrap())
+                    .into_bytes()
+            }
+            Output::Pushes(pushes) => pushes
+                .iter()
+                .fold(runestone, |script, push| {
+                    script.push_slice(PushBytesBuf::try_from(push.clone()).unwrap())
+                })
+                .into_bytes(),
+        }
+    }
+}
+
+/// Terms of the rune minted by the runestone, unless it's the one being etched. Amounts are `u64` for the same reason as
+/// `InputBalance`.
+#[derive(Arbitrary, Debug)]
+struct MintedRune {
+    amount: Option<u64>,
+    cap: Option<u64>,
+    total_mints: u64,
+}
+
+impl MintedRune {
+    fn db_rune(&self, rune_id: &RuneId) -> DbRune {
+        DbRune {
+            id: rune_id.to_string(),
+            block_height: PgNumericU64(rune_id.block),
+            tx_index: PgBigIntU32(rune_id.tx),
+            terms_amount: self.amount.map(|amount| PgNumericU128(amount as u128)),
+            terms_cap: self.cap.map(|cap| PgNumericU128(cap as u128)),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    timestamp: u32,
+    outputs: Vec<Output>,
+    balances: Vec<InputBalance>,
+    minted_rune: MintedRune,
+}
+
+fuzz_target!(|input: Input| {
+    let ctx = Context::empty();
+    let tx = BitcoinTransactionData {
+        transaction_identifier: TransactionIdentifier::new(&"1".repeat(64)),
+        operations: vec![],
+        metadata: BitcoinTransactionMetadata {
+            inputs: vec![],
+            outputs: input
+                .outputs
+                .iter()
+                .map(|output| TxOut {
+                    value: 546,
+                    script_pubkey: format!("0x{}", hex::encode(output.script_pubkey())),
+                })
+                .collect(),
+            stacks_operations: vec![],
+            ordinal_operations: vec![],
+            brc20_operation: None,
+            proof: None,
+            fee: 0,
+            index: 1,
+        },
+    };
+    let block = BitcoinBlockData {
+        block_identifier: BlockIdentifier {
+            index: 840000,
+            hash: format!("0x{}", "0".repeat(64)),
+        },
+        parent_block_identifier: BlockIdentifier {
+            index: 839999,
+            hash: format!("0x{}", "0".repeat(64)),
+        },
+        timestamp: input.timestamp,
+        transactions: vec![tx],
+        metadata: BitcoinBlockMetadata {
+            network: BitcoinNetwork::Mainnet,
+        },
+    };
+    let (transaction, eligible_outputs, first_eligible_output, total_outputs) =
+        bitcoin_tx_from_chainhook_tx(&block, &block.transactions[0]);
+    let artifact = Runestone::decipher(&transaction);
+
+    // Give the transaction balances of the runes its runestone refers to. The rune it etches can't have been input yet.
+    let location = location(input.timestamp);
+    let mut rune_ids: Vec<RuneId> = match artifact.as_ref() {
+        Some(Artifact::Runestone(runestone)) => runestone
+            .edicts
+            .iter()
+            .map(|e| e.id)
+            .chain(runestone.mint)
+            .collect(),
+        Some(Artifact::Cenotaph(cenotaph)) => cenotaph.mint.into_iter().collect(),
+        None => vec![],
+    };
+    rune_ids.retain(|id| *id != RuneId::default() && *id != location.rune_id());
+    rune_ids.push(RuneId::new(839000, 1).unwrap());
+    let (input_runes, mut supplied) = input_runes(&input.balances, &rune_ids);
+
+    let mut cache = TransactionCache::new(
+        location,
+        input_runes,
+        eligible_outputs,
+        first_eligible_output,
+        total_outputs,
+    );
+    let mut entries = vec![];
+    match artifact {
+        Some(Artifact::Runestone(runestone)) => {
+            if let Some(pointer) = runestone.pointer {
+                assert!(pointer < total_outputs);
+                cache.output_pointer = Some(pointer);
+            }
+            let mut etched_rune = None;
+            if let Some(etching) = runestone.etching {
+                let (rune_id, db_rune, entry) = cache.apply_etching(&etching, 1);
+                *supplied.entry(rune_id.to_string()).or_default() += etching.premine.unwrap_or(0);
+                entries.push(entry);
+                etched_rune = Some(db_rune);
+            }
+            // `IndexCache` resolves `0:0` to the rune being etched, if any.
+            if let Some(mint_rune_id) = runestone.mint {
+                let rune = if mint_rune_id == RuneId::default() {
+                    etched_rune.map(|db_rune| (db_rune, 0))
+                } else {
+                    Some((
+                        input.minted_rune.db_rune(&mint_rune_id),
+                        input.minted_rune.total_mints as u128,
+                    ))
+                };
+                if let Some((db_rune, total_mints)) = rune {
+                    if let Some(entry) =
+                        cache.apply_mint(&mint_rune_id, total_mints, &db_rune, &ctx)
+                    {
+                        *supplied.entry(entry.rune_id.clone()).or_default() +=
+                            db_rune.terms_amount.unwrap().0;
+                        entries.push(entry);
+                    }
+                }
+            }
+            for edict in runestone.edicts.iter() {
+                assert!(edict.output <= total_outputs);
+                entries.extend(cache.apply_edict(edict, &ctx));
+            }
+        }
+        Some(Artifact::Cenotaph(cenotaph)) => {
+            entries.extend(cache.apply_cenotaph_input_burn(&cenotaph));
+            if let Some(rune) = cenotaph.etching {
+                let (_rune_id, _db_rune, entry) = cache.apply_cenotaph_etching(&rune, 1);
+                entries.push(entry);
+            }
+            if let Some(mint_rune_id) = cenotaph.mint {
+                let db_rune = input.minted_rune.db_rune(&mint_rune_id);
+                let total_mints = input.minted_rune.total_mints as u128;
+                let mint_entries =
+                    cache.apply_cenotaph_mint(&mint_rune_id, total_mints, &db_rune, &ctx);
+                if !mint_entries.is_empty() {
+                    *supplied.entry(mint_rune_id.to_string()).or_default() +=
+                        db_rune.terms_amount.unwrap().0;
+                    entries.extend(mint_entries);
+                }
+            }
+        }
+        None => {}
+    }
+    entries.extend(cache.allocate_remaining_balances(&ctx));
+    assert_conserved(&supplied, &entries);
+});
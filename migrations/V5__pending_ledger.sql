@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS pending_ledger (
+    rune_id                 TEXT NOT NULL,
+    tx_id                   TEXT NOT NULL,
+    event_index             BIGINT NOT NULL,
+    output                  BIGINT,
+    address                 TEXT,
+    receiver_address        TEXT,
+    amount                  NUMERIC,
+    operation               ledger_operation NOT NULL,
+    timestamp               BIGINT NOT NULL,
+    expires_at              BIGINT NOT NULL,
+    PRIMARY KEY (tx_id, event_index)
+);
+
+CREATE INDEX pending_ledger_address_rune_id_index ON pending_ledger (address, rune_id);
+CREATE INDEX pending_ledger_receiver_address_rune_id_index ON pending_ledger (receiver_address, rune_id);
+CREATE INDEX pending_ledger_expires_at_index ON pending_ledger (expires_at);
@@ -0,0 +1,13 @@
+CREATE TYPE mint_status AS ENUM ('not_yet_open', 'open', 'cap_reached', 'window_closed');
+
+-- Absolute block window in which mints are valid, the intersection of the height and offset terms. `NULL` means unbounded.
+ALTER TABLE runes
+    ADD COLUMN mint_start_height NUMERIC
+        GENERATED ALWAYS AS (GREATEST(terms_height_start, block_height + terms_offset_start)) STORED,
+    ADD COLUMN mint_end_height NUMERIC
+        GENERATED ALWAYS AS (LEAST(terms_height_end, block_height + terms_offset_end)) STORED,
+    -- Status of mints included in the block after the indexed chain tip, `NULL` for runes without mint terms. Filled in
+    -- after each indexed block.
+    ADD COLUMN mint_status mint_status;
+
+CREATE INDEX runes_mint_status_index ON runes (mint_status);
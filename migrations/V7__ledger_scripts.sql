@@ -0,0 +1,15 @@
+CREATE TYPE script_type AS ENUM (
+    'p2pk', 'p2pkh', 'p2sh', 'p2wpkh', 'p2wsh', 'p2tr', 'multisig', 'witness_unknown', 'nonstandard'
+);
+
+-- Hex encoded script of the output a row refers to. Rows indexed before this migration and rows without an output that can
+-- hold runes are `NULL`.
+ALTER TABLE ledger
+    ADD COLUMN script_pubkey TEXT,
+    ADD COLUMN script_type script_type;
+
+CREATE INDEX ledger_script_pubkey_rune_id_index ON ledger (script_pubkey, rune_id);
+
+ALTER TABLE pending_ledger
+    ADD COLUMN script_pubkey TEXT,
+    ADD COLUMN script_type script_type;
@@ -0,0 +1,6 @@
+-- Sat values of the transaction that produced each row, `NULL` for rows indexed before this migration. `output_value` is
+-- the value of `output` and `tx_vsize` is `NULL` when the transaction could not be decoded.
+ALTER TABLE ledger
+    ADD COLUMN output_value NUMERIC,
+    ADD COLUMN tx_fee NUMERIC,
+    ADD COLUMN tx_vsize BIGINT;
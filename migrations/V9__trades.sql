@@ -0,0 +1,20 @@
+-- Likely sales of runes listed through PSBT marketplaces, see `src/trades.rs`. `price` is in sats and `price_per_unit` is
+-- the price of a whole rune, adjusted by its divisibility.
+CREATE TABLE IF NOT EXISTS trades (
+    rune_id                 TEXT NOT NULL,
+    block_hash              TEXT NOT NULL,
+    block_height            NUMERIC NOT NULL,
+    tx_index                BIGINT NOT NULL,
+    tx_id                   TEXT NOT NULL,
+    seller_address          TEXT NOT NULL,
+    buyer_address           TEXT NOT NULL,
+    amount                  NUMERIC NOT NULL,
+    price                   NUMERIC NOT NULL,
+    price_per_unit          DOUBLE PRECISION NOT NULL,
+    timestamp               BIGINT NOT NULL
+);
+
+CREATE INDEX trades_rune_id_block_height_index ON trades (rune_id, block_height DESC);
+CREATE INDEX trades_block_height_index ON trades (block_height);
+CREATE INDEX trades_seller_address_index ON trades (seller_address);
+CREATE INDEX trades_buyer_address_index ON trades (buyer_address);
@@ -42,3 +42,33 @@ pub fn bitcoind_get_block_height(config: &Config, ctx: &Context) -> u64 {
         };
     }
 }
+
+/// Returns the network bitcoind is running on, making a single RPC call.
+#[cfg_attr(test, mutants::skip)]
+pub fn bitcoind_get_network(config: &Config) -> Result<String, String> {
+    let auth = Auth::UserPass(
+        config.event_observer.bitcoind_rpc_username.clone(),
+        config.event_observer.bitcoind_rpc_password.clone(),
+    );
+    let bitcoin_rpc = Client::new(&config.event_observer.bitcoind_rpc_url, auth)
+        .map_err(|e| format!("unable to create bitcoind RPC client: {e}"))?;
+    bitcoin_rpc
+        .get_blockchain_info()
+        .map(|info| info.chain.to_string())
+        .map_err(|e| format!("unable to call bitcoind RPC: {e}"))
+}
+
+/// Returns the hash of the block at `block_height` on bitcoind's active chain, making a single RPC call.
+#[cfg_attr(test, mutants::skip)]
+pub fn bitcoind_get_block_hash(config: &Config, block_height: u64) -> Result<String, String> {
+    let auth = Auth::UserPass(
+        config.event_observer.bitcoind_rpc_username.clone(),
+        config.event_observer.bitcoind_rpc_password.clone(),
+    );
+    let bitcoin_rpc = Client::new(&config.event_observer.bitcoind_rpc_url, auth)
+        .map_err(|e| format!("unable to create bitcoind RPC client: {e}"))?;
+    bitcoin_rpc
+        .get_block_hash(block_height)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("unable to get block hash at height {block_height}: {e}"))
+}
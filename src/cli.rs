@@ -1,14 +1,46 @@
-use std::{thread::sleep, time::Duration};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    str::FromStr,
+    thread::sleep,
+    time::Duration,
+};
 
 use clap::{Parser, Subcommand};
+use ordinals::RuneId;
+use tokio_postgres::Client;
 
-use chainhook_sdk::utils::{BlockHeights, Context};
+use chainhook_sdk::{
+    types::BitcoinNetwork,
+    utils::{BlockHeights, Context},
+};
 
 use crate::{
-    config::{generator::generate_config, Config},
-    db::{cache::index_cache::IndexCache, pg_connect},
+    bitcoind::{bitcoind_get_block_hash, bitcoind_get_block_height},
+    config::{
+        check::{check_config, validate_config},
+        generator::generate_config,
+        Config, StorageBackend,
+    },
+    db::{
+        cache::index_cache::IndexCache,
+        consistency::{pg_check_derived_tables, pg_repair_derived_tables, ConsistencyReport},
+        index::roll_back_to_block,
+        latest_migration_version, pg_connect, pg_get_block_height, pg_get_migration_version,
+        pg_get_pending_migrations, pg_get_table_stats, pg_try_connect, pg_vacuum_analyze,
+        rebuild::{rebuild_derived_tables, DERIVED_TABLES},
+        storage::{sqlite::SqliteStorage, ConnectStorage},
+    },
+    export::{block_partitions, export_tables, parse_export_tables, ExportFormat},
+    health::{start_health_server_runloop, ServiceState},
+    mempool::pg_get_pending_mint_counts,
+    mints::{get_mint_pressure, MintPressure},
+    monitoring::{start_metrics_server_runloop, PrometheusMonitoring},
     scan::bitcoin::{drop_blocks, scan_blocks},
     service::start_service,
+    shutdown::ShutdownSignal,
+    snapshot::{create_snapshot, restore_snapshot, verify_snapshot},
     try_info,
 };
 
@@ -33,6 +65,15 @@ enum Command {
     /// Perform maintenance operations on local databases
     #[clap(subcommand)]
     Db(DbCommand),
+    /// Export indexed tables to files partitioned by block range
+    #[clap(name = "export", bin_name = "export")]
+    Export(ExportCommand),
+    /// Create and restore portable database snapshots
+    #[clap(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Show confirmed, pending and remaining mints of runes
+    #[clap(name = "mints", bin_name = "mints")]
+    Mints(MintsCommand),
 }
 
 #[derive(Subcommand, PartialEq, Clone, Debug)]
@@ -41,6 +82,26 @@ enum ConfigCommand {
     /// Generate new config
     #[clap(name = "new", bin_name = "new", aliases = &["generate"])]
     New(NewConfig),
+    /// Print the effective config, including environment overrides, with secrets redacted
+    #[clap(name = "show", bin_name = "show")]
+    Show(ShowConfigCommand),
+    /// Validate the config and check connectivity to postgres and bitcoind
+    #[clap(name = "check", bin_name = "check")]
+    Check(CheckConfigCommand),
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct CheckConfigCommand {
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct ShowConfigCommand {
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
 }
 
 #[derive(Parser, PartialEq, Clone, Debug)]
@@ -55,7 +116,7 @@ struct NewConfig {
     /// Target Testnet network
     #[clap(
         long = "testnet",
-        conflicts_with = "simnet",
+        conflicts_with = "devnet",
         conflicts_with = "mainnet"
     )]
     pub testnet: bool,
@@ -63,9 +124,27 @@ struct NewConfig {
     #[clap(
         long = "mainnet",
         conflicts_with = "testnet",
-        conflicts_with = "simnet"
+        conflicts_with = "devnet"
     )]
     pub mainnet: bool,
+    /// Path of the generated config file
+    #[clap(long = "output", default_value = "Runehook.toml")]
+    pub output: String,
+    /// Overwrite the output file if it already exists
+    #[clap(long = "force")]
+    pub force: bool,
+}
+
+impl NewConfig {
+    pub fn get_network(&self) -> BitcoinNetwork {
+        if self.devnet {
+            BitcoinNetwork::Regtest
+        } else if self.testnet {
+            BitcoinNetwork::Testnet
+        } else {
+            BitcoinNetwork::Mainnet
+        }
+    }
 }
 
 #[derive(Subcommand, PartialEq, Clone, Debug)]
@@ -100,7 +179,7 @@ struct StartScanCommand {
     #[clap(long = "interval", conflicts_with = "blocks")]
     pub blocks_interval: Option<String>,
     /// List of blocks (--blocks 767430,767431,767433,800000)
-    #[clap(long = "blocks", conflicts_with = "interval")]
+    #[clap(long = "blocks", conflicts_with = "blocks_interval")]
     pub blocks: Option<String>,
 }
 
@@ -116,6 +195,24 @@ enum DbCommand {
     /// Rebuild inscriptions entries for a given block
     #[clap(name = "drop", bin_name = "drop")]
     Drop(DropDbCommand),
+    /// Show the indexed chain tip, table sizes and schema version
+    #[clap(name = "status", bin_name = "status")]
+    Status(DbStatusCommand),
+    /// Roll back every block above the given height in a single transaction
+    #[clap(name = "rollback-to", bin_name = "rollback-to")]
+    RollbackTo(RollbackToDbCommand),
+    /// Roll back to the block before the given height and scan again up to the bitcoind chain tip
+    #[clap(name = "reindex", bin_name = "reindex")]
+    Reindex(ReindexDbCommand),
+    /// Reclaim storage and refresh query planner statistics
+    #[clap(name = "vacuum-analyze", bin_name = "vacuum-analyze")]
+    VacuumAnalyze(VacuumAnalyzeDbCommand),
+    /// Verify supply and balance tables against the ledger and optionally repair them
+    #[clap(name = "check", bin_name = "check")]
+    Check(CheckDbCommand),
+    /// Rebuild supply and balance tables from the ledger and replace them
+    #[clap(name = "rebuild-derived", bin_name = "rebuild-derived")]
+    RebuildDerived(RebuildDerivedDbCommand),
 }
 
 #[derive(Parser, PartialEq, Clone, Debug)]
@@ -127,15 +224,148 @@ struct DropDbCommand {
     /// Load config file path
     #[clap(long = "config-path")]
     pub config_path: String,
+    /// Skip the confirmation prompt
+    #[clap(long = "yes", short = 'y')]
+    pub yes: bool,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct DbStatusCommand {
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct RollbackToDbCommand {
+    /// Last block to keep
+    pub block_height: u64,
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+    /// Skip the confirmation prompt
+    #[clap(long = "yes", short = 'y')]
+    pub yes: bool,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct ReindexDbCommand {
+    /// First block to index again
+    pub start_block: u64,
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+    /// Skip the confirmation prompt
+    #[clap(long = "yes", short = 'y')]
+    pub yes: bool,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct VacuumAnalyzeDbCommand {
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct CheckDbCommand {
+    /// First block to check, defaults to the runes genesis height
+    #[clap(long = "from")]
+    pub from: Option<u64>,
+    /// Last block to check, defaults to the indexed chain tip
+    #[clap(long = "to")]
+    pub to: Option<u64>,
+    /// Rewrite diverging rows with the values recomputed from the ledger
+    #[clap(long = "repair")]
+    pub repair: bool,
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+    /// Skip the confirmation prompt
+    #[clap(long = "yes", short = 'y')]
+    pub yes: bool,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct RebuildDerivedDbCommand {
+    /// Number of postgres connections rebuilding runes in parallel
+    #[clap(long = "jobs", default_value_t = 4)]
+    pub jobs: usize,
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+    /// Skip the confirmation prompt
+    #[clap(long = "yes", short = 'y')]
+    pub yes: bool,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct ExportCommand {
+    /// Comma separated list of tables (ledger, runes, supply_changes, balance_changes, trades)
+    #[clap(long = "tables", default_value = "ledger,runes")]
+    pub tables: String,
+    /// First block to export, defaults to the runes genesis height
+    #[clap(long = "from")]
+    pub from: Option<u64>,
+    /// Last block to export, defaults to the indexed chain tip
+    #[clap(long = "to")]
+    pub to: Option<u64>,
+    /// Output format, `csv` or `parquet`
+    #[clap(long = "format", default_value = "csv")]
+    pub format: String,
+    /// Number of blocks per exported file
+    #[clap(long = "partition-size", default_value_t = 10_000)]
+    pub partition_size: u64,
+    /// Output directory, each table is written to its own subdirectory
+    #[clap(long = "out")]
+    pub out: String,
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+}
+
+#[derive(Subcommand, PartialEq, Clone, Debug)]
+enum SnapshotCommand {
+    /// Dump every table and the indexed chain tip into a compressed, checksummed archive
+    #[clap(name = "create", bin_name = "create")]
+    Create(CreateSnapshotCommand),
+    /// Load a snapshot into an empty database so `service start` can continue from its tip
+    #[clap(name = "restore", bin_name = "restore")]
+    Restore(RestoreSnapshotCommand),
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct CreateSnapshotCommand {
+    /// Path of the archive to write
+    #[clap(long = "out")]
+    pub out: String,
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct RestoreSnapshotCommand {
+    /// Path of the archive to load
+    pub path: String,
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
+}
+
+#[derive(Parser, PartialEq, Clone, Debug)]
+struct MintsCommand {
+    /// Ids of the runes to report on (840000:1)
+    #[clap(required = true)]
+    pub rune_ids: Vec<String>,
+    /// Load config file path
+    #[clap(long = "config-path")]
+    pub config_path: String,
 }
 
 pub fn main() {
     let logger = hiro_system_kit::log::setup_logger();
-    let _guard = hiro_system_kit::log::setup_global_logger(logger.clone());
-    let ctx = Context {
-        logger: Some(logger),
-        tracer: false,
-    };
+    let _guard = hiro_system_kit::log::setup_global_logger(logger);
 
     let opts: Opts = match Opts::try_parse() {
         Ok(opts) => opts,
@@ -145,65 +375,587 @@ pub fn main() {
         }
     };
 
-    match hiro_system_kit::nestable_block_on(handle_command(opts, ctx)) {
-        Err(e) => {
-            println!("{e}");
-            std::process::exit(1);
-        }
-        Ok(_) => {}
+    if let Err(e) = hiro_system_kit::nestable_block_on(handle_command(opts)) {
+        println!("{e}");
+        std::process::exit(1);
     }
 }
 
-async fn handle_command(opts: Opts, ctx: Context) -> Result<(), String> {
+/// Each command builds its own logging context from the `[logs]` section of its config file.
+async fn handle_command(opts: Opts) -> Result<(), String> {
     match opts.command {
-        Command::Config(ConfigCommand::New(_options)) => {
-            use std::fs::File;
-            use std::io::Write;
+        Command::Config(ConfigCommand::New(options)) => {
+            use std::fs::OpenOptions;
+            use std::io::{ErrorKind, Write};
             use std::path::PathBuf;
-            let config_content = generate_config();
-            let mut file_path = PathBuf::new();
-            file_path.push("Runehook.toml");
-            let mut file = File::create(&file_path)
-                .map_err(|e| format!("unable to open file {}\n{}", file_path.display(), e))?;
+            let config_content = generate_config(&options.get_network());
+            let file_path = PathBuf::from(&options.output);
+            // `create_new` fails atomically if the file exists, so it can't appear between a check and the write.
+            let mut file = OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .create(true)
+                .create_new(!options.force)
+                .open(&file_path)
+                .map_err(|e| match e.kind() {
+                    ErrorKind::AlreadyExists => format!(
+                        "File {} already exists, use --force to overwrite it",
+                        file_path.display()
+                    ),
+                    _ => format!("unable to open file {}\n{}", file_path.display(), e),
+                })?;
             file.write_all(config_content.as_bytes())
                 .map_err(|e| format!("unable to write file {}\n{}", file_path.display(), e))?;
-            println!("Created file Runehook.toml");
+            println!("Created file {}", file_path.display());
+        }
+        Command::Config(ConfigCommand::Show(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            print!("{}", config.to_redacted_toml());
+        }
+        Command::Config(ConfigCommand::Check(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)
+                .map_err(|e| format!("[error] config: {e}"))?;
+            let mut failures = 0;
+            for outcome in check_config(&config).await {
+                match outcome.result {
+                    Ok(message) => println!("[ok] {}: {}", outcome.name, message),
+                    Err(message) => {
+                        failures += 1;
+                        println!("[error] {}: {}", outcome.name, message);
+                    }
+                }
+            }
+            if failures > 0 {
+                return Err(format!("{failures} config checks failed"));
+            }
         }
         Command::Service(ServiceCommand::Start(cmd)) => {
             let config = Config::from_file_path(&cmd.config_path)?;
+            validate_config(&config)?;
+            let ctx = config.logs.runes_context();
+            let monitoring = start_monitoring(&config, &ctx);
+            if config.health.enabled {
+                start_health_server_runloop(
+                    config.health.http_port,
+                    &monitoring,
+                    config.health.max_blocks_behind,
+                    &ctx,
+                );
+            }
             let maintenance_enabled = std::env::var("MAINTENANCE_MODE").unwrap_or("0".into());
             if maintenance_enabled.eq("1") {
+                monitoring.metrics_set_service_state(ServiceState::Maintenance);
                 try_info!(ctx, "Entering maintenance mode. Unset MAINTENANCE_MODE and reboot to resume operations.");
                 sleep(Duration::from_secs(u64::MAX))
             }
-            start_service(&config, &ctx).await?;
+            let shutdown = ShutdownSignal::new();
+            shutdown.install_handler(&ctx)?;
+            match config.storage.backend {
+                StorageBackend::Postgres => {
+                    start_service::<Client>(&config, &monitoring, &shutdown, &ctx).await?
+                }
+                StorageBackend::Sqlite => {
+                    start_service::<SqliteStorage>(&config, &monitoring, &shutdown, &ctx).await?
+                }
+            }
         }
         Command::Scan(ScanCommand::Start(cmd)) => {
             let config = Config::from_file_path(&cmd.config_path)?;
+            let ctx = config.logs.runes_context();
             let blocks = cmd.get_blocks();
-            let mut pg_client = pg_connect(&config, true, &ctx).await;
-            let mut index_cache = IndexCache::new(&config, &mut pg_client, &ctx).await;
-            scan_blocks(blocks, &config, &mut pg_client, &mut index_cache, &ctx).await?;
+            match config.storage.backend {
+                StorageBackend::Postgres => scan::<Client>(&config, blocks, &ctx).await?,
+                StorageBackend::Sqlite => scan::<SqliteStorage>(&config, blocks, &ctx).await?,
+            }
         }
         Command::Db(DbCommand::Drop(cmd)) => {
             let config = Config::from_file_path(&cmd.config_path)?;
+            let ctx = config.logs.runes_context();
+            confirm(
+                &format!(
+                    "{} blocks will be deleted.",
+                    cmd.end_block - cmd.start_block + 1
+                ),
+                cmd.yes,
+            )?;
+
+            match config.storage.backend {
+                StorageBackend::Postgres => {
+                    drop::<Client>(&config, cmd.start_block, cmd.end_block, &ctx).await?
+                }
+                StorageBackend::Sqlite => {
+                    drop::<SqliteStorage>(&config, cmd.start_block, cmd.end_block, &ctx).await?
+                }
+            }
+        }
+        Command::Db(DbCommand::Status(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            require_postgres(&config)?;
+            let ctx = config.logs.runes_context();
+            let mut pg_client = pg_try_connect(&config).await?;
+            let pending = pg_get_pending_migrations(&pg_client).await?;
+            let Some(version) = pg_get_migration_version(&pg_client).await? else {
+                println!(
+                    "Database has not been migrated yet ({} pending migrations)",
+                    pending.len()
+                );
+                return Ok(());
+            };
+            println!("Migration version: V{version} ({} pending)", pending.len());
+            match pg_get_block_height(&mut pg_client, &ctx).await {
+                Some(height) => println!("Indexed chain tip: {height}"),
+                None => println!("Indexed chain tip: none"),
+            }
+            println!("Tables (estimated rows, total size):");
+            for stats in pg_get_table_stats(&pg_client).await? {
+                println!(
+                    "  {:<16} {:>14} {:>12}",
+                    stats.table,
+                    stats.estimated_rows,
+                    format_bytes(stats.total_bytes)
+                );
+            }
+        }
+        Command::Db(DbCommand::RollbackTo(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            let ctx = config.logs.runes_context();
+            check_rollback_height(&config, cmd.block_height)?;
+            confirm(
+                &format!("All blocks above {} will be deleted.", cmd.block_height),
+                cmd.yes,
+            )?;
+            match config.storage.backend {
+                StorageBackend::Postgres => {
+                    let mut pg_client = Client::connect(&config, false, &ctx).await;
+                    roll_back_to_block(&mut pg_client, cmd.block_height, &ctx).await?;
+                }
+                StorageBackend::Sqlite => {
+                    let mut storage = SqliteStorage::connect(&config, false, &ctx).await;
+                    roll_back_to_block(&mut storage, cmd.block_height, &ctx).await?;
+                }
+            }
+        }
+        Command::Db(DbCommand::Reindex(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            let ctx = config.logs.runes_context();
+            let rollback_height = cmd.start_block.saturating_sub(1);
+            check_rollback_height(&config, rollback_height)?;
+            confirm(
+                &format!(
+                    "All blocks starting at {} will be deleted and indexed again.",
+                    cmd.start_block
+                ),
+                cmd.yes,
+            )?;
+            match config.storage.backend {
+                StorageBackend::Postgres => {
+                    reindex::<Client>(&config, cmd.start_block, &ctx).await?
+                }
+                StorageBackend::Sqlite => {
+                    reindex::<SqliteStorage>(&config, cmd.start_block, &ctx).await?
+                }
+            }
+        }
+        Command::Db(DbCommand::VacuumAnalyze(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            require_postgres(&config)?;
+            let ctx = config.logs.runes_context();
+            let pg_client = pg_connect(&config, false, &ctx).await;
+            pg_vacuum_analyze(&pg_client, &ctx).await?;
+        }
+        Command::Db(DbCommand::Check(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            require_postgres(&config)?;
+            let ctx = config.logs.runes_context();
+            let mut pg_client = pg_connect(&config, false, &ctx).await;
+            let start_block = cmd.from.unwrap_or(config.runes.genesis_block_height);
+            let end_block = match cmd.to {
+                Some(to) => to,
+                None => pg_get_block_height(&mut pg_client, &ctx)
+                    .await
+                    .ok_or("Nothing has been indexed yet")?,
+            };
+            println!("Checking blocks {start_block} to {end_block}");
+            let report = pg_check_derived_tables(&pg_client, start_block, end_block).await?;
+            print_consistency_report(&report);
+            if report.is_consistent() {
+                return Ok(());
+            }
+            if !cmd.repair || report.divergences.is_empty() {
+                return Err(format!(
+                    "{} inconsistencies found",
+                    report.divergences.len() + report.negative_balances.len()
+                ));
+            }
+
+            confirm(
+                &format!(
+                    "Derived rows between blocks {start_block} and {end_block} will be rewritten."
+                ),
+                cmd.yes,
+            )?;
+            let mut db_tx = pg_client
+                .transaction()
+                .await
+                .map_err(|e| format!("unable to begin transaction: {e}"))?;
+            let (supply_rows, balance_rows) =
+                pg_repair_derived_tables(start_block, end_block, &mut db_tx).await?;
+            db_tx
+                .commit()
+                .await
+                .map_err(|e| format!("unable to commit repair: {e}"))?;
             println!(
-                "{} blocks will be deleted. Confirm? [Y/n]",
-                cmd.end_block - cmd.start_block + 1
+                "Rewrote {supply_rows} supply_changes and {balance_rows} balance_changes rows"
             );
-            let mut buffer = String::new();
-            std::io::stdin().read_line(&mut buffer).unwrap();
-            if buffer.starts_with('n') {
-                return Err("Deletion aborted".to_string());
-            }
 
+            // Negative balances recomputed from the ledger point at a ledger bug, rewriting derived rows can't fix them.
+            let report = pg_check_derived_tables(&pg_client, start_block, end_block).await?;
+            if !report.is_consistent() {
+                print_consistency_report(&report);
+                return Err(format!(
+                    "{} inconsistencies remain after repair",
+                    report.divergences.len() + report.negative_balances.len()
+                ));
+            }
+        }
+        Command::Db(DbCommand::RebuildDerived(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            require_postgres(&config)?;
+            let ctx = config.logs.runes_context();
+            let tables = DERIVED_TABLES.map(|t| t.name);
+            confirm(
+                &format!(
+                    "{} will be rebuilt from the ledger and replaced once every rune is done. Make sure the indexer is stopped.",
+                    tables.join(", ")
+                ),
+                cmd.yes,
+            )?;
+            let written = rebuild_derived_tables(&config, cmd.jobs, &ctx).await?;
+            for (table, rows) in tables.iter().zip(written) {
+                println!("Rebuilt {table} with {rows} rows");
+            }
+        }
+        Command::Export(cmd) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            require_postgres(&config)?;
+            let ctx = config.logs.runes_context();
+            let tables = parse_export_tables(&cmd.tables)?;
+            let format: ExportFormat = cmd.format.parse()?;
+            let mut pg_client = pg_connect(&config, false, &ctx).await;
+            let start_block = cmd.from.unwrap_or(config.runes.genesis_block_height);
+            let end_block = match cmd.to {
+                Some(to) => to,
+                None => pg_get_block_height(&mut pg_client, &ctx)
+                    .await
+                    .ok_or("Nothing has been indexed yet")?,
+            };
+            let partitions = block_partitions(start_block, end_block, cmd.partition_size);
+            export_tables(
+                &mut pg_client,
+                &tables,
+                &partitions,
+                format,
+                Path::new(&cmd.out),
+                &ctx,
+            )
+            .await?;
+            println!(
+                "Exported {} partitions of blocks {start_block} to {end_block} to {}",
+                partitions.len(),
+                cmd.out
+            );
+        }
+        Command::Mints(cmd) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            let ctx = config.logs.runes_context();
+            let rune_ids = cmd
+                .rune_ids
+                .iter()
+                .map(|id| RuneId::from_str(id).map_err(|e| format!("Invalid rune id {id}: {e}")))
+                .collect::<Result<Vec<_>, _>>()?;
+            let results = match config.storage.backend {
+                StorageBackend::Postgres => {
+                    let mut pg_client = pg_connect(&config, false, &ctx).await;
+                    let pending_mints = if config.mempool.enabled {
+                        Some(pg_get_pending_mint_counts(&pg_client).await?)
+                    } else {
+                        None
+                    };
+                    get_mint_pressure(&mut pg_client, &rune_ids, pending_mints.as_ref(), &ctx)
+                        .await?
+                }
+                StorageBackend::Sqlite => {
+                    let mut storage = SqliteStorage::connect(&config, false, &ctx).await;
+                    get_mint_pressure(&mut storage, &rune_ids, None, &ctx).await?
+                }
+            };
+            for pressure in results.iter() {
+                print!("{}", format_mint_pressure(pressure));
+            }
+        }
+        Command::Snapshot(SnapshotCommand::Create(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            require_postgres(&config)?;
+            let ctx = config.logs.runes_context();
             let mut pg_client = pg_connect(&config, false, &ctx).await;
-            drop_blocks(cmd.start_block, cmd.end_block, &mut pg_client, &ctx).await;
+            let Some(migration_version) = pg_get_migration_version(&pg_client).await? else {
+                return Err("Database has not been migrated yet".to_string());
+            };
+            let tmp_path = format!("{}.tmp", cmd.out);
+            let file =
+                File::create(&tmp_path).map_err(|e| format!("unable to create {tmp_path}: {e}"))?;
+            let manifest = create_snapshot(
+                &mut pg_client,
+                migration_version,
+                &config.get_bitcoin_network().to_string(),
+                BufWriter::new(file),
+                &ctx,
+            )
+            .await?;
+            std::fs::rename(&tmp_path, &cmd.out)
+                .map_err(|e| format!("unable to move snapshot to {}: {e}", cmd.out))?;
+            println!(
+                "Created snapshot {} at block {} ({})",
+                cmd.out, manifest.block_height, manifest.block_hash
+            );
+        }
+        Command::Snapshot(SnapshotCommand::Restore(cmd)) => {
+            let config = Config::from_file_path(&cmd.config_path)?;
+            require_postgres(&config)?;
+            let ctx = config.logs.runes_context();
+            let open = || {
+                File::open(&cmd.path)
+                    .map(BufReader::new)
+                    .map_err(|e| format!("unable to open {}: {e}", cmd.path))
+            };
+            let manifest = verify_snapshot(open()?)?;
+            let network = config.get_bitcoin_network().to_string();
+            if manifest.network != network {
+                return Err(format!(
+                    "Snapshot was created on {}, but the config targets {network}",
+                    manifest.network
+                ));
+            }
+            if manifest.migration_version != latest_migration_version() {
+                return Err(format!(
+                    "Snapshot was created at migration version V{}, this runehook version expects V{}",
+                    manifest.migration_version,
+                    latest_migration_version()
+                ));
+            }
+            let block_hash = bitcoind_get_block_hash(&config, manifest.block_height)?;
+            if block_hash != manifest.block_hash {
+                return Err(format!(
+                    "Snapshot tip {} at block {} is not on bitcoind's active chain ({block_hash})",
+                    manifest.block_hash, manifest.block_height
+                ));
+            }
+
+            let mut pg_client = pg_connect(&config, true, &ctx).await;
+            restore_snapshot(&mut pg_client, &manifest, open()?, &ctx).await?;
+            println!(
+                "Restored snapshot at block {} ({}), `service start` will continue from there",
+                manifest.block_height, manifest.block_hash
+            );
         }
     }
     Ok(())
 }
 
+/// Prints a summary of `report` followed by the first diverging rows.
+fn print_consistency_report(report: &ConsistencyReport) {
+    const MAX_ROWS: usize = 20;
+    println!(
+        "{} diverging rows, {} negative balances",
+        report.divergences.len(),
+        report.negative_balances.len()
+    );
+    for divergence in report.divergences.iter().take(MAX_ROWS) {
+        println!(
+            "  {} rune {} block {}{}: expected {}, found {}",
+            divergence.table,
+            divergence.rune_id,
+            divergence.block_height,
+            divergence
+                .address
+                .as_ref()
+                .map(|a| format!(" address {a}"))
+                .unwrap_or_default(),
+            divergence.expected.as_deref().unwrap_or("no row"),
+            divergence.actual.as_deref().unwrap_or("no row"),
+        );
+    }
+    for negative in report.negative_balances.iter().take(MAX_ROWS) {
+        println!(
+            "  negative balance rune {} block {} address {}: {}",
+            negative.rune_id, negative.block_height, negative.address, negative.balance
+        );
+    }
+    let hidden = report.divergences.len().saturating_sub(MAX_ROWS)
+        + report.negative_balances.len().saturating_sub(MAX_ROWS);
+    if hidden > 0 {
+        println!("  ... and {hidden} more");
+    }
+}
+
+/// Asks the operator to confirm a destructive operation, unless `--yes` was passed.
+fn confirm(message: &str, yes: bool) -> Result<(), String> {
+    if yes {
+        return Ok(());
+    }
+    println!("{message} Confirm? [Y/n]");
+    let mut buffer = String::new();
+    std::io::stdin()
+        .read_line(&mut buffer)
+        .map_err(|e| format!("unable to read confirmation: {e}"))?;
+    if buffer.starts_with('n') {
+        return Err("Operation aborted".to_string());
+    }
+    Ok(())
+}
+
+/// Indexes `blocks` into the configured storage backend.
+#[cfg_attr(test, mutants::skip)]
+async fn scan<S: ConnectStorage>(
+    config: &Config,
+    blocks: Vec<u64>,
+    ctx: &Context,
+) -> Result<(), String> {
+    let monitoring = start_monitoring(config, ctx);
+    let mut storage = S::connect(config, true, ctx).await;
+    let mut index_cache = IndexCache::new(config, &mut storage, &monitoring, ctx).await;
+    let shutdown = ShutdownSignal::new();
+    shutdown.install_handler(ctx)?;
+    scan_blocks(
+        blocks,
+        config,
+        &mut storage,
+        &mut index_cache,
+        &shutdown,
+        ctx,
+    )
+    .await?;
+    if shutdown.is_requested() {
+        return Err("Scan interrupted by termination signal".to_string());
+    }
+    Ok(())
+}
+
+#[cfg_attr(test, mutants::skip)]
+async fn drop<S: ConnectStorage>(
+    config: &Config,
+    start_block: u64,
+    end_block: u64,
+    ctx: &Context,
+) -> Result<(), String> {
+    let mut storage = S::connect(config, false, ctx).await;
+    let mut index_cache =
+        IndexCache::new(config, &mut storage, &PrometheusMonitoring::new(), ctx).await;
+    drop_blocks(start_block, end_block, &mut storage, &mut index_cache, ctx).await
+}
+
+/// Deletes every block starting at `start_block` and indexes them again up to the bitcoind chain tip.
+#[cfg_attr(test, mutants::skip)]
+async fn reindex<S: ConnectStorage>(
+    config: &Config,
+    start_block: u64,
+    ctx: &Context,
+) -> Result<(), String> {
+    let mut storage = S::connect(config, true, ctx).await;
+    roll_back_to_block(&mut storage, start_block.saturating_sub(1), ctx).await?;
+
+    let monitoring = start_monitoring(config, ctx);
+    let mut index_cache = IndexCache::new(config, &mut storage, &monitoring, ctx).await;
+    let shutdown = ShutdownSignal::new();
+    shutdown.install_handler(ctx)?;
+    let bitcoind_chain_tip = bitcoind_get_block_height(config, ctx);
+    scan_blocks(
+        (start_block..=bitcoind_chain_tip).collect(),
+        config,
+        &mut storage,
+        &mut index_cache,
+        &shutdown,
+        ctx,
+    )
+    .await?;
+    if shutdown.is_requested() {
+        return Err("Reindex interrupted by termination signal".to_string());
+    }
+    Ok(())
+}
+
+/// Fails with a helpful message when a command that only supports Postgres is run against another storage backend.
+fn require_postgres(config: &Config) -> Result<(), String> {
+    match config.storage.backend {
+        StorageBackend::Postgres => Ok(()),
+        backend => Err(format!(
+            "This command requires the postgres storage backend, the config uses {}",
+            backend.as_str()
+        )),
+    }
+}
+
+/// Blocks below the runes genesis height are never indexed, so rolling back further would only delete seeded data.
+fn check_rollback_height(config: &Config, block_height: u64) -> Result<(), String> {
+    let min_height = config.runes.genesis_block_height.saturating_sub(1);
+    if block_height < min_height {
+        return Err(format!(
+            "Cannot roll back below block {min_height}, the block before the runes genesis height"
+        ));
+    }
+    Ok(())
+}
+
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn format_mint_pressure(pressure: &MintPressure) -> String {
+    let count = |value: Option<u128>| match value {
+        Some(value) => value.to_string(),
+        None => "unlimited".to_string(),
+    };
+    let height = |value: Option<u64>, unbounded: &str| match value {
+        Some(value) => value.to_string(),
+        None => unbounded.to_string(),
+    };
+    format!(
+        "{} ({})\n  status:          {}\n  mint window:     {} to {}\n  cap:             {}\n  confirmed mints: {}\n  pending mints:   {}\n  remaining mints: {} ({} after pending)\n",
+        pressure.spaced_name,
+        pressure.rune_id,
+        match &pressure.status {
+            Some(status) => status.as_str(),
+            None => "not mintable",
+        },
+        height(pressure.mint_start_height, "etching"),
+        height(pressure.mint_end_height, "forever"),
+        count(pressure.terms_cap),
+        pressure.confirmed_mints,
+        match pressure.pending_mints {
+            Some(pending) => pending.to_string(),
+            None => "unknown, mempool tracking is disabled".to_string(),
+        },
+        count(pressure.remaining_mints),
+        count(pressure.remaining_after_pending()),
+    )
+}
+
+/// Creates the indexer's Prometheus metrics and serves them if enabled in the config.
+fn start_monitoring(config: &Config, ctx: &Context) -> PrometheusMonitoring {
+    let monitoring = PrometheusMonitoring::new();
+    if config.metrics.enabled {
+        start_metrics_server_runloop(config.metrics.prometheus_port, &monitoring, ctx);
+    }
+    monitoring
+}
+
 impl StartScanCommand {
     pub fn get_blocks(&self) -> Vec<u64> {
         let blocks = match (&self.blocks_interval, &self.blocks) {
@@ -233,3 +985,52 @@ impl StartScanCommand {
         blocks.unwrap().into()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use clap::CommandFactory;
+    use ordinals::RuneId;
+    use test_case::test_case;
+
+    use crate::{db::models::db_mint_status::DbMintStatus, mints::MintPressure};
+
+    use super::{format_bytes, format_mint_pressure, Opts};
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Opts::command().debug_assert();
+    }
+
+    #[test_case(0 => "0.0 B"; "zero")]
+    #[test_case(1536 => "1.5 KiB"; "kibibytes")]
+    #[test_case(5 * 1024 * 1024 * 1024 => "5.0 GiB"; "gibibytes")]
+    fn formats_bytes(bytes: i64) -> String {
+        format_bytes(bytes)
+    }
+
+    #[test]
+    fn formats_mint_pressure() {
+        let pressure = MintPressure {
+            rune_id: RuneId::new(840000, 1).unwrap(),
+            spaced_name: "Z•Z•Z".to_string(),
+            terms_cap: Some(10),
+            confirmed_mints: 4,
+            pending_mints: None,
+            remaining_mints: Some(6),
+            status: Some(DbMintStatus::Open),
+            mint_start_height: None,
+            mint_end_height: Some(850000),
+        };
+        assert_eq!(
+            format_mint_pressure(&pressure),
+            "Z•Z•Z (840000:1)
+  status:          open
+  mint window:     etching to 850000
+  cap:             10
+  confirmed mints: 4
+  pending mints:   unknown, mempool tracking is disabled
+  remaining mints: 6 (6 after pending)
+"
+        );
+    }
+}
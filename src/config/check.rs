@@ -0,0 +1,273 @@
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    time::Duration,
+};
+
+use chainhook_sdk::types::BitcoinBlockSignaling;
+
+use crate::{
+    bitcoind::bitcoind_get_network,
+    db::{
+        pg_get_pending_migrations, pg_try_connect,
+        storage::sqlite::{SqliteStorage, SCHEMA_VERSION},
+    },
+};
+
+use super::{Config, StorageBackend};
+
+/// Result of a single `config check` step. `Ok` and `Err` both carry a message meant for the operator.
+pub struct CheckOutcome {
+    pub name: &'static str,
+    pub result: Result<String, String>,
+}
+
+/// Checks settings that can be verified without contacting any external service.
+pub fn validate_config(config: &Config) -> Result<String, String> {
+    if let BitcoinBlockSignaling::Stacks(_) = config.event_observer.bitcoin_block_signaling {
+        return Err(
+            "network.bitcoind_zmq_url is not set, it is required to receive new blocks from bitcoind"
+                .to_string(),
+        );
+    }
+    if config.metrics.enabled
+        && config.health.enabled
+        && config.metrics.prometheus_port == config.health.http_port
+    {
+        return Err(format!(
+            "metrics.prometheus_port and health.http_port are both set to {}, use different ports",
+            config.health.http_port
+        ));
+    }
+    if config.mempool.enabled && config.storage.backend != StorageBackend::Postgres {
+        return Err(format!(
+            "mempool.enabled requires the postgres storage backend, the config uses {}",
+            config.storage.backend.as_str()
+        ));
+    }
+    Ok("config is valid".to_string())
+}
+
+/// Extracts the `host:port` part of a ZeroMQ `tcp://` endpoint.
+pub fn zmq_tcp_address(url: &str) -> Result<&str, String> {
+    match url.strip_prefix("tcp://") {
+        Some(address) if !address.is_empty() => Ok(address),
+        _ => Err(format!(
+            "network.bitcoind_zmq_url {url} is not a tcp:// endpoint"
+        )),
+    }
+}
+
+#[cfg_attr(test, mutants::skip)]
+async fn check_postgres(config: &Config) -> Result<String, String> {
+    let client = pg_try_connect(config).await.map_err(|e| {
+        format!(
+            "{e}, make sure postgres is running at {}:{} and the [postgres] credentials are correct",
+            config.postgres.host, config.postgres.port
+        )
+    })?;
+    let pending = pg_get_pending_migrations(&client).await?;
+    if pending.is_empty() {
+        Ok(format!(
+            "connected to {}:{}/{}, schema is up to date",
+            config.postgres.host, config.postgres.port, config.postgres.database
+        ))
+    } else {
+        Ok(format!(
+            "connected to {}:{}/{}, {} pending migrations will be applied on next start: {}",
+            config.postgres.host,
+            config.postgres.port,
+            config.postgres.database,
+            pending.len(),
+            pending.join(", ")
+        ))
+    }
+}
+
+/// Checks that `storage.sqlite_path` can be opened, or created on next start if it doesn't exist yet. The database is only
+/// read, upgrades are left to the service.
+fn check_sqlite(config: &Config) -> Result<String, String> {
+    let path = &config.storage.sqlite_path;
+    match SqliteStorage::schema_version(path)? {
+        None => {
+            let dir = Path::new(path)
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            if !dir.is_dir() {
+                return Err(format!(
+                    "directory {} of storage.sqlite_path does not exist",
+                    dir.display()
+                ));
+            }
+            Ok(format!("{path} will be created on next start"))
+        }
+        Some(version) if version > SCHEMA_VERSION => Err(format!(
+            "{path} has schema version {version}, newer than the supported version {SCHEMA_VERSION}"
+        )),
+        Some(version) if version < SCHEMA_VERSION => Ok(format!(
+            "opened {path}, schema version {version} will be upgraded to {SCHEMA_VERSION} on next start"
+        )),
+        Some(_) => Ok(format!("opened {path}, schema is up to date")),
+    }
+}
+
+#[cfg_attr(test, mutants::skip)]
+fn check_bitcoind_rpc(config: &Config) -> Result<String, String> {
+    let network = bitcoind_get_network(config).map_err(|e| {
+        format!(
+            "{e}, make sure bitcoind is reachable at {} and the RPC credentials are correct",
+            config.event_observer.bitcoind_rpc_url
+        )
+    })?;
+    let expected = config.get_bitcoin_network().to_string();
+    if network != expected {
+        return Err(format!(
+            "bitcoind is running on {network} but network.bitcoin_network is set to {expected}"
+        ));
+    }
+    Ok(format!(
+        "connected to {}, running on {network}",
+        config.event_observer.bitcoind_rpc_url
+    ))
+}
+
+#[cfg_attr(test, mutants::skip)]
+fn check_bitcoind_zmq(config: &Config) -> Result<String, String> {
+    let BitcoinBlockSignaling::ZeroMQ(url) = &config.event_observer.bitcoin_block_signaling else {
+        return Err("network.bitcoind_zmq_url is not set".to_string());
+    };
+    let address = zmq_tcp_address(url)?;
+    let socket_addr = address
+        .to_socket_addrs()
+        .map_err(|e| format!("unable to resolve {address}: {e}"))?
+        .next()
+        .ok_or(format!("unable to resolve {address}"))?;
+    TcpStream::connect_timeout(&socket_addr, Duration::from_secs(5)).map_err(|e| {
+        format!("unable to connect to {url}: {e}, make sure bitcoind is started with -zmqpubhashblock={url}")
+    })?;
+    Ok(format!("{url} is accepting connections"))
+}
+
+/// Runs every check against the given config. All checks run even if a previous one fails so the operator gets a full
+/// report in one go.
+#[cfg_attr(test, mutants::skip)]
+pub async fn check_config(config: &Config) -> Vec<CheckOutcome> {
+    vec![
+        CheckOutcome {
+            name: "config",
+            result: validate_config(config),
+        },
+        match config.storage.backend {
+            StorageBackend::Postgres => CheckOutcome {
+                name: "postgres",
+                result: check_postgres(config).await,
+            },
+            StorageBackend::Sqlite => CheckOutcome {
+                name: "sqlite",
+                result: check_sqlite(config),
+            },
+        },
+        CheckOutcome {
+            name: "bitcoind rpc",
+            result: check_bitcoind_rpc(config),
+        },
+        CheckOutcome {
+            name: "bitcoind zmq",
+            result: check_bitcoind_zmq(config),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use test_case::test_case;
+
+    use crate::{
+        config::{file::ConfigFile, Config, StorageBackend},
+        db::storage::sqlite::SqliteStorage,
+    };
+
+    use super::{check_sqlite, validate_config, zmq_tcp_address};
+
+    fn config(network: &str) -> Config {
+        let config_file: ConfigFile = toml::from_str(&format!(
+            r#"
+[postgres]
+[network]
+{network}
+[resources]
+[metrics]
+enabled = true
+prometheus_port = 9153
+[health]
+enabled = true
+"#
+        ))
+        .unwrap();
+        Config::from_config_file(config_file).unwrap()
+    }
+
+    #[test_case("bitcoin_network = \"mainnet\"\nbitcoind_zmq_url = \"tcp://0.0.0.0:18543\"" => true; "valid")]
+    #[test_case("bitcoin_network = \"mainnet\"" => false; "missing zmq url")]
+    #[test_case("bitcoin_network = \"regtest\"\nbitcoind_zmq_url = \"tcp://0.0.0.0:18543\"" => true; "regtest")]
+    fn validates_network(network: &str) -> bool {
+        validate_config(&config(network)).is_ok()
+    }
+
+    #[test]
+    fn rejects_port_conflicts() {
+        let mut config =
+            config("bitcoin_network = \"mainnet\"\nbitcoind_zmq_url = \"tcp://0.0.0.0:18543\"");
+        config.health.http_port = config.metrics.prometheus_port;
+        assert!(validate_config(&config).is_err());
+    }
+
+    #[test_case(StorageBackend::Postgres => true; "postgres")]
+    #[test_case(StorageBackend::Sqlite => false; "sqlite")]
+    fn validates_mempool_storage_backend(backend: StorageBackend) -> bool {
+        let mut config =
+            config("bitcoin_network = \"mainnet\"\nbitcoind_zmq_url = \"tcp://0.0.0.0:18543\"");
+        config.mempool.enabled = true;
+        config.storage.backend = backend;
+        validate_config(&config).is_ok()
+    }
+
+    #[test_case("tcp://0.0.0.0:18543" => Ok("0.0.0.0:18543"); "tcp")]
+    #[test_case("ipc:///tmp/bitcoind" => matches Err(_); "ipc")]
+    #[test_case("tcp://" => matches Err(_); "empty")]
+    fn parses_zmq_addresses(url: &str) -> Result<&str, String> {
+        zmq_tcp_address(url)
+    }
+
+    #[test]
+    fn checks_sqlite_path() {
+        let mut config =
+            config("bitcoin_network = \"mainnet\"\nbitcoind_zmq_url = \"tcp://0.0.0.0:18543\"");
+        config.storage.backend = StorageBackend::Sqlite;
+        let dir = std::env::temp_dir().join("runehook_test_check_sqlite");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("runehook.sqlite");
+        config.storage.sqlite_path = path.display().to_string();
+
+        assert!(check_sqlite(&config)
+            .unwrap()
+            .ends_with("will be created on next start"));
+        assert!(!path.exists());
+
+        SqliteStorage::open(&config.storage.sqlite_path, true).unwrap();
+        assert!(check_sqlite(&config)
+            .unwrap()
+            .ends_with("schema is up to date"));
+
+        fs::write(&path, "not a database").unwrap();
+        assert!(check_sqlite(&config).is_err());
+
+        config.storage.sqlite_path = dir.join("missing/runehook.sqlite").display().to_string();
+        assert!(check_sqlite(&config).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, fs, str::FromStr};
+
+use chainhook_sdk::observer::EventObserverConfigOverrides;
+
+use super::file::{ConfigFile, StorageConfigFile};
+
+/// Prefix of every environment variable that overrides a config file value.
+pub const ENV_PREFIX: &str = "RUNEHOOK_";
+
+/// Collects all `RUNEHOOK_*` variables from the process environment.
+#[cfg_attr(test, mutants::skip)]
+pub fn runehook_env_vars() -> HashMap<String, String> {
+    std::env::vars()
+        .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+        .collect()
+}
+
+/// Reads a secret from a file, ignoring the trailing newline most editors and secret managers add.
+pub fn read_secret_file(path: &str) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map(|s| s.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|e| format!("unable to read secret file {path}: {e}"))
+}
+
+struct EnvOverrides<'a> {
+    env: &'a HashMap<String, String>,
+}
+
+impl EnvOverrides<'_> {
+    fn string(&self, name: &str) -> Option<String> {
+        self.env.get(&format!("{ENV_PREFIX}{name}")).cloned()
+    }
+
+    fn parse<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.string(name) {
+            Some(value) => value
+                .parse::<T>()
+                .map(Some)
+                .map_err(|_| format!("invalid value for {ENV_PREFIX}{name}: {value}")),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a secret from `{name}`, or from the file pointed to by `{name}_FILE`.
+    fn secret(&self, name: &str) -> Result<Option<String>, String> {
+        match (self.string(name), self.string(&format!("{name}_FILE"))) {
+            (Some(_), Some(_)) => Err(format!(
+                "only one of {ENV_PREFIX}{name} and {ENV_PREFIX}{name}_FILE can be set"
+            )),
+            (Some(value), None) => Ok(Some(value)),
+            (None, Some(path)) => read_secret_file(&path).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Layers `RUNEHOOK_*` environment variables over the values read from the config file. Variables are named after the
+    /// config section and key, e.g. `RUNEHOOK_POSTGRES_HOST` or `RUNEHOOK_NETWORK_BITCOIND_RPC_URL`. Passwords can also be
+    /// read from a file with the `_FILE` suffix, e.g. `RUNEHOOK_POSTGRES_PASSWORD_FILE`.
+    pub fn apply_env_overrides(&mut self, env: &HashMap<String, String>) -> Result<(), String> {
+        let env = EnvOverrides { env };
+
+        let postgres = &mut self.postgres;
+        if let Some(database) = env.string("POSTGRES_DATABASE") {
+            postgres.database = Some(database);
+        }
+        if let Some(host) = env.string("POSTGRES_HOST") {
+            postgres.host = Some(host);
+        }
+        if let Some(port) = env.parse("POSTGRES_PORT")? {
+            postgres.port = Some(port);
+        }
+        if let Some(username) = env.string("POSTGRES_USERNAME") {
+            postgres.username = Some(username);
+        }
+        if let Some(password) = env.secret("POSTGRES_PASSWORD")? {
+            postgres.password = Some(password);
+            postgres.password_file = None;
+        }
+
+        if env.string("STORAGE_BACKEND").is_some() || env.string("STORAGE_SQLITE_PATH").is_some() {
+            let storage = self.storage.get_or_insert(StorageConfigFile {
+                backend: None,
+                sqlite_path: None,
+            });
+            if let Some(backend) = env.string("STORAGE_BACKEND") {
+                storage.backend = Some(backend);
+            }
+            if let Some(path) = env.string("STORAGE_SQLITE_PATH") {
+                storage.sqlite_path = Some(path);
+            }
+        }
+
+        if let Some(lru_cache_size) = env.parse("RESOURCES_LRU_CACHE_SIZE")? {
+            self.resources.lru_cache_size = Some(lru_cache_size);
+        }
+
+        let network = self.network.get_or_insert(EventObserverConfigOverrides {
+            ingestion_port: None,
+            bitcoind_rpc_username: None,
+            bitcoind_rpc_password: None,
+            bitcoind_rpc_url: None,
+            bitcoind_zmq_url: None,
+            stacks_node_rpc_url: None,
+            display_logs: None,
+            cache_path: None,
+            bitcoin_network: None,
+            stacks_network: None,
+        });
+        if let Some(bitcoin_network) = env.string("NETWORK_BITCOIN_NETWORK") {
+            network.bitcoin_network = Some(bitcoin_network);
+        }
+        if let Some(url) = env.string("NETWORK_BITCOIND_RPC_URL") {
+            network.bitcoind_rpc_url = Some(url);
+        }
+        if let Some(username) = env.string("NETWORK_BITCOIND_RPC_USERNAME") {
+            network.bitcoind_rpc_username = Some(username);
+        }
+        if let Some(password) = env.secret("NETWORK_BITCOIND_RPC_PASSWORD")? {
+            network.bitcoind_rpc_password = Some(password);
+        }
+        if let Some(url) = env.string("NETWORK_BITCOIND_ZMQ_URL") {
+            network.bitcoind_zmq_url = Some(url);
+        }
+        if let Some(port) = env.parse("NETWORK_INGESTION_PORT")? {
+            network.ingestion_port = Some(port);
+        }
+        if let Some(url) = env.string("NETWORK_STACKS_NODE_RPC_URL") {
+            network.stacks_node_rpc_url = Some(url);
+        }
+        if let Some(display_logs) = env.parse("NETWORK_DISPLAY_LOGS")? {
+            network.display_logs = Some(display_logs);
+        }
+        if let Some(cache_path) = env.string("NETWORK_CACHE_PATH") {
+            network.cache_path = Some(cache_path);
+        }
+        if let Some(stacks_network) = env.string("NETWORK_STACKS_NETWORK") {
+            network.stacks_network = Some(stacks_network);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::config::file::ConfigFile;
+
+    fn config_file() -> ConfigFile {
+        toml::from_str(
+            r#"
+[postgres]
+username = "postgres"
+password = "postgres"
+host = "localhost"
+port = 5432
+
+[network]
+bitcoin_network = "mainnet"
+bitcoind_rpc_url = "http://0.0.0.0:8332"
+
+[resources]
+lru_cache_size = 50000
+"#,
+        )
+        .unwrap()
+    }
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn env_overrides_file_values() {
+        let mut file = config_file();
+        file.apply_env_overrides(&env(&[
+            ("RUNEHOOK_POSTGRES_HOST", "db.internal"),
+            ("RUNEHOOK_POSTGRES_PORT", "6432"),
+            ("RUNEHOOK_RESOURCES_LRU_CACHE_SIZE", "1000"),
+            ("RUNEHOOK_NETWORK_BITCOIND_RPC_URL", "http://bitcoind:8332"),
+        ]))
+        .unwrap();
+        assert_eq!(file.postgres.host, Some("db.internal".to_string()));
+        assert_eq!(file.postgres.port, Some(6432));
+        assert_eq!(file.postgres.username, Some("postgres".to_string()));
+        assert_eq!(file.resources.lru_cache_size, Some(1000));
+        let network = file.network.unwrap();
+        assert_eq!(
+            network.bitcoind_rpc_url,
+            Some("http://bitcoind:8332".to_string())
+        );
+        assert_eq!(network.bitcoin_network, Some("mainnet".to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut file = config_file();
+        let result = file.apply_env_overrides(&env(&[("RUNEHOOK_POSTGRES_PORT", "not-a-port")]));
+        assert_eq!(
+            result,
+            Err("invalid value for RUNEHOOK_POSTGRES_PORT: not-a-port".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_secrets_from_files() {
+        let path = std::env::temp_dir().join("runehook_test_rpc_password");
+        std::fs::write(&path, "s3cret\n").unwrap();
+        let mut file = config_file();
+        file.apply_env_overrides(&env(&[(
+            "RUNEHOOK_NETWORK_BITCOIND_RPC_PASSWORD_FILE",
+            path.to_str().unwrap(),
+        )]))
+        .unwrap();
+        assert_eq!(
+            file.network.unwrap().bitcoind_rpc_password,
+            Some("s3cret".to_string())
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_secret_and_secret_file() {
+        let mut file = config_file();
+        let result = file.apply_env_overrides(&env(&[
+            ("RUNEHOOK_POSTGRES_PASSWORD", "a"),
+            ("RUNEHOOK_POSTGRES_PASSWORD_FILE", "/run/secrets/pg"),
+        ]));
+        assert!(result.is_err());
+    }
+}
@@ -4,12 +4,21 @@ use chainhook_sdk::observer::EventObserverConfigOverrides;
 pub struct ConfigFile {
     pub network: Option<EventObserverConfigOverrides>,
     pub postgres: PostgresConfigFile,
+    pub storage: Option<StorageConfigFile>,
     pub resources: ResourcesConfigFile,
+    pub runes: Option<RunesConfigFile>,
+    pub metrics: Option<MetricsConfigFile>,
+    pub health: Option<HealthConfigFile>,
+    pub mempool: Option<MempoolConfigFile>,
+    pub logs: Option<LogConfigFile>,
 }
 #[derive(Deserialize, Debug, Clone)]
 pub struct LogConfigFile {
     pub runes_internals: Option<bool>,
     pub chainhook_internals: Option<bool>,
+    pub level: Option<String>,
+    pub chainhook_level: Option<String>,
+    pub format: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -19,6 +28,15 @@ pub struct PostgresConfigFile {
     pub port: Option<u16>,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// Path to a file containing the password, for use with secret managers.
+    pub password_file: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StorageConfigFile {
+    /// `postgres` or `sqlite`.
+    pub backend: Option<String>,
+    pub sqlite_path: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -29,7 +47,32 @@ pub struct PredicatesApiConfigFile {
     pub disabled: Option<bool>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct RunesConfigFile {
+    pub genesis_block_height: Option<u64>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct ResourcesConfigFile {
     pub lru_cache_size: Option<usize>,
 }
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MetricsConfigFile {
+    pub enabled: Option<bool>,
+    pub prometheus_port: Option<u16>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HealthConfigFile {
+    pub enabled: Option<bool>,
+    pub http_port: Option<u16>,
+    pub max_blocks_behind: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MempoolConfigFile {
+    pub enabled: Option<bool>,
+    pub zmq_url: Option<String>,
+    pub expiry_seconds: Option<u64>,
+}
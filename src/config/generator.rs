@@ -1,27 +1,101 @@
-pub fn generate_config() -> String {
+use chainhook_sdk::types::BitcoinNetwork;
+
+use crate::db::index::get_rune_genesis_block_height;
+
+use super::bitcoin_network;
+
+pub fn generate_config(network: &BitcoinNetwork) -> String {
+    // ZMQ ports differ per network so nodes of several networks can run on the same host.
+    let (network_name, rpc_port, zmq_port, lru_cache_size) = match network {
+        BitcoinNetwork::Mainnet => ("mainnet", 8332, 18543, 50_000),
+        BitcoinNetwork::Testnet => ("testnet", 18332, 18544, 20_000),
+        BitcoinNetwork::Signet => ("signet", 38332, 18545, 10_000),
+        BitcoinNetwork::Regtest => ("regtest", 18443, 18546, 1_000),
+    };
+    let genesis_block_height = get_rune_genesis_block_height(bitcoin_network(network));
     let conf = format!(
         r#"
 [postgres]
 username = "postgres"
 password = "postgres"
+# Alternatively, read the password from a file:
+# password_file = "/run/secrets/postgres_password"
 database = "postgres"
 host = "localhost"
 port = 5432
 
+# Uncomment to index into an embedded SQLite database instead of Postgres.
+# [storage]
+# backend = "sqlite"
+# sqlite_path = "runehook.sqlite"
+
 [network]
-bitcoin_network = "mainnet"
-bitcoind_rpc_url = "http://0.0.0.0:8332"
+bitcoin_network = "{network_name}"
+bitcoind_rpc_url = "http://0.0.0.0:{rpc_port}"
 bitcoind_rpc_username = "user"
 bitcoind_rpc_password = "pass"
-bitcoind_zmq_url = "tcp://0.0.0.0:18543"
+bitcoind_zmq_url = "tcp://0.0.0.0:{zmq_port}"
 
 [resources]
-lru_cache_size = 50000
+lru_cache_size = {lru_cache_size}
+
+[runes]
+genesis_block_height = {genesis_block_height}
+
+[metrics]
+enabled = false
+prometheus_port = 9153
+
+[health]
+enabled = false
+http_port = 8080
+max_blocks_behind = 6
+
+# Uncomment to track rune movements of unconfirmed transactions. Requires the postgres storage backend and bitcoind started
+# with `-zmqpubrawtx`, `zmq_url` defaults to `network.bitcoind_zmq_url`.
+# [mempool]
+# enabled = true
+# zmq_url = "tcp://0.0.0.0:{zmq_port}"
+# expiry_seconds = 86400
 
 [logs]
 runes_internals = true
 chainhook_internals = false
+# One of `critical`, `error`, `warning`, `info`, `debug`.
+level = "info"
+chainhook_level = "info"
+# `text` or `json`.
+format = "text"
 "#
     );
     return conf;
 }
+
+#[cfg(test)]
+mod test {
+    use chainhook_sdk::types::{BitcoinBlockSignaling, BitcoinNetwork};
+    use test_case::test_case;
+
+    use crate::config::{file::ConfigFile, Config};
+
+    use super::generate_config;
+
+    #[test_case(BitcoinNetwork::Mainnet => ("http://0.0.0.0:8332".to_string(), "tcp://0.0.0.0:18543".to_string(), 840_000, 50_000); "mainnet")]
+    #[test_case(BitcoinNetwork::Testnet => ("http://0.0.0.0:18332".to_string(), "tcp://0.0.0.0:18544".to_string(), 2_520_000, 20_000); "testnet")]
+    #[test_case(BitcoinNetwork::Regtest => ("http://0.0.0.0:18443".to_string(), "tcp://0.0.0.0:18546".to_string(), 0, 1_000); "regtest")]
+    fn generates_network_config(network: BitcoinNetwork) -> (String, String, u64, usize) {
+        let config_file: ConfigFile = toml::from_str(&generate_config(&network)).unwrap();
+        let config = Config::from_config_file(config_file).unwrap();
+        assert_eq!(config.event_observer.bitcoin_network, network);
+        let BitcoinBlockSignaling::ZeroMQ(zmq_url) = config.event_observer.bitcoin_block_signaling
+        else {
+            panic!("expected a ZMQ url");
+        };
+        (
+            config.event_observer.bitcoind_rpc_url,
+            zmq_url,
+            config.runes.genesis_block_height,
+            config.resources.lru_cache_size,
+        )
+    }
+}
@@ -1,13 +1,25 @@
+pub mod check;
+pub mod env;
 pub mod file;
 pub mod generator;
 
 use bitcoin::Network;
 use chainhook_sdk::observer::EventObserverConfig;
+use chainhook_sdk::types::BitcoinBlockSignaling;
 
 use chainhook_sdk::types::BitcoinNetwork;
+use env::{read_secret_file, runehook_env_vars};
 use file::ConfigFile;
+use hiro_system_kit::slog::Level;
 use std::fs::File;
 use std::io::{BufReader, Read};
+use std::str::FromStr;
+
+use crate::db::index::get_rune_genesis_block_height;
+use crate::logging::{parse_log_level, LogFormat};
+
+/// Placeholder printed instead of secrets.
+pub const REDACTED: &str = "********";
 
 #[derive(Clone, Debug)]
 pub struct PostgresConfig {
@@ -18,16 +30,125 @@ pub struct PostgresConfig {
     pub password: Option<String>,
 }
 
+/// Database the index is written to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageBackend {
+    Postgres,
+    /// Embedded database for single-node deployments, development and CI. Postgres-only commands such as `export` and
+    /// `snapshot` are not available with this backend.
+    Sqlite,
+}
+
+impl StorageBackend {
+    pub fn as_str(&self) -> &str {
+        match self {
+            StorageBackend::Postgres => "postgres",
+            StorageBackend::Sqlite => "sqlite",
+        }
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "postgres" => Ok(StorageBackend::Postgres),
+            "sqlite" => Ok(StorageBackend::Sqlite),
+            _ => Err(format!(
+                "invalid storage backend {s}, expected `postgres` or `sqlite`"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Database file used by the `sqlite` backend.
+    pub sqlite_path: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::Postgres,
+            sqlite_path: "runehook.sqlite".to_string(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ResourcesConfig {
     pub lru_cache_size: usize,
 }
 
+#[derive(Clone, Debug)]
+pub struct RunesConfig {
+    /// First block to index when the database is empty. Defaults to the runes activation height of the network.
+    pub genesis_block_height: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub prometheus_port: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct HealthConfig {
+    pub enabled: bool,
+    pub http_port: u16,
+    /// The service reports itself as not ready while it is behind bitcoind by more than this number of blocks.
+    pub max_blocks_behind: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct MempoolConfig {
+    /// Track rune movements of unconfirmed transactions in the `pending_ledger` table.
+    pub enabled: bool,
+    /// ZeroMQ endpoint where bitcoind publishes `rawtx` notifications. Defaults to `network.bitcoind_zmq_url`.
+    pub zmq_url: Option<String>,
+    /// Pending entries of a transaction that hasn't confirmed this many seconds after it was first seen are deleted.
+    pub expiry_seconds: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    /// Display logs emitted by the runes indexer.
+    pub runes_internals: bool,
+    /// Display logs emitted by the Chainhook SDK event observer.
+    pub chainhook_internals: bool,
+    /// Minimum level of runes indexer logs.
+    pub level: Level,
+    /// Minimum level of Chainhook SDK logs.
+    pub chainhook_level: Level,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            runes_internals: true,
+            chainhook_internals: false,
+            level: Level::Info,
+            chainhook_level: Level::Info,
+            format: LogFormat::Text,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub event_observer: EventObserverConfig,
     pub postgres: PostgresConfig,
+    pub storage: StorageConfig,
     pub resources: ResourcesConfig,
+    pub runes: RunesConfig,
+    pub metrics: MetricsConfig,
+    pub health: HealthConfig,
+    pub mempool: MempoolConfig,
+    pub logs: LogConfig,
 }
 
 impl Config {
@@ -41,12 +162,13 @@ impl Config {
             .read_to_end(&mut file_buffer)
             .map_err(|e| format!("unable to read file {}\n{:?}", file_path, e))?;
 
-        let config_file: ConfigFile = match toml::from_slice(&file_buffer) {
+        let mut config_file: ConfigFile = match toml::from_slice(&file_buffer) {
             Ok(s) => s,
             Err(e) => {
                 return Err(format!("Config file malformatted {}", e.to_string()));
             }
         };
+        config_file.apply_env_overrides(&runehook_env_vars())?;
         Config::from_config_file(config_file)
     }
 
@@ -55,6 +177,34 @@ impl Config {
         let event_observer =
             EventObserverConfig::new_using_overrides(config_file.network.as_ref())?;
 
+        let postgres_password = match (
+            config_file.postgres.password,
+            config_file.postgres.password_file,
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(
+                    "only one of postgres.password and postgres.password_file can be set"
+                        .to_string(),
+                )
+            }
+            (Some(password), None) => Some(password),
+            (None, Some(path)) => Some(read_secret_file(&path)?),
+            (None, None) => None,
+        };
+        let genesis_block_height = config_file
+            .runes
+            .as_ref()
+            .and_then(|r| r.genesis_block_height)
+            .unwrap_or(get_rune_genesis_block_height(bitcoin_network(
+                &event_observer.bitcoin_network,
+            )));
+        let mempool_zmq_url = match config_file.mempool.as_ref().and_then(|m| m.zmq_url.clone()) {
+            Some(url) => Some(url),
+            None => match &event_observer.bitcoin_block_signaling {
+                BitcoinBlockSignaling::ZeroMQ(url) => Some(url.clone()),
+                BitcoinBlockSignaling::Stacks(_) => None,
+            },
+        };
         let config = Config {
             event_observer,
             postgres: PostgresConfig {
@@ -68,22 +218,227 @@ impl Config {
                     .postgres
                     .username
                     .unwrap_or("postgres".to_string()),
-                password: config_file.postgres.password,
+                password: postgres_password,
+            },
+            storage: match config_file.storage {
+                Some(storage) => StorageConfig {
+                    backend: match storage.backend {
+                        Some(backend) => backend.parse()?,
+                        None => StorageBackend::Postgres,
+                    },
+                    sqlite_path: storage
+                        .sqlite_path
+                        .unwrap_or(StorageConfig::default().sqlite_path),
+                },
+                None => StorageConfig::default(),
             },
             resources: ResourcesConfig {
                 lru_cache_size: config_file.resources.lru_cache_size.unwrap_or(10_000),
             },
+            runes: RunesConfig {
+                genesis_block_height,
+            },
+            metrics: MetricsConfig {
+                enabled: config_file
+                    .metrics
+                    .as_ref()
+                    .and_then(|m| m.enabled)
+                    .unwrap_or(false),
+                prometheus_port: config_file
+                    .metrics
+                    .as_ref()
+                    .and_then(|m| m.prometheus_port)
+                    .unwrap_or(9153),
+            },
+            health: HealthConfig {
+                enabled: config_file
+                    .health
+                    .as_ref()
+                    .and_then(|h| h.enabled)
+                    .unwrap_or(false),
+                http_port: config_file
+                    .health
+                    .as_ref()
+                    .and_then(|h| h.http_port)
+                    .unwrap_or(8080),
+                max_blocks_behind: config_file
+                    .health
+                    .as_ref()
+                    .and_then(|h| h.max_blocks_behind)
+                    .unwrap_or(6),
+            },
+            mempool: MempoolConfig {
+                enabled: config_file
+                    .mempool
+                    .as_ref()
+                    .and_then(|m| m.enabled)
+                    .unwrap_or(false),
+                zmq_url: mempool_zmq_url,
+                expiry_seconds: config_file
+                    .mempool
+                    .as_ref()
+                    .and_then(|m| m.expiry_seconds)
+                    .unwrap_or(86400),
+            },
+            logs: match config_file.logs {
+                Some(logs) => LogConfig {
+                    runes_internals: logs.runes_internals.unwrap_or(true),
+                    chainhook_internals: logs.chainhook_internals.unwrap_or(false),
+                    level: match logs.level {
+                        Some(level) => parse_log_level(&level)?,
+                        None => Level::Info,
+                    },
+                    chainhook_level: match logs.chainhook_level {
+                        Some(level) => parse_log_level(&level)?,
+                        None => Level::Info,
+                    },
+                    format: match logs.format {
+                        Some(format) => format.parse()?,
+                        None => LogFormat::Text,
+                    },
+                },
+                None => LogConfig::default(),
+            },
         };
         Ok(config)
     }
 
+    /// Renders the effective configuration as TOML, with passwords replaced by a placeholder so it can be safely shared.
+    pub fn to_redacted_toml(&self) -> String {
+        let redacted = |secret: &Option<String>| match secret {
+            Some(_) => format!("\"{REDACTED}\""),
+            None => "# not set".to_string(),
+        };
+        let bitcoin_network = match self.event_observer.bitcoin_network {
+            BitcoinNetwork::Mainnet => "mainnet",
+            BitcoinNetwork::Testnet => "testnet",
+            BitcoinNetwork::Regtest => "regtest",
+            BitcoinNetwork::Signet => "signet",
+        };
+        let bitcoind_zmq_url = match &self.event_observer.bitcoin_block_signaling {
+            BitcoinBlockSignaling::ZeroMQ(url) => format!("\"{url}\""),
+            BitcoinBlockSignaling::Stacks(_) => "# not set".to_string(),
+        };
+        format!(
+            r#"[postgres]
+username = "{pg_username}"
+password = {pg_password}
+database = "{pg_database}"
+host = "{pg_host}"
+port = {pg_port}
+
+[storage]
+backend = "{storage_backend}"
+sqlite_path = "{sqlite_path}"
+
+[network]
+bitcoin_network = "{bitcoin_network}"
+bitcoind_rpc_url = "{rpc_url}"
+bitcoind_rpc_username = "{rpc_username}"
+bitcoind_rpc_password = {rpc_password}
+bitcoind_zmq_url = {bitcoind_zmq_url}
+
+[resources]
+lru_cache_size = {lru_cache_size}
+
+[runes]
+genesis_block_height = {genesis_block_height}
+
+[metrics]
+enabled = {metrics_enabled}
+prometheus_port = {prometheus_port}
+
+[health]
+enabled = {health_enabled}
+http_port = {health_port}
+max_blocks_behind = {max_blocks_behind}
+
+[mempool]
+enabled = {mempool_enabled}
+zmq_url = {mempool_zmq_url}
+expiry_seconds = {mempool_expiry_seconds}
+
+[logs]
+runes_internals = {runes_internals}
+chainhook_internals = {chainhook_internals}
+level = "{level}"
+chainhook_level = "{chainhook_level}"
+format = "{format}"
+"#,
+            pg_username = self.postgres.username,
+            pg_password = redacted(&self.postgres.password),
+            pg_database = self.postgres.database,
+            pg_host = self.postgres.host,
+            pg_port = self.postgres.port,
+            storage_backend = self.storage.backend.as_str(),
+            sqlite_path = self.storage.sqlite_path,
+            rpc_url = self.event_observer.bitcoind_rpc_url,
+            rpc_username = self.event_observer.bitcoind_rpc_username,
+            rpc_password = redacted(&Some(self.event_observer.bitcoind_rpc_password.clone())),
+            lru_cache_size = self.resources.lru_cache_size,
+            genesis_block_height = self.runes.genesis_block_height,
+            metrics_enabled = self.metrics.enabled,
+            prometheus_port = self.metrics.prometheus_port,
+            health_enabled = self.health.enabled,
+            health_port = self.health.http_port,
+            max_blocks_behind = self.health.max_blocks_behind,
+            mempool_enabled = self.mempool.enabled,
+            mempool_zmq_url = match &self.mempool.zmq_url {
+                Some(url) => format!("\"{url}\""),
+                None => "# not set".to_string(),
+            },
+            mempool_expiry_seconds = self.mempool.expiry_seconds,
+            runes_internals = self.logs.runes_internals,
+            chainhook_internals = self.logs.chainhook_internals,
+            level = self.logs.level.as_str().to_lowercase(),
+            chainhook_level = self.logs.chainhook_level.as_str().to_lowercase(),
+            format = self.logs.format.as_str(),
+        )
+    }
+
     #[cfg_attr(test, mutants::skip)]
     pub fn get_bitcoin_network(&self) -> Network {
-        match self.event_observer.bitcoin_network {
-            BitcoinNetwork::Mainnet => Network::Bitcoin,
-            BitcoinNetwork::Regtest => Network::Regtest,
-            BitcoinNetwork::Testnet => Network::Testnet,
-            BitcoinNetwork::Signet => Network::Signet,
-        }
+        bitcoin_network(&self.event_observer.bitcoin_network)
+    }
+}
+
+pub fn bitcoin_network(network: &BitcoinNetwork) -> Network {
+    match network {
+        BitcoinNetwork::Mainnet => Network::Bitcoin,
+        BitcoinNetwork::Regtest => Network::Regtest,
+        BitcoinNetwork::Testnet => Network::Testnet,
+        BitcoinNetwork::Signet => Network::Signet,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{file::ConfigFile, Config, REDACTED};
+
+    #[test]
+    fn redacts_secrets() {
+        let config_file: ConfigFile = toml::from_str(
+            r#"
+[postgres]
+username = "postgres"
+password = "pg-secret"
+
+[network]
+bitcoin_network = "mainnet"
+bitcoind_rpc_password = "rpc-secret"
+bitcoind_zmq_url = "tcp://0.0.0.0:18543"
+
+[resources]
+"#,
+        )
+        .unwrap();
+        let config = Config::from_config_file(config_file).unwrap();
+        let output = config.to_redacted_toml();
+        assert!(!output.contains("pg-secret"));
+        assert!(!output.contains("rpc-secret"));
+        assert_eq!(output.matches(REDACTED).count(), 2);
+        assert!(output.contains("bitcoind_zmq_url = \"tcp://0.0.0.0:18543\""));
+        // The output can be read back as a config file.
+        assert!(toml::from_str::<ConfigFile>(&output).is_ok());
     }
 }
@@ -1,17 +1,16 @@
 use std::collections::HashMap;
 
 use chainhook_sdk::utils::Context;
-use tokio_postgres::Transaction;
 
 use crate::{
     db::{
         models::{
             db_balance_change::DbBalanceChange, db_ledger_entry::DbLedgerEntry, db_rune::DbRune,
-            db_supply_change::DbSupplyChange,
+            db_supply_change::DbSupplyChange, db_trade::DbTrade,
         },
-        pg_insert_balance_changes, pg_insert_ledger_entries, pg_insert_runes,
-        pg_insert_supply_changes,
+        storage::StorageTransaction,
     },
+    monitoring::PrometheusMonitoring,
     try_debug, try_info,
 };
 
@@ -22,6 +21,7 @@ pub struct DbCache {
     pub supply_changes: HashMap<String, DbSupplyChange>,
     pub balance_increases: HashMap<(String, String), DbBalanceChange>,
     pub balance_deductions: HashMap<(String, String), DbBalanceChange>,
+    pub trades: Vec<DbTrade>,
 }
 
 impl DbCache {
@@ -32,30 +32,39 @@ impl DbCache {
             supply_changes: HashMap::new(),
             balance_increases: HashMap::new(),
             balance_deductions: HashMap::new(),
+            trades: Vec::new(),
         }
     }
 
     /// Insert all data into the DB and clear cache.
-    pub async fn flush(&mut self, db_tx: &mut Transaction<'_>, ctx: &Context) {
+    pub async fn flush(
+        &mut self,
+        db_tx: &mut impl StorageTransaction,
+        monitoring: &PrometheusMonitoring,
+        ctx: &Context,
+    ) {
         try_info!(ctx, "Flushing DB cache...");
         if self.runes.len() > 0 {
             try_debug!(ctx, "Flushing {} runes", self.runes.len());
-            let _ = pg_insert_runes(&self.runes, db_tx, ctx).await;
+            db_tx.insert_runes(&self.runes, ctx).await;
+            monitoring.metrics_rows_flushed("runes", self.runes.len());
             self.runes.clear();
         }
         if self.supply_changes.len() > 0 {
             try_debug!(ctx, "Flushing {} supply changes", self.supply_changes.len());
-            let _ = pg_insert_supply_changes(
-                &self.supply_changes.values().cloned().collect(),
-                db_tx,
-                ctx,
-            )
-            .await;
+            db_tx
+                .insert_supply_changes(
+                    &self.supply_changes.values().cloned().collect::<Vec<_>>(),
+                    ctx,
+                )
+                .await;
+            monitoring.metrics_rows_flushed("supply_changes", self.supply_changes.len());
             self.supply_changes.clear();
         }
         if self.ledger_entries.len() > 0 {
             try_debug!(ctx, "Flushing {} ledger entries", self.ledger_entries.len());
-            let _ = pg_insert_ledger_entries(&self.ledger_entries, db_tx, ctx).await;
+            db_tx.insert_ledger_entries(&self.ledger_entries, ctx).await;
+            monitoring.metrics_rows_flushed("ledger", self.ledger_entries.len());
             self.ledger_entries.clear();
         }
         if self.balance_increases.len() > 0 {
@@ -64,13 +73,14 @@ impl DbCache {
                 "Flushing {} balance increases",
                 self.balance_increases.len()
             );
-            let _ = pg_insert_balance_changes(
-                &self.balance_increases.values().cloned().collect(),
-                true,
-                db_tx,
-                ctx,
-            )
-            .await;
+            db_tx
+                .insert_balance_changes(
+                    &self.balance_increases.values().cloned().collect::<Vec<_>>(),
+                    true,
+                    ctx,
+                )
+                .await;
+            monitoring.metrics_rows_flushed("balance_changes", self.balance_increases.len());
             self.balance_increases.clear();
         }
         if self.balance_deductions.len() > 0 {
@@ -79,14 +89,25 @@ impl DbCache {
                 "Flushing {} balance deductions",
                 self.balance_deductions.len()
             );
-            let _ = pg_insert_balance_changes(
-                &self.balance_deductions.values().cloned().collect(),
-                false,
-                db_tx,
-                ctx,
-            )
-            .await;
+            db_tx
+                .insert_balance_changes(
+                    &self
+                        .balance_deductions
+                        .values()
+                        .cloned()
+                        .collect::<Vec<_>>(),
+                    false,
+                    ctx,
+                )
+                .await;
+            monitoring.metrics_rows_flushed("balance_changes", self.balance_deductions.len());
             self.balance_deductions.clear();
         }
+        if !self.trades.is_empty() {
+            try_debug!(ctx, "Flushing {} trades", self.trades.len());
+            db_tx.insert_trades(&self.trades, ctx).await;
+            monitoring.metrics_rows_flushed("trades", self.trades.len());
+            self.trades.clear();
+        }
     }
 }
@@ -1,40 +1,44 @@
 use std::{collections::HashMap, num::NonZeroUsize, str::FromStr};
 
-use bitcoin::{Network, ScriptBuf};
-use chainhook_sdk::{types::bitcoin::TxIn, utils::Context};
+use bitcoin::{Address, Network, ScriptBuf};
+use chainhook_sdk::{types::BitcoinTransactionData, utils::Context};
 use lru::LruCache;
 use ordinals::{Cenotaph, Edict, Etching, Rune, RuneId, Runestone};
-use tokio_postgres::{Client, Transaction};
 
 use crate::{
     config::Config,
     db::{
-        cache::utils::input_rune_balances_from_tx_inputs,
+        cache::utils::{input_rune_balances_by_vin, merge_input_rune_balances},
         models::{
             db_balance_change::DbBalanceChange, db_ledger_entry::DbLedgerEntry,
             db_ledger_operation::DbLedgerOperation, db_rune::DbRune,
             db_supply_change::DbSupplyChange,
         },
-        pg_get_max_rune_number, pg_get_rune_by_id, pg_get_rune_total_mints,
+        storage::{Storage, StorageTransaction},
     },
+    monitoring::PrometheusMonitoring,
+    trades::{detect_trades, is_listing_input, Listing},
     try_debug, try_info, try_warn,
 };
 
 use super::{
     db_cache::DbCache, input_rune_balance::InputRuneBalance, transaction_cache::TransactionCache,
-    transaction_location::TransactionLocation, utils::move_block_output_cache_to_output_cache,
+    transaction_location::TransactionLocation, transaction_values::TransactionValues,
+    utils::move_block_output_cache_to_output_cache,
 };
 
 /// Holds rune data across multiple blocks for faster computations. Processes rune events as they happen during transactions and
 /// generates database rows for later insertion.
 pub struct IndexCache {
     pub network: Network,
-    /// Number to be assigned to the next rune etching.
+    /// Number to be assigned to the next rune etching. Loaded once from the DB and kept up to date as runes are etched or
+    /// rolled back.
     next_rune_number: u32,
     /// LRU cache for runes.
     rune_cache: LruCache<RuneId, DbRune>,
-    /// LRU cache for total mints for runes.
-    rune_total_mints_cache: LruCache<RuneId, u128>,
+    /// Total mints for every rune. Loaded once from the DB and kept up to date as mints are applied or rolled back, so it is
+    /// authoritative and never needs to fall back to the DB.
+    rune_total_mints: HashMap<RuneId, u128>,
     /// LRU cache for outputs with rune balances.
     output_cache: LruCache<(String, u32), HashMap<RuneId, Vec<InputRuneBalance>>>,
     /// Same as above but only for the current block. We use a `HashMap` instead of an LRU cache to make sure we keep all outputs
@@ -42,19 +46,31 @@ pub struct IndexCache {
     block_output_cache: HashMap<(String, u32), HashMap<RuneId, Vec<InputRuneBalance>>>,
     /// Holds a single transaction's rune cache. Must be cleared every time a new transaction is processed.
     tx_cache: TransactionCache,
+    /// Inputs of the transaction held in `tx_cache` that were listed for sale.
+    tx_listings: Vec<Listing>,
+    /// Ledger entries of the transaction held in `tx_cache`, moved to `db_cache` once their sat values are filled in by
+    /// `end_transaction`. Entries in `db_cache` can't be kept there meanwhile because it may be flushed mid-transaction.
+    tx_entries: Vec<DbLedgerEntry>,
     /// Keeps rows that have not yet been inserted in the DB.
     pub db_cache: DbCache,
+    /// Prometheus metrics reported while indexing.
+    pub monitoring: PrometheusMonitoring,
 }
 
 impl IndexCache {
-    pub async fn new(config: &Config, pg_client: &mut Client, ctx: &Context) -> Self {
+    pub async fn new(
+        config: &Config,
+        storage: &mut impl Storage,
+        monitoring: &PrometheusMonitoring,
+        ctx: &Context,
+    ) -> Self {
         let network = config.get_bitcoin_network();
         let cap = NonZeroUsize::new(config.resources.lru_cache_size).unwrap();
         IndexCache {
             network,
-            next_rune_number: pg_get_max_rune_number(pg_client, ctx).await + 1,
+            next_rune_number: storage.get_max_rune_number(ctx).await + 1,
             rune_cache: LruCache::new(cap),
-            rune_total_mints_cache: LruCache::new(cap),
+            rune_total_mints: storage.get_rune_total_mints(ctx).await,
             output_cache: LruCache::new(cap),
             block_output_cache: HashMap::new(),
             tx_cache: TransactionCache::new(
@@ -71,33 +87,103 @@ impl IndexCache {
                 None,
                 0,
             ),
+            tx_listings: vec![],
+            tx_entries: vec![],
             db_cache: DbCache::new(),
+            monitoring: monitoring.clone(),
+        }
+    }
+
+    /// Reconciles in-memory counters with the rune etchings and mints contained in a block that is about to be rolled back. Must
+    /// be called within the same DB transaction that deletes the block's rows, before they are deleted.
+    pub async fn roll_back_block(
+        &mut self,
+        block_height: u64,
+        db_tx: &mut impl StorageTransaction,
+        ctx: &Context,
+    ) {
+        let (etched_runes, block_mints) = db_tx.get_block_rune_counts(block_height, ctx).await;
+        for (rune_id, mints) in block_mints.iter() {
+            if let Some(total) = self.rune_total_mints.get_mut(rune_id) {
+                *total = total.saturating_sub(*mints);
+            }
+        }
+        for rune_id in etched_runes.iter() {
+            self.rune_total_mints.remove(rune_id);
+            self.rune_cache.pop(rune_id);
         }
+        self.next_rune_number = self
+            .next_rune_number
+            .saturating_sub(etched_runes.len() as u32);
+        try_debug!(
+            ctx,
+            "Rolled back {} etchings and mints for {} runes at block {}, next rune number is {}",
+            etched_runes.len(),
+            block_mints.len(),
+            block_height,
+            self.next_rune_number
+        );
     }
 
-    pub async fn reset_max_rune_number(&mut self, db_tx: &mut Transaction<'_>, ctx: &Context) {
-        self.next_rune_number = pg_get_max_rune_number(db_tx, ctx).await + 1;
+    /// Reloads the rune number and mint counters from `storage` and drops every cached rune, output and pending row. Must be
+    /// called when a block transaction fails to commit, since the cache already reflects the changes that were lost.
+    pub async fn reset(&mut self, storage: &mut impl Storage, ctx: &Context) {
+        self.next_rune_number = storage.get_max_rune_number(ctx).await + 1;
+        self.rune_total_mints = storage.get_rune_total_mints(ctx).await;
+        self.rune_cache.clear();
+        self.output_cache.clear();
+        self.block_output_cache.clear();
+        self.db_cache = DbCache::new();
+        try_warn!(
+            ctx,
+            "Index cache reset, next rune number is {}",
+            self.next_rune_number
+        );
     }
 
     /// Creates a fresh transaction index cache.
     pub async fn begin_transaction(
         &mut self,
         location: TransactionLocation,
-        tx_inputs: &Vec<TxIn>,
+        tx: &BitcoinTransactionData,
         eligible_outputs: HashMap<u32, ScriptBuf>,
         first_eligible_output: Option<u32>,
         total_outputs: u32,
-        db_tx: &mut Transaction<'_>,
+        db_tx: &mut impl StorageTransaction,
         ctx: &Context,
     ) {
-        let input_runes = input_rune_balances_from_tx_inputs(
-            tx_inputs,
+        let indexed_input_runes = input_rune_balances_by_vin(
+            &tx.metadata.inputs,
             &self.block_output_cache,
             &mut self.output_cache,
             db_tx,
+            &self.monitoring,
             ctx,
         )
         .await;
+        self.tx_listings = indexed_input_runes
+            .iter()
+            .filter(|(vin, _)| {
+                tx.metadata
+                    .inputs
+                    .get(**vin as usize)
+                    .is_some_and(|input| is_listing_input(&input.witness))
+            })
+            .map(|(vin, balances)| Listing {
+                balances: balances.clone(),
+                payment: tx
+                    .metadata
+                    .outputs
+                    .get(*vin as usize)
+                    .map(|output| output.value),
+                payment_address: eligible_outputs
+                    .get(vin)
+                    .and_then(|script| Address::from_script(script, self.network).ok())
+                    .map(|address| address.to_string()),
+            })
+            .collect();
+        self.tx_entries.clear();
+        let input_runes = merge_input_rune_balances(&indexed_input_runes);
         #[cfg(not(feature = "release"))]
         {
             for (rune_id, balances) in input_runes.iter() {
@@ -119,10 +205,54 @@ impl IndexCache {
         );
     }
 
-    /// Finalizes the current transaction index cache by moving all unallocated balances to the correct output.
-    pub fn end_transaction(&mut self, _db_tx: &mut Transaction<'_>, ctx: &Context) {
+    /// Finalizes the current transaction index cache by moving all unallocated balances to the correct output, then records
+    /// the trades settled by the transaction. `tx` must be the transaction passed to `begin_transaction`.
+    pub async fn end_transaction(
+        &mut self,
+        tx: &BitcoinTransactionData,
+        db_tx: &mut impl StorageTransaction,
+        ctx: &Context,
+    ) {
         let entries = self.tx_cache.allocate_remaining_balances(ctx);
         self.add_ledger_entries_to_db_cache(&entries);
+        if self.tx_entries.is_empty() {
+            return;
+        }
+        // Computing the virtual size rebuilds the whole transaction, so it's only done for the ones with rune activity.
+        let tx_values = TransactionValues::from_chainhook_tx(tx);
+        self.db_cache.ledger_entries.extend(
+            self.tx_entries
+                .iter()
+                .map(|entry| tx_values.apply(entry.clone())),
+        );
+        if self.tx_listings.is_empty() {
+            return;
+        }
+        let rune_ids: Vec<RuneId> = self
+            .tx_listings
+            .iter()
+            .flat_map(|listing| listing.balances.keys().copied())
+            .collect();
+        let mut divisibilities = HashMap::new();
+        for rune_id in rune_ids.iter() {
+            if let Some(db_rune) = self.get_cached_rune_by_rune_id(rune_id, db_tx, ctx).await {
+                divisibilities.insert(rune_id.to_string(), db_rune.divisibility.0);
+            }
+        }
+        let trades = detect_trades(&self.tx_listings, &self.tx_entries, &divisibilities);
+        for trade in trades.iter() {
+            try_debug!(
+                ctx,
+                "TRADE {} {} from {} to {} for {} sats {}",
+                trade.rune_id,
+                trade.amount.0,
+                trade.seller_address,
+                trade.buyer_address,
+                trade.price.0,
+                self.tx_cache.location
+            );
+        }
+        self.db_cache.trades.extend(trades);
     }
 
     pub fn end_block(&mut self) {
@@ -135,7 +265,7 @@ impl IndexCache {
     pub async fn apply_runestone(
         &mut self,
         runestone: &Runestone,
-        _db_tx: &mut Transaction<'_>,
+        _db_tx: &mut impl StorageTransaction,
         ctx: &Context,
     ) {
         try_debug!(ctx, "{:?} {}", runestone, self.tx_cache.location);
@@ -147,7 +277,7 @@ impl IndexCache {
     pub async fn apply_cenotaph(
         &mut self,
         cenotaph: &Cenotaph,
-        _db_tx: &mut Transaction<'_>,
+        _db_tx: &mut impl StorageTransaction,
         ctx: &Context,
     ) {
         try_debug!(ctx, "{:?} {}", cenotaph, self.tx_cache.location);
@@ -158,7 +288,7 @@ impl IndexCache {
     pub async fn apply_etching(
         &mut self,
         etching: &Etching,
-        _db_tx: &mut Transaction<'_>,
+        _db_tx: &mut impl StorageTransaction,
         ctx: &Context,
     ) {
         let (rune_id, db_rune, entry) = self.tx_cache.apply_etching(etching, self.next_rune_number);
@@ -167,10 +297,12 @@ impl IndexCache {
             "Etching {} ({}) {}",
             db_rune.spaced_name,
             db_rune.id,
-            self.tx_cache.location
+            self.tx_cache.location;
+            "rune_id" => %db_rune.id
         );
         self.db_cache.runes.push(db_rune.clone());
         self.rune_cache.put(rune_id, db_rune);
+        self.rune_total_mints.insert(rune_id, 0);
         self.add_ledger_entries_to_db_cache(&vec![entry]);
         self.next_rune_number += 1;
     }
@@ -178,7 +310,7 @@ impl IndexCache {
     pub async fn apply_cenotaph_etching(
         &mut self,
         rune: &Rune,
-        _db_tx: &mut Transaction<'_>,
+        _db_tx: &mut impl StorageTransaction,
         ctx: &Context,
     ) {
         let (rune_id, db_rune, entry) = self
@@ -189,10 +321,12 @@ impl IndexCache {
             "Etching cenotaph {} ({}) {}",
             db_rune.spaced_name,
             db_rune.id,
-            self.tx_cache.location
+            self.tx_cache.location;
+            "rune_id" => %db_rune.id
         );
         self.db_cache.runes.push(db_rune.clone());
         self.rune_cache.put(rune_id, db_rune);
+        self.rune_total_mints.insert(rune_id, 0);
         self.add_ledger_entries_to_db_cache(&vec![entry]);
         self.next_rune_number += 1;
     }
@@ -200,7 +334,7 @@ impl IndexCache {
     pub async fn apply_mint(
         &mut self,
         rune_id: &RuneId,
-        db_tx: &mut Transaction<'_>,
+        db_tx: &mut impl StorageTransaction,
         ctx: &Context,
     ) {
         let Some(db_rune) = self.get_cached_rune_by_rune_id(rune_id, db_tx, ctx).await else {
@@ -208,31 +342,29 @@ impl IndexCache {
                 ctx,
                 "Rune {} not found for mint {}",
                 rune_id,
-                self.tx_cache.location
+                self.tx_cache.location;
+                "rune_id" => %rune_id
             );
             return;
         };
-        let total_mints = self
-            .get_cached_rune_total_mints(rune_id, db_tx, ctx)
-            .await
-            .unwrap_or(0);
+        let real_rune_id = db_rune.rune_id();
+        let total_mints = self.get_rune_total_mints(&real_rune_id);
         if let Some(ledger_entry) = self
             .tx_cache
             .apply_mint(&rune_id, total_mints, &db_rune, ctx)
         {
             self.add_ledger_entries_to_db_cache(&vec![ledger_entry.clone()]);
-            if let Some(total) = self.rune_total_mints_cache.get_mut(rune_id) {
-                *total += 1;
-            } else {
-                self.rune_total_mints_cache.put(rune_id.clone(), 1);
-            }
+            self.rune_total_mints
+                .entry(real_rune_id)
+                .and_modify(|total| *total += 1)
+                .or_insert(1);
         }
     }
 
     pub async fn apply_cenotaph_mint(
         &mut self,
         rune_id: &RuneId,
-        db_tx: &mut Transaction<'_>,
+        db_tx: &mut impl StorageTransaction,
         ctx: &Context,
     ) {
         let Some(db_rune) = self.get_cached_rune_by_rune_id(rune_id, db_tx, ctx).await else {
@@ -240,34 +372,38 @@ impl IndexCache {
                 ctx,
                 "Rune {} not found for cenotaph mint {}",
                 rune_id,
-                self.tx_cache.location
+                self.tx_cache.location;
+                "rune_id" => %rune_id
             );
             return;
         };
-        let total_mints = self
-            .get_cached_rune_total_mints(rune_id, db_tx, ctx)
-            .await
-            .unwrap_or(0);
-        if let Some(ledger_entry) =
-            self.tx_cache
-                .apply_cenotaph_mint(&rune_id, total_mints, &db_rune, ctx)
-        {
-            self.add_ledger_entries_to_db_cache(&vec![ledger_entry]);
-            if let Some(total) = self.rune_total_mints_cache.get_mut(rune_id) {
-                *total += 1;
-            } else {
-                self.rune_total_mints_cache.put(rune_id.clone(), 1);
-            }
+        let real_rune_id = db_rune.rune_id();
+        let total_mints = self.get_rune_total_mints(&real_rune_id);
+        let ledger_entries = self
+            .tx_cache
+            .apply_cenotaph_mint(rune_id, total_mints, &db_rune, ctx);
+        if !ledger_entries.is_empty() {
+            self.add_ledger_entries_to_db_cache(&ledger_entries);
+            self.rune_total_mints
+                .entry(real_rune_id)
+                .and_modify(|total| *total += 1)
+                .or_insert(1);
         }
     }
 
-    pub async fn apply_edict(&mut self, edict: &Edict, db_tx: &mut Transaction<'_>, ctx: &Context) {
+    pub async fn apply_edict(
+        &mut self,
+        edict: &Edict,
+        db_tx: &mut impl StorageTransaction,
+        ctx: &Context,
+    ) {
         let Some(db_rune) = self.get_cached_rune_by_rune_id(&edict.id, db_tx, ctx).await else {
             try_warn!(
                 ctx,
                 "Rune {} not found for edict {}",
                 edict.id,
-                self.tx_cache.location
+                self.tx_cache.location;
+                "rune_id" => %edict.id
             );
             return;
         };
@@ -287,7 +423,7 @@ impl IndexCache {
     async fn get_cached_rune_by_rune_id(
         &mut self,
         rune_id: &RuneId,
-        db_tx: &mut Transaction<'_>,
+        db_tx: &mut impl StorageTransaction,
         ctx: &Context,
     ) -> Option<DbRune> {
         // Id 0:0 is used to mean the rune being etched in this transaction, if any.
@@ -295,47 +431,25 @@ impl IndexCache {
             return self.tx_cache.etching.clone();
         }
         if let Some(cached_rune) = self.rune_cache.get(&rune_id) {
+            self.monitoring.metrics_cache_hit("rune");
             return Some(cached_rune.clone());
         }
         // Cache miss, look in DB.
-        self.db_cache.flush(db_tx, ctx).await;
-        let Some(db_rune) = pg_get_rune_by_id(rune_id, db_tx, ctx).await else {
-            return None;
-        };
+        self.monitoring.metrics_cache_miss("rune");
+        self.db_cache.flush(db_tx, &self.monitoring, ctx).await;
+        let db_rune = db_tx.get_rune_by_id(rune_id, ctx).await?;
         self.rune_cache.put(rune_id.clone(), db_rune.clone());
         return Some(db_rune);
     }
 
-    async fn get_cached_rune_total_mints(
-        &mut self,
-        rune_id: &RuneId,
-        db_tx: &mut Transaction<'_>,
-        ctx: &Context,
-    ) -> Option<u128> {
-        let real_rune_id = if rune_id.block == 0 && rune_id.tx == 0 {
-            let Some(etching) = self.tx_cache.etching.as_ref() else {
-                return None;
-            };
-            RuneId::from_str(etching.id.as_str()).unwrap()
-        } else {
-            rune_id.clone()
-        };
-        if let Some(total) = self.rune_total_mints_cache.get(&real_rune_id) {
-            return Some(*total);
-        }
-        // Cache miss, look in DB.
-        self.db_cache.flush(db_tx, ctx).await;
-        let Some(total) = pg_get_rune_total_mints(rune_id, db_tx, ctx).await else {
-            return None;
-        };
-        self.rune_total_mints_cache.put(rune_id.clone(), total);
-        return Some(total);
+    fn get_rune_total_mints(&self, rune_id: &RuneId) -> u128 {
+        self.rune_total_mints.get(rune_id).copied().unwrap_or(0)
     }
 
     /// Take ledger entries returned by the `TransactionCache` and add them to the `DbCache`. Update global balances and counters
     /// as well.
     fn add_ledger_entries_to_db_cache(&mut self, entries: &Vec<DbLedgerEntry>) {
-        self.db_cache.ledger_entries.extend(entries.clone());
+        self.tx_entries.extend(entries.iter().cloned());
         for entry in entries.iter() {
             match entry.operation {
                 DbLedgerOperation::Etching => {
@@ -393,7 +507,10 @@ impl IndexCache {
                         self.db_cache
                             .balance_deductions
                             .entry((entry.rune_id.clone(), address.clone()))
-                            .and_modify(|i| i.balance += entry.amount.unwrap())
+                            .and_modify(|i| {
+                                i.balance += entry.amount.unwrap();
+                                i.total_operations += 1;
+                            })
                             .or_insert(DbBalanceChange::from_operation(
                                 entry.rune_id.clone(),
                                 entry.block_height.clone(),
@@ -411,35 +528,40 @@ impl IndexCache {
                             entry.rune_id.clone(),
                             entry.block_height.clone(),
                         ));
+                    // Outputs without an address hold runes too, but only addresses have balances.
                     if let Some(address) = entry.address.clone() {
                         self.db_cache
                             .balance_increases
                             .entry((entry.rune_id.clone(), address.clone()))
-                            .and_modify(|i| i.balance += entry.amount.unwrap())
+                            .and_modify(|i| {
+                                i.balance += entry.amount.unwrap();
+                                i.total_operations += 1;
+                            })
                             .or_insert(DbBalanceChange::from_operation(
                                 entry.rune_id.clone(),
                                 entry.block_height.clone(),
                                 address,
                                 entry.amount.unwrap(),
                             ));
-                        // Add to current block's output cache if it's received balance.
-                        let k = (entry.tx_id.clone(), entry.output.unwrap().0);
-                        let rune_id = RuneId::from_str(entry.rune_id.as_str()).unwrap();
-                        let balance = InputRuneBalance {
-                            address: entry.address.clone(),
-                            amount: entry.amount.unwrap().0,
-                        };
-                        let mut default = HashMap::new();
-                        default.insert(rune_id, vec![balance.clone()]);
-                        self.block_output_cache
-                            .entry(k)
-                            .and_modify(|i| {
-                                i.entry(rune_id)
-                                    .and_modify(|v| v.push(balance.clone()))
-                                    .or_insert(vec![balance]);
-                            })
-                            .or_insert(default);
                     }
+                    // Add to current block's output cache if it's received balance.
+                    let k = (entry.tx_id.clone(), entry.output.unwrap().0);
+                    let rune_id = RuneId::from_str(entry.rune_id.as_str()).unwrap();
+                    let balance = InputRuneBalance {
+                        address: entry.address.clone(),
+                        script_pubkey: entry.script_pubkey.clone(),
+                        amount: entry.amount.unwrap().0,
+                    };
+                    let mut default = HashMap::new();
+                    default.insert(rune_id, vec![balance.clone()]);
+                    self.block_output_cache
+                        .entry(k)
+                        .and_modify(|i| {
+                            i.entry(rune_id)
+                                .and_modify(|v| v.push(balance.clone()))
+                                .or_insert(vec![balance]);
+                        })
+                        .or_insert(default);
                 }
             }
         }
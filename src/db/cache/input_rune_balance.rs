@@ -1,11 +1,21 @@
 #[derive(Debug, Clone, Default)]
 pub struct InputRuneBalance {
-    /// Previous owner of this balance. If this is `None`, it means the balance was just minted or premined.
+    /// Previous owner of this balance. If this and `script_pubkey` are `None`, it means the balance was just minted or
+    /// premined.
     pub address: Option<String>,
+    /// Hex encoded script of the output that held this balance. Outputs without an address are only identified by it.
+    pub script_pubkey: Option<String>,
     /// How much balance was input to this transaction.
     pub amount: u128,
 }
 
+impl InputRuneBalance {
+    /// Returns true if this balance came from a spent output, as opposed to being minted or premined in this transaction.
+    pub fn is_held(&self) -> bool {
+        self.address.is_some() || self.script_pubkey.is_some()
+    }
+}
+
 #[cfg(test)]
 impl InputRuneBalance {
     pub fn dummy() -> Self {
@@ -14,6 +24,7 @@ impl InputRuneBalance {
                 "bc1p8zxlhgdsq6dmkzk4ammzcx55c3hfrg69ftx0gzlnfwq0wh38prds0nzqwf".to_string(),
             ),
             amount: 1000,
+            script_pubkey: None,
         }
     }
 
@@ -26,4 +37,9 @@ impl InputRuneBalance {
         self.address = address;
         return self;
     }
+
+    pub fn script_pubkey(&mut self, script_pubkey: Option<String>) -> &mut Self {
+        self.script_pubkey = script_pubkey;
+        self
+    }
 }
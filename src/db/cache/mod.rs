@@ -3,4 +3,5 @@ pub mod index_cache;
 pub mod input_rune_balance;
 pub mod transaction_cache;
 pub mod transaction_location;
+pub mod transaction_values;
 pub mod utils;
@@ -1,6 +1,5 @@
 use bitcoin::ScriptBuf;
 use chainhook_sdk::utils::Context;
-use maplit::hashmap;
 use ordinals::{Cenotaph, Edict, Etching, Rune, RuneId};
 use std::{
     collections::{HashMap, VecDeque},
@@ -68,8 +67,8 @@ impl TransactionCache {
             next_event_index: 0,
             etching: None,
             output_pointer: None,
-            input_runes: hashmap! {},
-            eligible_outputs: hashmap! {},
+            input_runes: HashMap::new(),
+            eligible_outputs: HashMap::new(),
             total_outputs: 0,
         }
     }
@@ -141,6 +140,7 @@ impl TransactionCache {
                 &rune_id,
                 InputRuneBalance {
                     address: None,
+                    script_pubkey: None,
                     amount: premine,
                 },
             );
@@ -187,7 +187,7 @@ impl TransactionCache {
         db_rune: &DbRune,
         ctx: &Context,
     ) -> Option<DbLedgerEntry> {
-        if !is_rune_mintable(db_rune, total_mints, &self.location) {
+        if !is_rune_mintable(db_rune, total_mints, self.location.block_height) {
             try_debug!(ctx, "Invalid mint {} {}", rune_id, self.location);
             return None;
         }
@@ -198,12 +198,14 @@ impl TransactionCache {
             rune_id,
             db_rune.spaced_name,
             terms_amount.0,
-            self.location
+            self.location;
+            "rune_id" => %rune_id
         );
         self.add_input_runes(
             rune_id,
             InputRuneBalance {
                 address: None,
+                script_pubkey: None,
                 amount: terms_amount.0,
             },
         );
@@ -219,16 +221,18 @@ impl TransactionCache {
         ))
     }
 
+    /// Cenotaph mints count towards the rune's cap but their runes are burned right away, so they are recorded as a `mint`
+    /// entry followed by a `burn` entry of the same amount.
     pub fn apply_cenotaph_mint(
         &mut self,
         rune_id: &RuneId,
         total_mints: u128,
         db_rune: &DbRune,
         ctx: &Context,
-    ) -> Option<DbLedgerEntry> {
-        if !is_rune_mintable(db_rune, total_mints, &self.location) {
+    ) -> Vec<DbLedgerEntry> {
+        if !is_rune_mintable(db_rune, total_mints, self.location.block_height) {
             try_debug!(ctx, "Invalid mint {} {}", rune_id, self.location);
-            return None;
+            return vec![];
         }
         let terms_amount = db_rune.terms_amount.unwrap();
         try_info!(
@@ -236,19 +240,25 @@ impl TransactionCache {
             "CENOTAPH MINT {} {} {}",
             db_rune.spaced_name,
             terms_amount.0,
-            self.location
+            self.location;
+            "rune_id" => %rune_id
         );
-        // This entry does not go in the input runes, it gets burned immediately.
-        Some(new_sequential_ledger_entry(
-            &self.location,
-            Some(terms_amount.0),
-            rune_id.clone(),
-            None,
-            None,
-            None,
-            DbLedgerOperation::Burn,
-            &mut self.next_event_index,
-        ))
+        // These runes do not go in the input runes, they get burned immediately.
+        [DbLedgerOperation::Mint, DbLedgerOperation::Burn]
+            .into_iter()
+            .map(|operation| {
+                new_sequential_ledger_entry(
+                    &self.location,
+                    Some(terms_amount.0),
+                    *rune_id,
+                    None,
+                    None,
+                    None,
+                    operation,
+                    &mut self.next_event_index,
+                )
+            })
+            .collect()
     }
 
     pub fn apply_edict(&mut self, edict: &Edict, ctx: &Context) -> Vec<DbLedgerEntry> {
@@ -272,7 +282,8 @@ impl TransactionCache {
                 ctx,
                 "No unallocated runes {} remain for edict {}",
                 edict.id,
-                self.location
+                self.location;
+                "rune_id" => %edict.id
             );
             return vec![];
         };
@@ -290,7 +301,8 @@ impl TransactionCache {
                 ctx,
                 "No eligible outputs for edict on rune {} {}",
                 edict.id,
-                self.location
+                self.location;
+                "rune_id" => %edict.id
             );
             results.extend(move_rune_balance_to_output(
                 &self.location,
@@ -373,7 +385,8 @@ impl TransactionCache {
                         "Edict for {} attempted move to nonexistent output {}, amount will be burnt {}",
                         edict.id,
                         edict.output,
-                        self.location
+                        self.location;
+                        "rune_id" => %edict.id
                     );
                     results.extend(move_rune_balance_to_output(
                         &self.location,
@@ -404,19 +417,23 @@ impl TransactionCache {
 
 #[cfg(test)]
 mod test {
-    use std::collections::VecDeque;
+    use std::collections::{HashMap, VecDeque};
 
     use bitcoin::ScriptBuf;
     use chainhook_sdk::utils::Context;
     use maplit::hashmap;
-    use ordinals::{Edict, Etching, Rune, Terms};
+    use ordinals::{Edict, Etching, Rune, RuneId, Terms};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     use crate::db::{
         cache::{
             input_rune_balance::InputRuneBalance, transaction_location::TransactionLocation,
             utils::is_rune_mintable,
         },
-        models::{db_ledger_operation::DbLedgerOperation, db_rune::DbRune},
+        fixtures::{owner_address, owner_script},
+        models::{
+            db_ledger_entry::DbLedgerEntry, db_ledger_operation::DbLedgerOperation, db_rune::DbRune,
+        },
     };
 
     use super::TransactionCache;
@@ -463,7 +480,7 @@ mod test {
         let (_rune_id, db_rune, db_ledger_entry) = cache.apply_cenotaph_etching(&rune, number);
 
         // // the etched rune has supply zero and is unmintable.
-        assert_eq!(is_rune_mintable(&db_rune, 0, &location), false);
+        assert!(!is_rune_mintable(&db_rune, 0, location.block_height));
         assert_eq!(db_ledger_entry.amount, None);
         assert_eq!(db_rune.id, "840000:0");
         assert_eq!(db_ledger_entry.operation, DbLedgerOperation::Etching);
@@ -520,14 +537,20 @@ mod test {
 
         let db_rune = DbRune::factory();
         let rune_id = db_rune.rune_id();
-        let ledger_entry = cache.apply_cenotaph_mint(&rune_id, 0, &db_rune, &Context::empty());
-        assert!(ledger_entry.is_some());
-        let ledger_entry = ledger_entry.unwrap();
-        assert_eq!(ledger_entry.operation, DbLedgerOperation::Burn);
+        let ledger_entries = cache.apply_cenotaph_mint(&rune_id, 0, &db_rune, &Context::empty());
         assert_eq!(
-            ledger_entry.amount.unwrap().0,
-            db_rune.terms_amount.unwrap().0
+            ledger_entries
+                .iter()
+                .map(|entry| (entry.operation.clone(), entry.amount.unwrap().0))
+                .collect::<Vec<_>>(),
+            vec![
+                (DbLedgerOperation::Mint, db_rune.terms_amount.unwrap().0),
+                (DbLedgerOperation::Burn, db_rune.terms_amount.unwrap().0),
+            ]
         );
+        assert!(cache
+            .apply_cenotaph_mint(&rune_id, 1111111, &db_rune, &Context::empty())
+            .is_empty());
     }
 
     #[test]
@@ -542,6 +565,7 @@ mod test {
             "bc1p8zxlhgdsq6dmkzk4ammzcx55c3hfrg69ftx0gzlnfwq0wh38prds0nzqwf".to_string();
         balances.push_back(InputRuneBalance {
             address: Some(sender_address.clone()),
+            script_pubkey: None,
             amount: 1000,
         });
         let input_runes = hashmap! {
@@ -567,6 +591,28 @@ mod test {
         assert_eq!(send.receiver_address, Some(receiver_address.clone()));
     }
 
+    #[test]
+    fn allocates_inputs_after_an_empty_one() {
+        let location = TransactionLocation::dummy();
+        let rune_id = DbRune::factory().rune_id();
+        let mut balances = VecDeque::new();
+        balances.push_back(InputRuneBalance {
+            address: None,
+            script_pubkey: None,
+            amount: 0,
+        });
+        balances.push_back(InputRuneBalance::dummy());
+        let input_runes = hashmap! { rune_id => balances };
+        let eligible_outputs = hashmap! {0=> ScriptBuf::from_hex("5120388dfba1b0069bbb0ad5eef62c1a94c46e91a3454accf40bf34b80f75e2708db").unwrap()};
+        let mut cache = TransactionCache::new(location, input_runes, eligible_outputs, Some(0), 1);
+
+        let entries = cache.allocate_remaining_balances(&Context::empty());
+        assert_eq!(entries.len(), 2);
+        let receive = entries.first().unwrap();
+        assert_eq!(receive.operation, DbLedgerOperation::Receive);
+        assert_eq!(receive.amount.unwrap().0, 1000);
+    }
+
     #[test]
     fn allocates_remaining_runes_to_first_eligible_output() {
         let location = TransactionLocation::dummy();
@@ -579,6 +625,7 @@ mod test {
             "bc1p8zxlhgdsq6dmkzk4ammzcx55c3hfrg69ftx0gzlnfwq0wh38prds0nzqwf".to_string();
         balances.push_back(InputRuneBalance {
             address: Some(sender_address.clone()),
+            script_pubkey: None,
             amount: 1000,
         });
         let input_runes = hashmap! {
@@ -610,6 +657,7 @@ mod test {
             "bc1p8zxlhgdsq6dmkzk4ammzcx55c3hfrg69ftx0gzlnfwq0wh38prds0nzqwf".to_string();
         balances.push_back(InputRuneBalance {
             address: Some(sender_address.clone()),
+            script_pubkey: None,
             amount: 1000,
         });
         let input_runes = hashmap! {
@@ -629,4 +677,207 @@ mod test {
         assert_eq!(send.address, Some(sender_address.clone()));
         assert_eq!(send.receiver_address, Some(receiver_address.clone()));
     }
+
+    /// Number of random transactions checked by each property test. Every case is generated from its own seed, so a failing
+    /// case can be replayed by running `random_transaction` with the seed reported in the assertion message.
+    const PROPERTY_CASES: u64 = 2000;
+
+    /// A randomly generated transaction after all of its runestone operations were applied.
+    struct RandomTransaction {
+        /// Units of each rune that entered the transaction through inputs, premine or mints.
+        supplied: HashMap<String, u128>,
+        /// Units of each rune held by each input address.
+        inputs_by_address: HashMap<(String, String), u128>,
+        entries: Vec<DbLedgerEntry>,
+    }
+
+    fn random_amount(rng: &mut StdRng) -> u128 {
+        match rng.gen_range(0..3) {
+            0 => rng.gen_range(1..=10),
+            1 => rng.gen_range(1..=1000),
+            _ => rng.gen_range(1..=u64::MAX as u128),
+        }
+    }
+
+    /// Builds a transaction with random input balances, outputs, pointer, etching, mint and edicts, then processes it the same
+    /// way `IndexCache` does and returns every ledger entry it produced.
+    fn random_transaction(seed: u64) -> RandomTransaction {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let ctx = Context::empty();
+        let location = TransactionLocation::dummy();
+        let total_outputs: u32 = rng.gen_range(0..=4);
+        // Outputs left out of the map are OP_RETURNs.
+        let eligible_outputs: HashMap<u32, ScriptBuf> = (0..total_outputs)
+            .filter(|_| rng.gen_bool(0.7))
+            .map(|output| (output, owner_script(output as u8 + 1)))
+            .collect();
+        let first_eligible_output = eligible_outputs.keys().min().cloned();
+
+        let mut supplied: HashMap<String, u128> = HashMap::new();
+        let mut inputs_by_address: HashMap<(String, String), u128> = HashMap::new();
+        let mut input_runes = HashMap::new();
+        let mut rune_ids: Vec<RuneId> = (1..=rng.gen_range(1..=3))
+            .map(|tx| RuneId::new(839000, tx).unwrap())
+            .collect();
+        for rune_id in rune_ids.iter() {
+            let mut balances = VecDeque::new();
+            for _ in 0..rng.gen_range(0..=4) {
+                let address = owner_address(rng.gen_range(10..=12));
+                let amount = random_amount(&mut rng);
+                *supplied.entry(rune_id.to_string()).or_default() += amount;
+                *inputs_by_address
+                    .entry((rune_id.to_string(), address.clone()))
+                    .or_default() += amount;
+                balances.push_back(InputRuneBalance {
+                    address: Some(address),
+                    script_pubkey: None,
+                    amount,
+                });
+            }
+            input_runes.insert(*rune_id, balances);
+        }
+        let mut cache = TransactionCache::new(
+            location.clone(),
+            input_runes,
+            eligible_outputs,
+            first_eligible_output,
+            total_outputs,
+        );
+
+        let mut entries = vec![];
+        if rng.gen_bool(0.5) {
+            let premine = random_amount(&mut rng);
+            let etching = Etching {
+                divisibility: None,
+                premine: Some(premine),
+                rune: Some(Rune::reserved(location.block_height, location.tx_index)),
+                spacers: None,
+                symbol: None,
+                terms: None,
+                turbo: false,
+            };
+            let (rune_id, _db_rune, entry) = cache.apply_etching(&etching, 1);
+            *supplied.entry(rune_id.to_string()).or_default() += premine;
+            entries.push(entry);
+            // Edicts refer to the rune being etched as `0:0`.
+            rune_ids.push(RuneId::default());
+        }
+        if rng.gen_bool(0.5) {
+            let db_rune = DbRune::factory();
+            let entry = cache
+                .apply_mint(&db_rune.rune_id(), 0, &db_rune, &ctx)
+                .unwrap();
+            *supplied.entry(db_rune.id.clone()).or_default() += db_rune.terms_amount.unwrap().0;
+            entries.push(entry);
+            rune_ids.push(db_rune.rune_id());
+        }
+        if rng.gen_bool(0.3) {
+            // Pointers may target OP_RETURN outputs.
+            cache.output_pointer = Some(rng.gen_range(0..=total_outputs));
+        }
+        // Also target a rune that was never input to the transaction.
+        rune_ids.push(RuneId::new(839999, 1).unwrap());
+        for _ in 0..rng.gen_range(0..=5) {
+            let edict = Edict {
+                id: rune_ids[rng.gen_range(0..rune_ids.len())],
+                amount: if rng.gen_bool(0.25) {
+                    0
+                } else {
+                    random_amount(&mut rng)
+                },
+                // Up to one past `total_outputs` to cover both the "all outputs" and the nonexistent output cases.
+                output: rng.gen_range(0..=total_outputs + 1),
+            };
+            entries.extend(cache.apply_edict(&edict, &ctx));
+        }
+        entries.extend(cache.allocate_remaining_balances(&ctx));
+
+        RandomTransaction {
+            supplied,
+            inputs_by_address,
+            entries,
+        }
+    }
+
+    fn amount(entry: &DbLedgerEntry) -> u128 {
+        entry.amount.as_ref().map(|a| a.0).unwrap_or(0)
+    }
+
+    #[test]
+    fn conserves_rune_balances() {
+        for seed in 0..PROPERTY_CASES {
+            let tx = random_transaction(seed);
+            // Everything input, premined or minted ends up either in an output or burned.
+            let mut allocated: HashMap<String, u128> = HashMap::new();
+            for entry in tx.entries.iter() {
+                if matches!(
+                    entry.operation,
+                    DbLedgerOperation::Receive | DbLedgerOperation::Burn
+                ) {
+                    *allocated.entry(entry.rune_id.clone()).or_default() += amount(entry);
+                }
+            }
+            allocated.retain(|_, amount| *amount > 0);
+            let mut supplied = tx.supplied.clone();
+            supplied.retain(|_, amount| *amount > 0);
+            assert_eq!(supplied, allocated, "seed {seed}");
+
+            // Each input address gives away exactly what it put in, no more and no less.
+            let mut spent: HashMap<(String, String), u128> = HashMap::new();
+            for entry in tx.entries.iter() {
+                if let (DbLedgerOperation::Send | DbLedgerOperation::Burn, Some(address)) =
+                    (&entry.operation, &entry.address)
+                {
+                    *spent
+                        .entry((entry.rune_id.clone(), address.clone()))
+                        .or_default() += amount(entry);
+                }
+            }
+            assert_eq!(tx.inputs_by_address, spent, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn assigns_sequential_event_indexes() {
+        for seed in 0..PROPERTY_CASES {
+            let tx = random_transaction(seed);
+            for (i, entry) in tx.entries.iter().enumerate() {
+                assert_eq!(entry.event_index.0, i as u32, "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn pairs_every_send_with_a_receive() {
+        for seed in 0..PROPERTY_CASES {
+            let tx = random_transaction(seed);
+            // `Send` entries immediately follow the `Receive` of the move they belong to and can't add up to more than it.
+            let mut receive: Option<(&DbLedgerEntry, u128)> = None;
+            for entry in tx.entries.iter() {
+                match entry.operation {
+                    DbLedgerOperation::Receive => {
+                        assert!(entry.address.is_some(), "seed {seed}");
+                        assert!(amount(entry) > 0, "seed {seed}");
+                        receive = Some((entry, amount(entry)));
+                    }
+                    DbLedgerOperation::Send => {
+                        let Some((received, remaining)) = receive.as_mut() else {
+                            panic!("seed {seed}: send without receive {:?}", entry);
+                        };
+                        assert_eq!(entry.rune_id, received.rune_id, "seed {seed}");
+                        assert_eq!(
+                            entry.output.map(|o| o.0),
+                            received.output.map(|o| o.0),
+                            "seed {seed}"
+                        );
+                        assert_eq!(entry.receiver_address, received.address, "seed {seed}");
+                        *remaining = remaining
+                            .checked_sub(amount(entry))
+                            .unwrap_or_else(|| panic!("seed {seed}: sent more than received"));
+                    }
+                    _ => receive = None,
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,149 @@
+use bitcoin::{
+    absolute::LockTime, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use chainhook_sdk::types::BitcoinTransactionData;
+
+use crate::db::{
+    models::db_ledger_entry::DbLedgerEntry,
+    types::{pg_bigint_u32::PgBigIntU32, pg_numeric_u64::PgNumericU64},
+};
+
+/// Sat values of the transaction being indexed, copied to each of its ledger entries so trades can be priced in BTC.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionValues {
+    pub fee: u64,
+    /// `None` if the transaction's scripts or witness could not be decoded.
+    pub vsize: Option<u32>,
+    /// Value of each output, by index.
+    pub output_values: Vec<u64>,
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    hex::decode(value.strip_prefix("0x").unwrap_or(value)).ok()
+}
+
+/// Rebuilds the full transaction, inputs and witnesses included, to compute its virtual size.
+fn vsize(tx: &BitcoinTransactionData) -> Option<u32> {
+    let mut input = vec![];
+    for tx_in in tx.metadata.inputs.iter() {
+        let witness = tx_in
+            .witness
+            .iter()
+            .map(|item| decode_hex(item))
+            .collect::<Option<Vec<_>>>()?;
+        input.push(TxIn {
+            // Only the size of the outpoint matters here.
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::from_bytes(decode_hex(&tx_in.script_sig)?),
+            sequence: Sequence(tx_in.sequence),
+            witness: Witness::from_slice(&witness),
+        });
+    }
+    let mut output = vec![];
+    for tx_out in tx.metadata.outputs.iter() {
+        output.push(TxOut {
+            value: tx_out.value,
+            script_pubkey: ScriptBuf::from_bytes(decode_hex(&tx_out.script_pubkey)?),
+        });
+    }
+    let transaction = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input,
+        output,
+    };
+    u32::try_from(transaction.vsize()).ok()
+}
+
+impl TransactionValues {
+    pub fn from_chainhook_tx(tx: &BitcoinTransactionData) -> Self {
+        TransactionValues {
+            fee: tx.metadata.fee,
+            vsize: vsize(tx),
+            output_values: tx.metadata.outputs.iter().map(|o| o.value).collect(),
+        }
+    }
+
+    /// Fills in the sat value columns of `entry`.
+    pub fn apply(&self, mut entry: DbLedgerEntry) -> DbLedgerEntry {
+        entry.output_value = entry
+            .output
+            .and_then(|output| self.output_values.get(output.0 as usize))
+            .map(|value| PgNumericU64(*value));
+        entry.tx_fee = Some(PgNumericU64(self.fee));
+        entry.tx_vsize = self.vsize.map(PgBigIntU32);
+        entry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chainhook_sdk::types::{
+        bitcoin::{OutPoint, TxIn, TxOut},
+        BitcoinTransactionData, BitcoinTransactionMetadata, TransactionIdentifier,
+    };
+    use test_case::test_case;
+
+    use crate::db::{models::db_ledger_entry::DbLedgerEntry, types::pg_bigint_u32::PgBigIntU32};
+
+    use super::TransactionValues;
+
+    fn transaction(witness: Vec<String>) -> BitcoinTransactionData {
+        BitcoinTransactionData {
+            transaction_identifier: TransactionIdentifier::new(&"0".repeat(64)),
+            operations: vec![],
+            metadata: BitcoinTransactionMetadata {
+                inputs: vec![TxIn {
+                    previous_output: OutPoint {
+                        txid: TransactionIdentifier::new(&"f".repeat(64)),
+                        vout: 0,
+                        value: 10_000,
+                        block_height: 0,
+                    },
+                    script_sig: "0x".to_string(),
+                    sequence: 0xffffffff,
+                    witness,
+                }],
+                outputs: vec![
+                    TxOut {
+                        value: 546,
+                        script_pubkey: "0x0014751e76e8199196d454941c45d1b3a323f1433bd6".to_string(),
+                    },
+                    TxOut {
+                        value: 9_000,
+                        script_pubkey: "0x0014751e76e8199196d454941c45d1b3a323f1433bd6".to_string(),
+                    },
+                ],
+                stacks_operations: vec![],
+                ordinal_operations: vec![],
+                brc20_operation: None,
+                proof: None,
+                fee: 454,
+                index: 1,
+            },
+        }
+    }
+
+    // One P2WPKH input spent with a 72 byte signature and a 33 byte key, and two P2WPKH outputs.
+    #[test_case(vec![format!("0x{}", "30".repeat(72)), format!("0x{}", "02".repeat(33))] => Some(141); "segwit")]
+    #[test_case(vec![] => Some(113); "no witness")]
+    #[test_case(vec!["0xzz".to_string()] => None; "undecodable witness")]
+    fn computes_vsize(witness: Vec<String>) -> Option<u32> {
+        TransactionValues::from_chainhook_tx(&transaction(witness)).vsize
+    }
+
+    #[test]
+    fn fills_in_entry_values() {
+        let values = TransactionValues::from_chainhook_tx(&transaction(vec![]));
+        let entry = values.apply(DbLedgerEntry {
+            output: Some(PgBigIntU32(1)),
+            ..Default::default()
+        });
+        assert_eq!(entry.output_value.map(|v| v.0), Some(9_000));
+        assert_eq!(entry.tx_fee.map(|v| v.0), Some(454));
+        assert_eq!(entry.tx_vsize.map(|v| v.0), Some(113));
+
+        let entry = values.apply(DbLedgerEntry::default());
+        assert_eq!(entry.output_value.map(|v| v.0), None);
+    }
+}
@@ -4,16 +4,16 @@ use bitcoin::{Address, ScriptBuf};
 use chainhook_sdk::{types::bitcoin::TxIn, utils::Context};
 use lru::LruCache;
 use ordinals::RuneId;
-use tokio_postgres::Transaction;
 
 use crate::{
     db::{
         models::{
             db_ledger_entry::DbLedgerEntry, db_ledger_operation::DbLedgerOperation, db_rune::DbRune,
         },
-        pg_get_input_rune_balances,
+        storage::StorageTransaction,
     },
-    try_info, try_warn,
+    monitoring::PrometheusMonitoring,
+    try_debug, try_info,
 };
 
 use super::{input_rune_balance::InputRuneBalance, transaction_location::TransactionLocation};
@@ -27,14 +27,39 @@ use super::{input_rune_balance::InputRuneBalance, transaction_location::Transact
 /// * `block_output_cache` - Cache with output balances produced by the current block
 /// * `output_cache` - LRU cache with output balances
 /// * `db_tx` - DB transaction
+/// * `monitoring` - Prometheus metrics, used to track cache hits and misses
 /// * `ctx` - Context
 pub async fn input_rune_balances_from_tx_inputs(
     inputs: &Vec<TxIn>,
     block_output_cache: &HashMap<(String, u32), HashMap<RuneId, Vec<InputRuneBalance>>>,
     output_cache: &mut LruCache<(String, u32), HashMap<RuneId, Vec<InputRuneBalance>>>,
-    db_tx: &mut Transaction<'_>,
+    db_tx: &mut impl StorageTransaction,
+    monitoring: &PrometheusMonitoring,
     ctx: &Context,
 ) -> HashMap<RuneId, VecDeque<InputRuneBalance>> {
+    merge_input_rune_balances(
+        &input_rune_balances_by_vin(
+            inputs,
+            block_output_cache,
+            output_cache,
+            db_tx,
+            monitoring,
+            ctx,
+        )
+        .await,
+    )
+}
+
+/// Same as `input_rune_balances_from_tx_inputs`, but returns the rune balances of each input keyed by input index instead
+/// of merging them.
+pub async fn input_rune_balances_by_vin(
+    inputs: &[TxIn],
+    block_output_cache: &HashMap<(String, u32), HashMap<RuneId, Vec<InputRuneBalance>>>,
+    output_cache: &mut LruCache<(String, u32), HashMap<RuneId, Vec<InputRuneBalance>>>,
+    db_tx: &mut impl StorageTransaction,
+    monitoring: &PrometheusMonitoring,
+    ctx: &Context,
+) -> HashMap<u32, HashMap<RuneId, Vec<InputRuneBalance>>> {
     // Maps input index to all of its rune balances. Useful in order to keep rune inputs in order.
     let mut indexed_input_runes = HashMap::new();
     let mut cache_misses = vec![];
@@ -45,20 +70,29 @@ pub async fn input_rune_balances_from_tx_inputs(
         let vout = input.previous_output.vout;
         let k = (tx_id.clone(), vout);
         if let Some(map) = block_output_cache.get(&k) {
+            monitoring.metrics_cache_hit("block_output");
             indexed_input_runes.insert(i as u32, map.clone());
         } else if let Some(map) = output_cache.get(&k) {
+            monitoring.metrics_cache_hit("output");
             indexed_input_runes.insert(i as u32, map.clone());
         } else {
+            monitoring.metrics_cache_miss("output");
             cache_misses.push((i as u32, tx_id, vout));
         }
     }
     // Look for cache misses in database. We don't need to `flush` the DB cache here because we've already looked in the current
     // block's output cache.
     if cache_misses.len() > 0 {
-        let output_balances = pg_get_input_rune_balances(cache_misses, db_tx, ctx).await;
+        let output_balances = db_tx.get_input_rune_balances(cache_misses, ctx).await;
         indexed_input_runes.extend(output_balances);
     }
+    indexed_input_runes
+}
 
+/// Merges the rune balances of each input, keyed by input index, into a single queue per rune that follows input order.
+pub fn merge_input_rune_balances(
+    indexed_input_runes: &HashMap<u32, HashMap<RuneId, Vec<InputRuneBalance>>>,
+) -> HashMap<RuneId, VecDeque<InputRuneBalance>> {
     let mut final_input_runes: HashMap<RuneId, VecDeque<InputRuneBalance>> = HashMap::new();
     let mut input_keys: Vec<u32> = indexed_input_runes.keys().copied().collect();
     input_keys.sort();
@@ -135,7 +169,8 @@ pub fn new_sequential_ledger_entry(
 /// # Arguments
 ///
 /// * `location` - Transaction location.
-/// * `output` - Output where runes will be moved to. If `None`, runes are burned.
+/// * `output` - Output where runes will be moved to. If `None` or not eligible, runes are burned. Eligible outputs whose
+///   script has no address still receive them, identified by their script.
 /// * `rune_id` - Rune that is being moved.
 /// * `input_balances` - Balances input to this transaction for this rune. This value will be modified by the moves happening in
 ///   this function.
@@ -154,36 +189,35 @@ pub fn move_rune_balance_to_output(
     ctx: &Context,
 ) -> Vec<DbLedgerEntry> {
     let mut results = vec![];
-    // Who is this balance going to?
-    let receiver_address = if let Some(output) = output {
-        match outputs.get(&output) {
-            Some(script) => match Address::from_script(script, location.network) {
-                Ok(address) => Some(address.to_string()),
-                Err(e) => {
-                    try_warn!(
-                        ctx,
-                        "Unable to decode address for output {}, {} {}",
-                        output,
-                        e,
-                        location
-                    );
-                    None
-                }
-            },
-            None => {
-                try_info!(
+    // Who is this balance going to? Per the runes spec any eligible output holds runes, even if its script (P2PK, bare
+    // multisig, non-standard) has no address.
+    let receiver_script = output.and_then(|output| outputs.get(&output));
+    let receiver_address = match (output, receiver_script) {
+        (Some(output), Some(script)) => match Address::from_script(script, location.network) {
+            Ok(address) => Some(address.to_string()),
+            Err(e) => {
+                try_debug!(
                     ctx,
-                    "Attempted move to non-eligible output {}, runes will be burnt {}",
+                    "No address for output {}, runes are held by its script: {} {}",
                     output,
+                    e,
                     location
                 );
                 None
             }
+        },
+        (Some(output), None) => {
+            try_info!(
+                ctx,
+                "Attempted move to non-eligible output {}, runes will be burnt {}",
+                output,
+                location
+            );
+            None
         }
-    } else {
-        None
+        (None, _) => None,
     };
-    let operation = if receiver_address.is_some() {
+    let operation = if receiver_script.is_some() {
         DbLedgerOperation::Send
     } else {
         DbLedgerOperation::Burn
@@ -204,61 +238,81 @@ pub fn move_rune_balance_to_output(
             input_bal.amount.min(amount - total_sent)
         };
         total_sent += balance_taken;
-        // If the input balance came from an address, add to `Send` operations.
-        if let Some(sender_address) = input_bal.address.clone() {
-            senders.push((balance_taken, sender_address));
+        // If the input balance came from an output, add to `Send` operations. Burns are recorded for minted and premined
+        // balances too, otherwise they would never be counted as burned supply.
+        if balance_taken > 0 && (input_bal.is_held() || receiver_script.is_none()) {
+            senders.push((
+                balance_taken,
+                input_bal.address.clone(),
+                input_bal.script_pubkey.clone(),
+            ));
         }
         // Is there still some balance left on this input? If so, keep it for later but break the loop because we've satisfied the
         // move amount.
         if balance_taken < input_bal.amount {
             input_balances.push_front(InputRuneBalance {
                 address: input_bal.address,
+                script_pubkey: input_bal.script_pubkey,
                 amount: input_bal.amount - balance_taken,
             });
             break;
         }
-        // Have we finished moving balance?
-        if total_sent == amount {
+        // Have we finished moving balance? A zero amount moves everything, even if an input was empty.
+        if amount != 0 && total_sent == amount {
             break;
         }
     }
     // Add the "receive" entry, if applicable.
-    if receiver_address.is_some() && total_sent > 0 {
-        results.push(new_sequential_ledger_entry(
-            location,
-            Some(total_sent),
-            *rune_id,
-            output,
-            receiver_address.as_ref(),
-            None,
-            DbLedgerOperation::Receive,
-            next_event_index,
-        ));
+    if let Some(script) = receiver_script.filter(|_| total_sent > 0) {
+        results.push(
+            new_sequential_ledger_entry(
+                location,
+                Some(total_sent),
+                *rune_id,
+                output,
+                receiver_address.as_ref(),
+                None,
+                DbLedgerOperation::Receive,
+                next_event_index,
+            )
+            .with_script(script),
+        );
         try_info!(
             ctx,
             "{} {} ({}) {} {}",
             DbLedgerOperation::Receive,
             rune_id,
             total_sent,
-            receiver_address.as_ref().unwrap(),
+            receiver_address
+                .clone()
+                .unwrap_or_else(|| script.to_hex_string()),
             location
         );
     }
-    // Add the "send"/"burn" entries.
-    for (balance_taken, sender_address) in senders.iter() {
-        results.push(new_sequential_ledger_entry(
+    // Add the "send"/"burn" entries. They keep the script of the spent output, like `address` holds the sender.
+    for (balance_taken, sender_address, sender_script) in senders.iter() {
+        let entry = new_sequential_ledger_entry(
             location,
             Some(*balance_taken),
             *rune_id,
             output,
-            Some(sender_address),
+            sender_address.as_ref(),
             receiver_address.as_ref(),
             operation.clone(),
             next_event_index,
-        ));
+        );
+        results.push(
+            match sender_script
+                .as_ref()
+                .and_then(|script| ScriptBuf::from_hex(script).ok())
+            {
+                Some(script) => entry.with_script(&script),
+                None => entry,
+            },
+        );
         try_info!(
             ctx,
-            "{} {} ({}) {} -> {:?} {}",
+            "{} {} ({}) {:?} -> {:?} {}",
             operation,
             rune_id,
             balance_taken,
@@ -270,12 +324,8 @@ pub fn move_rune_balance_to_output(
     results
 }
 
-/// Determines if a mint is valid depending on the rune's mint terms.
-pub fn is_rune_mintable(
-    db_rune: &DbRune,
-    total_mints: u128,
-    location: &TransactionLocation,
-) -> bool {
+/// Determines if a mint included in the block at `block_height` is valid depending on the rune's mint terms.
+pub fn is_rune_mintable(db_rune: &DbRune, total_mints: u128, block_height: u64) -> bool {
     if db_rune.cenotaph {
         return false;
     }
@@ -288,22 +338,22 @@ pub fn is_rune_mintable(
         }
     }
     if let Some(terms_height_start) = db_rune.terms_height_start {
-        if location.block_height < terms_height_start.0 {
+        if block_height < terms_height_start.0 {
             return false;
         }
     }
     if let Some(terms_height_end) = db_rune.terms_height_end {
-        if location.block_height > terms_height_end.0 {
+        if block_height > terms_height_end.0 {
             return false;
         }
     }
     if let Some(terms_offset_start) = db_rune.terms_offset_start {
-        if location.block_height < db_rune.block_height.0 + terms_offset_start.0 {
+        if block_height < db_rune.block_height.0 + terms_offset_start.0 {
             return false;
         }
     }
     if let Some(terms_offset_end) = db_rune.terms_offset_end {
-        if location.block_height > db_rune.block_height.0 + terms_offset_end.0 {
+        if block_height > db_rune.block_height.0 + terms_offset_end.0 {
             return false;
         }
     }
@@ -325,7 +375,7 @@ mod test {
                 input_rune_balance::InputRuneBalance, transaction_location::TransactionLocation,
                 utils::move_rune_balance_to_output,
             },
-            models::db_ledger_operation::DbLedgerOperation,
+            models::{db_ledger_operation::DbLedgerOperation, db_script_type::DbScriptType},
         };
 
         fn dummy_eligible_output() -> HashMap<u32, ScriptBuf> {
@@ -376,6 +426,39 @@ mod test {
             assert_eq!(available_inputs.len(), 0);
         }
 
+        #[test]
+        fn entries_record_output_scripts() {
+            let sender_script = "0014751e76e8199196d454941c45d1b3a323f1433bd6".to_string();
+            let mut input = InputRuneBalance::dummy();
+            input.script_pubkey(Some(sender_script.clone()));
+            let mut available_inputs = VecDeque::from([input]);
+            let eligible_outputs = dummy_eligible_output();
+
+            let results = move_rune_balance_to_output(
+                &TransactionLocation::dummy(),
+                Some(0),
+                &RuneId::new(840000, 25).unwrap(),
+                &mut available_inputs,
+                &eligible_outputs,
+                0,
+                &mut 0,
+                &Context::empty(),
+            );
+
+            assert_eq!(results.len(), 2);
+            let receive = &results[0];
+            assert_eq!(receive.operation, DbLedgerOperation::Receive);
+            assert_eq!(
+                receive.script_pubkey,
+                Some(eligible_outputs.get(&0).unwrap().to_hex_string())
+            );
+            assert_eq!(receive.script_type, Some(DbScriptType::P2tr));
+            let send = &results[1];
+            assert_eq!(send.operation, DbLedgerOperation::Send);
+            assert_eq!(send.script_pubkey, Some(sender_script));
+            assert_eq!(send.script_type, Some(DbScriptType::P2wpkh));
+        }
+
         #[test]
         fn move_to_empty_output_is_burned() {
             let address =
@@ -506,13 +589,13 @@ mod test {
         }
 
         #[test]
-        fn move_to_output_with_address_failure_is_burned() {
+        fn move_to_output_without_address_is_held_by_script() {
             let mut available_inputs = VecDeque::new();
             let mut input1 = InputRuneBalance::dummy();
             input1.amount(1000);
             available_inputs.push_back(input1);
             let mut eligible_outputs = HashMap::new();
-            // Broken script buf that yields no address.
+            // Non-standard script buf that yields no address.
             eligible_outputs.insert(0u32, ScriptBuf::from_hex("0101010101").unwrap());
 
             let results = move_rune_balance_to_output(
@@ -526,13 +609,51 @@ mod test {
                 &Context::empty(),
             );
 
-            assert_eq!(results.len(), 1);
-            let entry1 = results.get(0).unwrap();
-            assert_eq!(entry1.operation, DbLedgerOperation::Burn);
-            assert_eq!(entry1.amount.unwrap().0, 1000);
+            assert_eq!(results.len(), 2);
+            let receive = results.first().unwrap();
+            assert_eq!(receive.operation, DbLedgerOperation::Receive);
+            assert_eq!(receive.amount.unwrap().0, 1000);
+            assert_eq!(receive.address, None);
+            assert_eq!(receive.script_pubkey, Some("0101010101".to_string()));
+            assert_eq!(receive.script_type, Some(DbScriptType::NonStandard));
+            let send = results.get(1).unwrap();
+            assert_eq!(send.operation, DbLedgerOperation::Send);
+            assert_eq!(send.amount.unwrap().0, 1000);
+            assert_eq!(send.address, InputRuneBalance::dummy().address);
+            assert_eq!(send.receiver_address, None);
             assert_eq!(available_inputs.len(), 0);
         }
 
+        #[test]
+        fn move_from_output_without_address_is_sent() {
+            let mut available_inputs = VecDeque::new();
+            let mut input1 = InputRuneBalance::dummy();
+            input1
+                .address(None)
+                .script_pubkey(Some("0101010101".to_string()))
+                .amount(1000);
+            available_inputs.push_back(input1);
+            let eligible_outputs = dummy_eligible_output();
+
+            let results = move_rune_balance_to_output(
+                &TransactionLocation::dummy(),
+                Some(0),
+                &RuneId::new(840000, 25).unwrap(),
+                &mut available_inputs,
+                &eligible_outputs,
+                0,
+                &mut 0,
+                &Context::empty(),
+            );
+
+            // Unlike minted balance, balance held by a script is spent with a `Send`.
+            assert_eq!(results.len(), 2);
+            assert_eq!(results[0].operation, DbLedgerOperation::Receive);
+            assert_eq!(results[1].operation, DbLedgerOperation::Send);
+            assert_eq!(results[1].address, None);
+            assert_eq!(results[1].amount.unwrap().0, 1000);
+        }
+
         #[test]
         fn move_to_nonexistent_output_is_burned() {
             let mut available_inputs = VecDeque::new();
@@ -584,6 +705,58 @@ mod test {
             assert_eq!(entry1.amount.unwrap().0, 1000);
             assert_eq!(available_inputs.len(), 0);
         }
+        #[test]
+        fn moves_all_remaining_balance_after_empty_input() {
+            let mut available_inputs = VecDeque::new();
+            let mut input1 = InputRuneBalance::dummy();
+            input1.amount(0).address(None); // Premine of zero.
+            available_inputs.push_back(input1);
+            available_inputs.push_back(InputRuneBalance::dummy());
+            let eligible_outputs = dummy_eligible_output();
+
+            let results = move_rune_balance_to_output(
+                &TransactionLocation::dummy(),
+                Some(0),
+                &RuneId::new(840000, 25).unwrap(),
+                &mut available_inputs,
+                &eligible_outputs,
+                0, // Move all.
+                &mut 0,
+                &Context::empty(),
+            );
+
+            assert_eq!(results.len(), 2);
+            let entry1 = results.first().unwrap();
+            assert_eq!(entry1.operation, DbLedgerOperation::Receive);
+            assert_eq!(entry1.amount.unwrap().0, 1000);
+            assert_eq!(available_inputs.len(), 0);
+        }
+
+        #[test]
+        fn burn_generated_on_minted_balance() {
+            let mut available_inputs = VecDeque::new();
+            let mut input1 = InputRuneBalance::dummy();
+            input1.amount(1000).address(None); // No address because it's a mint.
+            available_inputs.push_back(input1);
+
+            let results = move_rune_balance_to_output(
+                &TransactionLocation::dummy(),
+                None, // Burn
+                &RuneId::new(840000, 25).unwrap(),
+                &mut available_inputs,
+                &HashMap::new(),
+                0,
+                &mut 0,
+                &Context::empty(),
+            );
+
+            assert_eq!(results.len(), 1);
+            let entry1 = results.first().unwrap();
+            assert_eq!(entry1.operation, DbLedgerOperation::Burn);
+            assert_eq!(entry1.address, None);
+            assert_eq!(entry1.amount.unwrap().0, 1000);
+            assert_eq!(available_inputs.len(), 0);
+        }
     }
 
     mod mint_validation {
@@ -604,9 +777,7 @@ mod test {
             let mut rune = DbRune::factory();
             rune.terms_height_start(Some(PgNumericU64(840100)));
             rune.terms_height_end(Some(PgNumericU64(840200)));
-            let mut location = TransactionLocation::dummy();
-            location.block_height(block_height);
-            is_rune_mintable(&rune, 0, &location)
+            is_rune_mintable(&rune, 0, block_height)
         }
 
         #[test_case(840000 => false; "early block")]
@@ -618,9 +789,7 @@ mod test {
             let mut rune = DbRune::factory();
             rune.terms_offset_start(Some(PgNumericU64(100)));
             rune.terms_offset_end(Some(PgNumericU64(200)));
-            let mut location = TransactionLocation::dummy();
-            location.block_height(block_height);
-            is_rune_mintable(&rune, 0, &location)
+            is_rune_mintable(&rune, 0, block_height)
         }
 
         #[test_case(0 => true; "first mint")]
@@ -629,7 +798,7 @@ mod test {
         fn mint_cap_is_validated(cap: u128) -> bool {
             let mut rune = DbRune::factory();
             rune.terms_cap(Some(PgNumericU128(50)));
-            is_rune_mintable(&rune, cap, &TransactionLocation::dummy())
+            is_rune_mintable(&rune, cap, TransactionLocation::dummy().block_height)
         }
     }
 
@@ -697,12 +866,16 @@ mod test {
         use maplit::hashmap;
         use ordinals::RuneId;
 
-        use crate::db::{
-            cache::{
-                input_rune_balance::InputRuneBalance, utils::input_rune_balances_from_tx_inputs,
+        use crate::{
+            db::{
+                cache::{
+                    input_rune_balance::InputRuneBalance, utils::input_rune_balances_from_tx_inputs,
+                },
+                models::{db_ledger_entry::DbLedgerEntry, db_ledger_operation::DbLedgerOperation},
+                pg_insert_ledger_entries, pg_test_client, pg_test_roll_back_migrations,
+                storage::{memory::MemoryStorage, Storage},
             },
-            models::{db_ledger_entry::DbLedgerEntry, db_ledger_operation::DbLedgerOperation},
-            pg_insert_ledger_entries, pg_test_client, pg_test_roll_back_migrations,
+            monitoring::PrometheusMonitoring,
         };
 
         #[tokio::test]
@@ -725,24 +898,23 @@ mod test {
             let block_output_cache = hashmap! {
                 ("045fe33f1174d6a72084e751735a89746a259c6d3e418b65c03ec0740f924c7b"
                             .to_string(), 1) => hashmap! {
-                                rune_id => vec![InputRuneBalance { address: None, amount: 2000 }]
+                                rune_id => vec![InputRuneBalance { address: None, script_pubkey: None, amount: 2000 }]
                             }
             };
             let mut output_cache = LruCache::new(NonZeroUsize::new(1).unwrap());
             let ctx = Context::empty();
 
-            let mut pg_client = pg_test_client(true, &ctx).await;
-            let mut db_tx = pg_client.transaction().await.unwrap();
+            let mut storage = MemoryStorage::new();
+            let mut db_tx = storage.begin().await.unwrap();
             let results = input_rune_balances_from_tx_inputs(
                 &inputs,
                 &block_output_cache,
                 &mut output_cache,
                 &mut db_tx,
+                &PrometheusMonitoring::new(),
                 &ctx,
             )
             .await;
-            let _ = db_tx.rollback().await;
-            pg_test_roll_back_migrations(&mut pg_client, &ctx).await;
 
             assert_eq!(results.len(), 1);
             let rune_results = results.get(&rune_id).unwrap();
@@ -777,23 +949,22 @@ mod test {
                     1,
                 ),
                 hashmap! {
-                    rune_id => vec![InputRuneBalance { address: None, amount: 2000 }]
+                    rune_id => vec![InputRuneBalance { address: None, script_pubkey: None, amount: 2000 }]
                 },
             );
             let ctx = Context::empty();
 
-            let mut pg_client = pg_test_client(true, &ctx).await;
-            let mut db_tx = pg_client.transaction().await.unwrap();
+            let mut storage = MemoryStorage::new();
+            let mut db_tx = storage.begin().await.unwrap();
             let results = input_rune_balances_from_tx_inputs(
                 &inputs,
                 &block_output_cache,
                 &mut output_cache,
                 &mut db_tx,
+                &PrometheusMonitoring::new(),
                 &ctx,
             )
             .await;
-            let _ = db_tx.rollback().await;
-            pg_test_roll_back_migrations(&mut pg_client, &ctx).await;
 
             assert_eq!(results.len(), 1);
             let rune_results = results.get(&rune_id).unwrap();
@@ -841,13 +1012,14 @@ mod test {
                 DbLedgerOperation::Receive,
                 0,
             );
-            let _ = pg_insert_ledger_entries(&vec![entry], &mut db_tx, &ctx).await;
+            let _ = pg_insert_ledger_entries(&[entry], &mut db_tx, &ctx).await;
 
             let results = input_rune_balances_from_tx_inputs(
                 &inputs,
                 &block_output_cache,
                 &mut output_cache,
                 &mut db_tx,
+                &PrometheusMonitoring::new(),
                 &ctx,
             )
             .await;
@@ -882,18 +1054,17 @@ mod test {
             let mut output_cache = LruCache::new(NonZeroUsize::new(1).unwrap());
             let ctx = Context::empty();
 
-            let mut pg_client = pg_test_client(true, &ctx).await;
-            let mut db_tx = pg_client.transaction().await.unwrap();
+            let mut storage = MemoryStorage::new();
+            let mut db_tx = storage.begin().await.unwrap();
             let results = input_rune_balances_from_tx_inputs(
                 &inputs,
                 &block_output_cache,
                 &mut output_cache,
                 &mut db_tx,
+                &PrometheusMonitoring::new(),
                 &ctx,
             )
             .await;
-            let _ = db_tx.rollback().await;
-            pg_test_roll_back_migrations(&mut pg_client, &ctx).await;
 
             assert_eq!(results.len(), 0);
         }
@@ -916,7 +1087,7 @@ mod test {
             let mut block_output_cache = hashmap! {
                 ("045fe33f1174d6a72084e751735a89746a259c6d3e418b65c03ec0740f924c7b"
                             .to_string(), 1) => hashmap! {
-                                rune_id => vec![InputRuneBalance { address: None, amount: 2000 }]
+                                rune_id => vec![InputRuneBalance { address: None, script_pubkey: None, amount: 2000 }]
                             }
             };
             let mut output_cache = LruCache::new(NonZeroUsize::new(1).unwrap());
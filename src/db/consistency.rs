@@ -0,0 +1,312 @@
+use tokio_postgres::{types::ToSql, GenericClient, Transaction};
+
+use super::types::pg_numeric_u64::PgNumericU64;
+
+/// Cumulative `supply_changes` values recomputed from the `ledger` rows matching `ledger_filter`, exposed as an `expected`
+/// CTE. Mirrors the aggregation done by `IndexCache::add_ledger_entries_to_db_cache` and `pg_insert_supply_changes`.
+pub(super) fn expected_supply_changes(ledger_filter: &str) -> String {
+    format!(
+        "
+    per_block AS (
+        SELECT rune_id, block_height,
+            COALESCE(SUM(amount) FILTER (WHERE operation = 'mint'), 0) AS minted,
+            COUNT(*) FILTER (WHERE operation = 'mint') AS mints,
+            COALESCE(SUM(amount) FILTER (WHERE operation = 'burn'), 0) AS burned,
+            COUNT(*) FILTER (WHERE operation = 'burn') AS burns,
+            COUNT(*) AS operations
+        FROM ledger
+        WHERE {ledger_filter}
+        GROUP BY rune_id, block_height
+    ),
+    expected AS (
+        SELECT rune_id, block_height,
+            SUM(minted) OVER w AS minted,
+            SUM(mints) OVER w AS total_mints,
+            SUM(burned) OVER w AS burned,
+            SUM(burns) OVER w AS total_burns,
+            SUM(operations) OVER w AS total_operations
+        FROM per_block
+        WINDOW w AS (PARTITION BY rune_id ORDER BY block_height)
+    )"
+    )
+}
+
+/// Cumulative `balance_changes` values recomputed from the `ledger` rows matching `ledger_filter`, exposed as an `expected`
+/// CTE. Mirrors the aggregation done by `IndexCache::add_ledger_entries_to_db_cache` and `pg_insert_balance_changes`.
+pub(super) fn expected_balance_changes(ledger_filter: &str) -> String {
+    format!(
+        "
+    per_block AS (
+        SELECT rune_id, block_height, address,
+            COALESCE(SUM(amount) FILTER (WHERE operation = 'receive'), 0)
+                - COALESCE(SUM(amount) FILTER (WHERE operation = 'send'), 0) AS balance,
+            COUNT(*) AS operations
+        FROM ledger
+        WHERE ({ledger_filter}) AND address IS NOT NULL AND operation IN ('send', 'receive')
+        GROUP BY rune_id, block_height, address
+    ),
+    expected AS (
+        SELECT rune_id, block_height, address,
+            SUM(balance) OVER w AS balance,
+            SUM(operations) OVER w AS total_operations
+        FROM per_block
+        WINDOW w AS (PARTITION BY rune_id, address ORDER BY block_height)
+    )"
+    )
+}
+
+/// A row of a derived table that does not match the value recomputed from `ledger`. `expected` is `None` if the row should
+/// not exist and `actual` is `None` if it is missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub table: &'static str,
+    pub rune_id: String,
+    pub block_height: String,
+    pub address: Option<String>,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+/// A `balance_changes` row with a negative balance, which means more runes were sent from an address than it received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NegativeBalance {
+    pub rune_id: String,
+    pub block_height: String,
+    pub address: String,
+    pub balance: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConsistencyReport {
+    pub divergences: Vec<Divergence>,
+    pub negative_balances: Vec<NegativeBalance>,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.divergences.is_empty() && self.negative_balances.is_empty()
+    }
+}
+
+/// Recomputes `supply_changes` and `balance_changes` rows between `start_block` and `end_block` from `ledger` and reports
+/// every row that differs, plus any negative balance.
+pub async fn pg_check_derived_tables<T: GenericClient>(
+    client: &T,
+    start_block: u64,
+    end_block: u64,
+) -> Result<ConsistencyReport, String> {
+    let (start, end) = (PgNumericU64(start_block), PgNumericU64(end_block));
+    let range: [&(dyn ToSql + Sync); 2] = [&start, &end];
+    let mut report = ConsistencyReport::default();
+
+    let rows = client
+        .query(
+            &format!(
+                "WITH {},
+                actual AS (SELECT * FROM supply_changes WHERE block_height BETWEEN $1 AND $2)
+                SELECT COALESCE(e.rune_id, a.rune_id) AS rune_id,
+                    COALESCE(e.block_height, a.block_height)::TEXT AS block_height,
+                    CASE WHEN e.rune_id IS NULL THEN NULL ELSE CONCAT('minted=', e.minted,
+                        ' total_mints=', e.total_mints, ' burned=', e.burned, ' total_burns=', e.total_burns,
+                        ' total_operations=', e.total_operations) END AS expected,
+                    CASE WHEN a.rune_id IS NULL THEN NULL ELSE CONCAT('minted=', a.minted,
+                        ' total_mints=', a.total_mints, ' burned=', a.burned, ' total_burns=', a.total_burns,
+                        ' total_operations=', a.total_operations) END AS actual
+                FROM (SELECT * FROM expected WHERE block_height BETWEEN $1 AND $2) AS e
+                FULL OUTER JOIN actual AS a ON e.rune_id = a.rune_id AND e.block_height = a.block_height
+                WHERE e.rune_id IS NULL OR a.rune_id IS NULL
+                    OR (e.minted, e.total_mints, e.burned, e.total_burns, e.total_operations)
+                        IS DISTINCT FROM (a.minted, a.total_mints, a.burned, a.total_burns, a.total_operations)
+                ORDER BY COALESCE(e.block_height, a.block_height), 1",
+                expected_supply_changes("block_height <= $2")
+            ),
+            &range,
+        )
+        .await
+        .map_err(|e| format!("error checking supply_changes: {e}"))?;
+    report.divergences.extend(rows.iter().map(|row| Divergence {
+        table: "supply_changes",
+        rune_id: row.get("rune_id"),
+        block_height: row.get("block_height"),
+        address: None,
+        expected: row.get("expected"),
+        actual: row.get("actual"),
+    }));
+
+    let rows = client
+        .query(
+            &format!(
+                "WITH {},
+                actual AS (SELECT * FROM balance_changes WHERE block_height BETWEEN $1 AND $2)
+                SELECT COALESCE(e.rune_id, a.rune_id) AS rune_id,
+                    COALESCE(e.block_height, a.block_height)::TEXT AS block_height,
+                    COALESCE(e.address, a.address) AS address,
+                    CASE WHEN e.rune_id IS NULL THEN NULL ELSE CONCAT('balance=', e.balance,
+                        ' total_operations=', e.total_operations) END AS expected,
+                    CASE WHEN a.rune_id IS NULL THEN NULL ELSE CONCAT('balance=', a.balance,
+                        ' total_operations=', a.total_operations) END AS actual
+                FROM (SELECT * FROM expected WHERE block_height BETWEEN $1 AND $2) AS e
+                FULL OUTER JOIN actual AS a
+                    ON e.rune_id = a.rune_id AND e.block_height = a.block_height AND e.address = a.address
+                WHERE e.rune_id IS NULL OR a.rune_id IS NULL
+                    OR (e.balance, e.total_operations) IS DISTINCT FROM (a.balance, a.total_operations)
+                ORDER BY COALESCE(e.block_height, a.block_height), 1, 3",
+                expected_balance_changes("block_height <= $2")
+            ),
+            &range,
+        )
+        .await
+        .map_err(|e| format!("error checking balance_changes: {e}"))?;
+    report.divergences.extend(rows.iter().map(|row| Divergence {
+        table: "balance_changes",
+        rune_id: row.get("rune_id"),
+        block_height: row.get("block_height"),
+        address: row.get("address"),
+        expected: row.get("expected"),
+        actual: row.get("actual"),
+    }));
+
+    let rows = client
+        .query(
+            "SELECT rune_id, block_height::TEXT AS block_height, address, balance::TEXT AS balance
+            FROM balance_changes
+            WHERE block_height BETWEEN $1 AND $2 AND balance < 0
+            ORDER BY block_height, rune_id, address",
+            &range,
+        )
+        .await
+        .map_err(|e| format!("error checking negative balances: {e}"))?;
+    report
+        .negative_balances
+        .extend(rows.iter().map(|row| NegativeBalance {
+            rune_id: row.get("rune_id"),
+            block_height: row.get("block_height"),
+            address: row.get("address"),
+            balance: row.get("balance"),
+        }));
+
+    Ok(report)
+}
+
+/// Replaces `supply_changes` and `balance_changes` rows between `start_block` and `end_block` with values recomputed from
+/// `ledger`. Returns the number of rows written to each table.
+pub async fn pg_repair_derived_tables(
+    start_block: u64,
+    end_block: u64,
+    db_tx: &mut Transaction<'_>,
+) -> Result<(u64, u64), String> {
+    let (start, end) = (PgNumericU64(start_block), PgNumericU64(end_block));
+    let range: [&(dyn ToSql + Sync); 2] = [&start, &end];
+    db_tx
+        .execute(
+            "DELETE FROM supply_changes WHERE block_height BETWEEN $1 AND $2",
+            &range,
+        )
+        .await
+        .map_err(|e| format!("error deleting supply_changes: {e}"))?;
+    let supply_rows = db_tx
+        .execute(
+            &format!(
+                "WITH {}
+                INSERT INTO supply_changes (rune_id, block_height, minted, total_mints, burned, total_burns, total_operations)
+                SELECT rune_id, block_height, minted, total_mints, burned, total_burns, total_operations
+                FROM expected WHERE block_height BETWEEN $1 AND $2",
+                expected_supply_changes("block_height <= $2")
+            ),
+            &range,
+        )
+        .await
+        .map_err(|e| format!("error repairing supply_changes: {e}"))?;
+    db_tx
+        .execute(
+            "DELETE FROM balance_changes WHERE block_height BETWEEN $1 AND $2",
+            &range,
+        )
+        .await
+        .map_err(|e| format!("error deleting balance_changes: {e}"))?;
+    let balance_rows = db_tx
+        .execute(
+            &format!(
+                "WITH {}
+                INSERT INTO balance_changes (rune_id, block_height, address, balance, total_operations)
+                SELECT rune_id, block_height, address, balance, total_operations
+                FROM expected WHERE block_height BETWEEN $1 AND $2",
+                expected_balance_changes("block_height <= $2")
+            ),
+            &range,
+        )
+        .await
+        .map_err(|e| format!("error repairing balance_changes: {e}"))?;
+    Ok((supply_rows, balance_rows))
+}
+
+#[cfg(test)]
+mod test {
+    use chainhook_sdk::utils::Context;
+
+    use crate::db::{pg_test_drop_schema, pg_test_schema_client};
+
+    use super::{pg_check_derived_tables, pg_repair_derived_tables};
+
+    const SCHEMA: &str = "test_consistency";
+
+    #[tokio::test]
+    async fn detects_and_repairs_divergences() {
+        let ctx = Context::empty();
+        let mut client = pg_test_schema_client(SCHEMA, &ctx).await;
+        client
+            .batch_execute(
+                "INSERT INTO ledger (rune_id, block_hash, block_height, tx_index, event_index, tx_id, output, address,
+                    amount, operation, timestamp)
+                VALUES
+                    ('1:0', 'h1', 840001, 0, 0, 't1', NULL, NULL, 100, 'mint', 0),
+                    ('1:0', 'h1', 840001, 0, 1, 't1', 0, 'a', 100, 'receive', 0),
+                    ('1:0', 'h2', 840002, 0, 0, 't2', NULL, 'a', 40, 'send', 0),
+                    ('1:0', 'h2', 840002, 0, 1, 't2', 0, 'b', 30, 'receive', 0),
+                    ('1:0', 'h2', 840002, 0, 2, 't2', NULL, NULL, 10, 'burn', 0);
+                INSERT INTO supply_changes VALUES
+                    ('1:0', 840001, 100, 1, 0, 0, 2),
+                    ('1:0', 840002, 100, 1, 10, 1, 4);
+                INSERT INTO balance_changes VALUES
+                    ('1:0', 840001, 'a', 100, 1),
+                    ('1:0', 840002, 'a', 60, 2),
+                    ('1:0', 840002, 'b', -30, 1);",
+            )
+            .await
+            .unwrap();
+
+        let report = pg_check_derived_tables(&client, 840000, 840010)
+            .await
+            .unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.divergences.len(), 2);
+        let supply = &report.divergences[0];
+        assert_eq!(supply.table, "supply_changes");
+        assert_eq!(supply.block_height, "840002");
+        assert_eq!(
+            supply.expected,
+            Some("minted=100 total_mints=1 burned=10 total_burns=1 total_operations=5".to_string())
+        );
+        let balance = &report.divergences[1];
+        assert_eq!(balance.table, "balance_changes");
+        assert_eq!(balance.address, Some("b".to_string()));
+        assert_eq!(
+            balance.expected,
+            Some("balance=30 total_operations=1".to_string())
+        );
+        assert_eq!(report.negative_balances.len(), 1);
+        assert_eq!(report.negative_balances[0].balance, "-30");
+
+        let mut db_tx = client.transaction().await.unwrap();
+        let written = pg_repair_derived_tables(840000, 840010, &mut db_tx)
+            .await
+            .unwrap();
+        db_tx.commit().await.unwrap();
+        assert_eq!(written, (2, 3));
+        let report = pg_check_derived_tables(&client, 840000, 840010)
+            .await
+            .unwrap();
+        pg_test_drop_schema(&client, SCHEMA).await;
+        assert!(report.is_consistent());
+    }
+}
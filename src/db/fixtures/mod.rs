@@ -0,0 +1,371 @@
+//! Test support for indexing synthetic blocks. `BlockBuilder` and `TxBuilder` describe blocks in terms of runestones and test
+//! wallets, `index_blocks` runs them through `index_block` with in-memory storage and `assert_golden` compares the resulting
+//! tables with a checked-in JSON file under `src/db/fixtures/golden`.
+//!
+//! Golden files are (re)generated by running the tests with `RUNEHOOK_UPDATE_GOLDEN=1`. Review the diff before committing it.
+
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use bitcoin::{opcodes::all::OP_RETURN, script::Builder, Address, Network, ScriptBuf};
+use chainhook_sdk::{
+    types::{
+        bitcoin::{OutPoint, TxIn, TxOut},
+        BitcoinBlockData, BitcoinBlockMetadata, BitcoinNetwork, BitcoinTransactionData,
+        BitcoinTransactionMetadata, BlockIdentifier, TransactionIdentifier,
+    },
+    utils::Context,
+};
+use ordinals::{varint, Runestone};
+use serde_json::{json, Value};
+
+use crate::{
+    config::{file::ConfigFile, generator::generate_config, Config},
+    db::{
+        cache::index_cache::IndexCache,
+        index::index_block,
+        storage::memory::{MemoryStorage, MemoryTables},
+    },
+    monitoring::PrometheusMonitoring,
+};
+
+/// Value of every output created by the builders, unless given to `TxBuilder::pay`.
+const OUTPUT_VALUE: u64 = 546;
+
+/// Timestamp of every fixture block.
+const BLOCK_TIMESTAMP: u32 = 1713571767;
+
+/// Id of the `tx_index`th transaction of the fixture block at `block_height`. The location is readable from the id so golden
+/// files can show it instead.
+pub fn tx_id(block_height: u64, tx_index: u32) -> String {
+    format!("{:032x}{:032x}", block_height, tx_index)
+}
+
+/// P2WPKH script of the test wallet `owner`.
+pub fn owner_script(owner: u8) -> ScriptBuf {
+    let mut bytes = vec![0x00, 0x14];
+    bytes.extend([owner; 20]);
+    ScriptBuf::from_bytes(bytes)
+}
+
+pub fn owner_address(owner: u8) -> String {
+    Address::from_script(&owner_script(owner), Network::Bitcoin)
+        .unwrap()
+        .to_string()
+}
+
+/// A transaction whose outputs are created in the order the builder methods are called.
+#[derive(Clone, Default)]
+pub struct TxBuilder {
+    /// Spent outputs and the witness of each input.
+    inputs: Vec<(String, u32, Vec<String>)>,
+    outputs: Vec<(ScriptBuf, u64)>,
+}
+
+impl TxBuilder {
+    pub fn new() -> Self {
+        TxBuilder::default()
+    }
+
+    /// Spends output `vout` of the `tx_index`th transaction of the block at `block_height`.
+    pub fn spend(mut self, block_height: u64, tx_index: u32, vout: u32) -> Self {
+        self.inputs
+            .push((tx_id(block_height, tx_index), vout, vec![]));
+        self
+    }
+
+    /// Same as `spend`, but signs the input with `SIGHASH_SINGLE | SIGHASH_ANYONECANPAY` as a marketplace listing would.
+    pub fn spend_listed(mut self, block_height: u64, tx_index: u32, vout: u32) -> Self {
+        let signature = format!("0x{}83", "01".repeat(64));
+        self.inputs
+            .push((tx_id(block_height, tx_index), vout, vec![signature]));
+        self
+    }
+
+    /// Spends an output that was never indexed and thus holds no runes, to pay for fees.
+    pub fn fund(mut self) -> Self {
+        let vout = self.inputs.len() as u32;
+        self.inputs.push(("f".repeat(64), vout, vec![]));
+        self
+    }
+
+    /// Adds a P2WPKH output paying to the test wallet `owner`.
+    pub fn to(mut self, owner: u8) -> Self {
+        self.outputs.push((owner_script(owner), OUTPUT_VALUE));
+        self
+    }
+
+    /// Adds a P2WPKH output paying `value` sats to the test wallet `owner`.
+    pub fn pay(mut self, owner: u8, value: u64) -> Self {
+        self.outputs.push((owner_script(owner), value));
+        self
+    }
+
+    /// Adds an output with an arbitrary `script`, such as P2PK or bare multisig outputs that have no address.
+    pub fn script(mut self, script: ScriptBuf) -> Self {
+        self.outputs.push((script, OUTPUT_VALUE));
+        self
+    }
+
+    /// Adds an OP_RETURN output with `runestone`.
+    pub fn runestone(mut self, runestone: &Runestone) -> Self {
+        self.outputs.push((runestone.encipher(), OUTPUT_VALUE));
+        self
+    }
+
+    /// Adds a runestone OP_RETURN output with a raw list of integers, to build cenotaphs that `Runestone::encipher` refuses to
+    /// produce.
+    pub fn payload(mut self, integers: &[u128]) -> Self {
+        let mut payload = vec![];
+        for integer in integers.iter() {
+            varint::encode_to_vec(*integer, &mut payload);
+        }
+        let push: &bitcoin::script::PushBytes = payload.as_slice().try_into().unwrap();
+        self.outputs.push((
+            Builder::new()
+                .push_opcode(OP_RETURN)
+                .push_opcode(Runestone::MAGIC_NUMBER)
+                .push_slice(push)
+                .into_script(),
+            OUTPUT_VALUE,
+        ));
+        self
+    }
+
+    /// Adds an OP_RETURN output without a runestone. Runes sent to it are burned.
+    pub fn op_return(mut self) -> Self {
+        self.outputs.push((
+            Builder::new().push_opcode(OP_RETURN).into_script(),
+            OUTPUT_VALUE,
+        ));
+        self
+    }
+
+    fn build(&self, block_height: u64, tx_index: u32) -> BitcoinTransactionData {
+        BitcoinTransactionData {
+            transaction_identifier: TransactionIdentifier::new(&tx_id(block_height, tx_index)),
+            operations: vec![],
+            metadata: BitcoinTransactionMetadata {
+                inputs: self
+                    .inputs
+                    .iter()
+                    .map(|(tx_id, vout, witness)| TxIn {
+                        previous_output: OutPoint {
+                            txid: TransactionIdentifier::new(tx_id),
+                            vout: *vout,
+                            value: OUTPUT_VALUE,
+                            block_height: 0,
+                        },
+                        script_sig: "".to_string(),
+                        sequence: 0,
+                        witness: witness.clone(),
+                    })
+                    .collect(),
+                outputs: self
+                    .outputs
+                    .iter()
+                    .map(|(script, value)| TxOut {
+                        value: *value,
+                        script_pubkey: format!("0x{}", hex::encode(script.as_bytes())),
+                    })
+                    .collect(),
+                stacks_operations: vec![],
+                ordinal_operations: vec![],
+                brc20_operation: None,
+                proof: None,
+                fee: 0,
+                index: tx_index,
+            },
+        }
+    }
+}
+
+/// A mainnet block. Transactions are indexed starting at 1 because index 0 is reserved for the coinbase, so the first
+/// transaction added to block 840000 etches rune `840000:1`.
+#[derive(Clone)]
+pub struct BlockBuilder {
+    height: u64,
+    txs: Vec<TxBuilder>,
+}
+
+impl BlockBuilder {
+    pub fn new(height: u64) -> Self {
+        BlockBuilder {
+            height,
+            txs: vec![],
+        }
+    }
+
+    pub fn tx(mut self, tx: TxBuilder) -> Self {
+        self.txs.push(tx);
+        self
+    }
+
+    pub fn build(&self) -> BitcoinBlockData {
+        BitcoinBlockData {
+            block_identifier: BlockIdentifier {
+                index: self.height,
+                hash: format!("0x{:064x}", self.height),
+            },
+            parent_block_identifier: BlockIdentifier {
+                index: self.height - 1,
+                hash: format!("0x{:064x}", self.height - 1),
+            },
+            timestamp: BLOCK_TIMESTAMP,
+            transactions: self
+                .txs
+                .iter()
+                .enumerate()
+                .map(|(i, tx)| tx.build(self.height, i as u32 + 1))
+                .collect(),
+            metadata: BitcoinBlockMetadata {
+                network: BitcoinNetwork::Mainnet,
+            },
+        }
+    }
+}
+
+pub fn mainnet_config() -> Config {
+    let config_file: ConfigFile =
+        toml::from_str(&generate_config(&BitcoinNetwork::Mainnet)).unwrap();
+    Config::from_config_file(config_file).unwrap()
+}
+
+/// Indexes `blocks` in order into a fresh in-memory index.
+pub async fn index_blocks(blocks: &[BlockBuilder]) -> (MemoryStorage, IndexCache) {
+    let ctx = Context::empty();
+    let mut storage = MemoryStorage::new();
+    let mut index_cache = IndexCache::new(
+        &mainnet_config(),
+        &mut storage,
+        &PrometheusMonitoring::new(),
+        &ctx,
+    )
+    .await;
+    for block in blocks.iter() {
+        index_block(&mut storage, &mut index_cache, &mut block.build(), &ctx)
+            .await
+            .unwrap();
+    }
+    (storage, index_cache)
+}
+
+/// Renders every row produced by the indexer, replacing fixture tx ids with `block:tx` and test wallet addresses and scripts
+/// with `owner_N`. Amounts are strings because JSON numbers can't hold every `u128`.
+pub fn tables_json(tables: &MemoryTables) -> Value {
+    let owners: HashMap<String, String> = (0..=u8::MAX)
+        .map(|owner| (owner_address(owner), format!("owner_{owner}")))
+        .collect();
+    let address = |address: &Option<String>| match address {
+        Some(address) => json!(owners.get(address).unwrap_or(address)),
+        None => Value::Null,
+    };
+    let scripts: HashMap<String, String> = (0..=u8::MAX)
+        .map(|owner| {
+            (
+                owner_script(owner).to_hex_string(),
+                format!("owner_{owner}"),
+            )
+        })
+        .collect();
+    let script = |script: &Option<String>| match script {
+        Some(script) => json!(scripts.get(script).unwrap_or(script)),
+        None => Value::Null,
+    };
+    let tx = |tx_id: &str| match (
+        u64::from_str_radix(&tx_id[..32], 16),
+        u32::from_str_radix(&tx_id[32..], 16),
+    ) {
+        (Ok(block_height), Ok(tx_index)) => format!("{block_height}:{tx_index}"),
+        _ => tx_id.to_string(),
+    };
+    let text = |value: Option<String>| value.map_or(Value::Null, |v| json!(v));
+    json!({
+        "runes": tables.runes.iter().filter(|rune| rune.number.0 > 0).map(|rune| json!({
+            "id": rune.id,
+            "number": rune.number.0,
+            "spaced_name": rune.spaced_name,
+            "divisibility": rune.divisibility.0,
+            "premine": rune.premine.0.to_string(),
+            "symbol": rune.symbol,
+            "terms_amount": text(rune.terms_amount.map(|v| v.0.to_string())),
+            "terms_cap": text(rune.terms_cap.map(|v| v.0.to_string())),
+            "terms_height_start": rune.terms_height_start.map(|v| v.0),
+            "terms_height_end": rune.terms_height_end.map(|v| v.0),
+            "terms_offset_start": rune.terms_offset_start.map(|v| v.0),
+            "terms_offset_end": rune.terms_offset_end.map(|v| v.0),
+            "turbo": rune.turbo,
+            "cenotaph": rune.cenotaph,
+            "mint_status": tables.mint_statuses.get(&rune.id).map(|status| status.as_str()),
+            "tx": tx(&rune.tx_id),
+        })).collect::<Vec<_>>(),
+        "ledger": tables.ledger.iter().map(|entry| json!({
+            "rune_id": entry.rune_id,
+            "tx": tx(&entry.tx_id),
+            "event_index": entry.event_index.0,
+            "operation": entry.operation.as_str(),
+            "output": entry.output.map(|o| o.0),
+            "address": address(&entry.address),
+            "receiver_address": address(&entry.receiver_address),
+            "amount": text(entry.amount.map(|a| a.0.to_string())),
+            "script_pubkey": script(&entry.script_pubkey),
+            "script_type": entry.script_type.map(|t| t.as_str().to_string()),
+            "output_value": entry.output_value.map(|v| v.0),
+            "tx_fee": entry.tx_fee.map(|v| v.0),
+            "tx_vsize": entry.tx_vsize.map(|v| v.0),
+        })).collect::<Vec<_>>(),
+        "supply_changes": tables.supply_changes.values().map(|row| json!({
+            "rune_id": row.rune_id,
+            "block_height": row.block_height.0,
+            "minted": row.minted.0.to_string(),
+            "total_mints": row.total_mints.0.to_string(),
+            "burned": row.burned.0.to_string(),
+            "total_burns": row.total_burns.0.to_string(),
+            "total_operations": row.total_operations.0.to_string(),
+        })).collect::<Vec<_>>(),
+        "balance_changes": tables.balance_changes.values().map(|row| json!({
+            "rune_id": row.rune_id,
+            "block_height": row.block_height.0,
+            "address": address(&Some(row.address.clone())),
+            "balance": row.balance.0.to_string(),
+            "total_operations": row.total_operations.0,
+        })).collect::<Vec<_>>(),
+        "trades": tables.trades.iter().map(|row| json!({
+            "rune_id": row.rune_id,
+            "tx": tx(&row.tx_id),
+            "seller_address": address(&Some(row.seller_address.clone())),
+            "buyer_address": address(&Some(row.buyer_address.clone())),
+            "amount": row.amount.0.to_string(),
+            "price": row.price.0,
+            "price_per_unit": row.price_per_unit,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/db/fixtures/golden")
+        .join(format!("{name}.json"))
+}
+
+/// Compares `actual` with the golden file `name`, or overwrites the file when `RUNEHOOK_UPDATE_GOLDEN` is set.
+pub fn assert_golden(name: &str, actual: &Value) {
+    let path = golden_path(name);
+    let rendered = format!("{}\n", serde_json::to_string_pretty(actual).unwrap());
+    if std::env::var("RUNEHOOK_UPDATE_GOLDEN").is_ok() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, rendered).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing golden file {}, run with RUNEHOOK_UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    assert_eq!(
+        rendered,
+        expected,
+        "{} is out of date, run with RUNEHOOK_UPDATE_GOLDEN=1 and review the diff",
+        path.display()
+    );
+}
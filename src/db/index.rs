@@ -8,11 +8,12 @@ use bitcoin::Transaction;
 use chainhook_sdk::types::BitcoinTransactionData;
 use chainhook_sdk::{types::BitcoinBlockData, utils::Context};
 use ordinals::Artifact;
+use ordinals::Rune;
 use ordinals::Runestone;
-use tokio_postgres::Client;
 
 use crate::db::cache::transaction_location::TransactionLocation;
-use crate::db::pg_roll_back_block;
+use crate::db::storage::{Storage, StorageTransaction};
+use crate::logging::{with_block_height, with_tx_id};
 use crate::try_info;
 
 use super::cache::index_cache::IndexCache;
@@ -20,40 +21,32 @@ use super::cache::index_cache::IndexCache;
 pub fn get_rune_genesis_block_height(network: Network) -> u64 {
     match network {
         Network::Bitcoin => 840_000,
-        Network::Testnet => todo!(),
-        Network::Signet => todo!(),
-        Network::Regtest => todo!(),
-        _ => todo!(),
+        network => Rune::first_rune_height(network) as u64,
     }
 }
 
 /// Transforms a Bitcoin transaction from a Chainhook format to a rust bitcoin crate format so it can be parsed by the ord crate
 /// to look for `Artifact`s. Also, takes all non-OP_RETURN outputs and returns them so they can be used later to receive runes.
 #[cfg_attr(test, mutants::skip)]
-fn bitcoin_tx_from_chainhook_tx(
+pub fn bitcoin_tx_from_chainhook_tx(
     block: &BitcoinBlockData,
     tx: &BitcoinTransactionData,
 ) -> (Transaction, HashMap<u32, ScriptBuf>, Option<u32>, u32) {
-    let mut outputs = vec![];
-    let mut eligible_outputs = HashMap::new();
-    let mut first_eligible_output: Option<u32> = None;
-    for (i, output) in tx.metadata.outputs.iter().enumerate() {
-        let script = ScriptBuf::from_bytes(output.get_script_pubkey_bytes());
-        if !script.is_op_return() {
-            eligible_outputs.insert(i as u32, script.clone());
-            if first_eligible_output.is_none() {
-                first_eligible_output = Some(i as u32);
-            }
-        }
-        outputs.push(TxOut {
+    let outputs: Vec<TxOut> = tx
+        .metadata
+        .outputs
+        .iter()
+        .map(|output| TxOut {
             value: output.value,
-            script_pubkey: script,
-        });
-    }
+            script_pubkey: ScriptBuf::from_bytes(output.get_script_pubkey_bytes()),
+        })
+        .collect();
+    let (eligible_outputs, first_eligible_output) = get_eligible_outputs(&outputs);
     (
         Transaction {
             version: 2,
-            lock_time: LockTime::from_time(block.timestamp).unwrap(),
+            // Lock time doesn't matter for Runestone parsing either, this only needs to never fail.
+            lock_time: LockTime::from_consensus(block.timestamp),
             // Inputs don't matter for Runestone parsing.
             input: vec![],
             output: outputs,
@@ -64,28 +57,48 @@ fn bitcoin_tx_from_chainhook_tx(
     )
 }
 
-/// Index a Bitcoin block for runes data.
+/// Returns every non-OP_RETURN output of a transaction, which are the ones that can receive runes, along with the index of the
+/// first one.
+pub fn get_eligible_outputs(outputs: &[TxOut]) -> (HashMap<u32, ScriptBuf>, Option<u32>) {
+    let mut eligible_outputs = HashMap::new();
+    let mut first_eligible_output: Option<u32> = None;
+    for (i, output) in outputs.iter().enumerate() {
+        if !output.script_pubkey.is_op_return() {
+            eligible_outputs.insert(i as u32, output.script_pubkey.clone());
+            if first_eligible_output.is_none() {
+                first_eligible_output = Some(i as u32);
+            }
+        }
+    }
+    (eligible_outputs, first_eligible_output)
+}
+
+/// Index a Bitcoin block for runes data. If the block can't be committed, `index_cache` is reset from `storage` before the
+/// error is returned.
 pub async fn index_block(
-    pg_client: &mut Client,
+    storage: &mut impl Storage,
     index_cache: &mut IndexCache,
     block: &mut BitcoinBlockData,
     ctx: &Context,
-) {
+) -> Result<(), String> {
     let stopwatch = std::time::Instant::now();
     let block_hash = &block.block_identifier.hash;
     let block_height = block.block_identifier.index;
+    let block_ctx = with_block_height(ctx, block_height);
+    let ctx = &block_ctx;
     try_info!(ctx, "Indexing block {}...", block_height);
 
-    let mut db_tx = pg_client
-        .transaction()
+    let mut db_tx = storage
+        .begin()
         .await
-        .expect("Unable to begin block processing pg transaction");
-    index_cache.reset_max_rune_number(&mut db_tx, ctx).await;
+        .expect("Unable to begin block processing transaction");
     for tx in block.transactions.iter() {
         let (transaction, eligible_outputs, first_eligible_output, total_outputs) =
             bitcoin_tx_from_chainhook_tx(block, tx);
         let tx_index = tx.metadata.index;
         let tx_id = &tx.transaction_identifier.hash;
+        let tx_ctx = with_tx_id(ctx, tx_id);
+        let ctx = &tx_ctx;
         let location = TransactionLocation {
             network: index_cache.network,
             block_hash: block_hash.clone(),
@@ -97,7 +110,7 @@ pub async fn index_block(
         index_cache
             .begin_transaction(
                 location,
-                &tx.metadata.inputs,
+                tx,
                 eligible_outputs,
                 first_eligible_output,
                 total_outputs,
@@ -136,39 +149,590 @@ pub async fn index_block(
                 }
             }
         }
-        index_cache.end_transaction(&mut db_tx, ctx);
+        index_cache.end_transaction(tx, &mut db_tx, ctx).await;
     }
     index_cache.end_block();
-    index_cache.db_cache.flush(&mut db_tx, ctx).await;
-    db_tx
-        .commit()
-        .await
-        .expect("Unable to commit pg transaction");
+    index_cache
+        .db_cache
+        .flush(&mut db_tx, &index_cache.monitoring, ctx)
+        .await;
+    db_tx.update_mint_statuses(block_height, ctx).await;
+    if let Err(e) = db_tx.commit().await {
+        index_cache.reset(storage, ctx).await;
+        return Err(format!("Unable to commit block {block_height}: {e}"));
+    }
+    index_cache
+        .monitoring
+        .metrics_block_indexed(block_height, stopwatch.elapsed().as_secs_f64());
     try_info!(
         ctx,
         "Block {} indexed in {}s",
         block_height,
         stopwatch.elapsed().as_millis() as f32 / 1000.0
     );
+    Ok(())
 }
 
-/// Roll back a Bitcoin block because of a re-org.
-pub async fn roll_back_block(pg_client: &mut Client, block_height: u64, ctx: &Context) {
+/// Roll back a Bitcoin block because of a re-org. If the roll back can't be committed, `index_cache` is reset from `storage`
+/// before the error is returned.
+pub async fn roll_back_block(
+    storage: &mut impl Storage,
+    index_cache: &mut IndexCache,
+    block_height: u64,
+    ctx: &Context,
+) -> Result<(), String> {
     let stopwatch = std::time::Instant::now();
     try_info!(ctx, "Rolling back block {}...", block_height);
-    let mut db_tx = pg_client
-        .transaction()
+    let mut db_tx = storage
+        .begin()
         .await
-        .expect("Unable to begin block roll back pg transaction");
-    pg_roll_back_block(block_height, &mut db_tx, ctx).await;
-    db_tx
-        .commit()
-        .await
-        .expect("Unable to commit pg transaction");
+        .expect("Unable to begin block roll back transaction");
+    index_cache
+        .roll_back_block(block_height, &mut db_tx, ctx)
+        .await;
+    db_tx.roll_back_block(block_height, ctx).await;
+    if let Err(e) = db_tx.commit().await {
+        index_cache.reset(storage, ctx).await;
+        return Err(format!("Unable to roll back block {block_height}: {e}"));
+    }
+    index_cache
+        .monitoring
+        .metrics_block_rolled_back(block_height);
     try_info!(
         ctx,
         "Block {} rolled back in {}s",
         block_height,
         stopwatch.elapsed().as_millis() as f32 / 1000.0
     );
+    Ok(())
+}
+
+/// Rolls back every block above `block_height` in a single DB transaction, so the database is never left at an
+/// intermediate height if the operation is interrupted.
+#[cfg_attr(test, mutants::skip)]
+pub async fn roll_back_to_block(
+    storage: &mut impl Storage,
+    block_height: u64,
+    ctx: &Context,
+) -> Result<(), String> {
+    let stopwatch = std::time::Instant::now();
+    try_info!(ctx, "Rolling back to block {}...", block_height);
+    let mut db_tx = storage.begin().await?;
+    db_tx.roll_back_to_block(block_height, ctx).await?;
+    db_tx.commit().await?;
+    try_info!(
+        ctx,
+        "Rolled back to block {} in {}s",
+        block_height,
+        stopwatch.elapsed().as_millis() as f32 / 1000.0
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::ScriptBuf;
+    use chainhook_sdk::utils::Context;
+    use ordinals::{Edict, Etching, Rune, RuneId, Runestone, Terms};
+    use test_case::test_case;
+
+    use crate::db::{
+        fixtures::{
+            assert_golden, index_blocks, owner_address, tables_json, BlockBuilder, TxBuilder,
+        },
+        storage::memory::MemoryStorage,
+    };
+
+    use super::{bitcoin_tx_from_chainhook_tx, index_block, roll_back_block};
+
+    const RUNE_ID: RuneId = RuneId {
+        block: 840000,
+        tx: 1,
+    };
+
+    fn etching(premine: u128, terms: Option<Terms>) -> Etching {
+        Etching {
+            divisibility: Some(2),
+            premine: Some(premine),
+            rune: Some(Rune::from_str("ZZZZZFEHUZZZZZ").unwrap()),
+            spacers: Some(0b10000),
+            symbol: Some('ᚠ'),
+            terms,
+            turbo: false,
+        }
+    }
+
+    fn open_terms(cap: u128) -> Option<Terms> {
+        Some(Terms {
+            amount: Some(100),
+            cap: Some(cap),
+            height: (None, None),
+            offset: (None, None),
+        })
+    }
+
+    /// Block 840000 with a single transaction that etches `RUNE_ID` and premines 1000 units to `owner_1`.
+    fn premined_block() -> BlockBuilder {
+        BlockBuilder::new(840000).tx(TxBuilder::new()
+            .fund()
+            .runestone(&Runestone {
+                etching: Some(etching(1000, None)),
+                ..Default::default()
+            })
+            .to(1))
+    }
+
+    fn mint() -> Runestone {
+        Runestone {
+            mint: Some(RUNE_ID),
+            ..Default::default()
+        }
+    }
+
+    fn edicts(edicts: Vec<(u128, u32)>, pointer: Option<u32>) -> Runestone {
+        Runestone {
+            edicts: edicts
+                .into_iter()
+                .map(|(amount, output)| Edict {
+                    id: RUNE_ID,
+                    amount,
+                    output,
+                })
+                .collect(),
+            pointer,
+            ..Default::default()
+        }
+    }
+
+    fn etching_with_premine() -> Vec<BlockBuilder> {
+        vec![premined_block()]
+    }
+
+    fn premine_to_pointer() -> Vec<BlockBuilder> {
+        vec![
+            BlockBuilder::new(840000).tx(TxBuilder::new().fund().to(1).to(2).runestone(
+                &Runestone {
+                    etching: Some(etching(1000, None)),
+                    pointer: Some(1),
+                    ..Default::default()
+                },
+            )),
+        ]
+    }
+
+    fn mints_up_to_cap() -> Vec<BlockBuilder> {
+        vec![BlockBuilder::new(840000)
+            .tx(TxBuilder::new().fund().runestone(&Runestone {
+                etching: Some(etching(0, open_terms(2))),
+                ..Default::default()
+            }))
+            .tx(TxBuilder::new().fund().runestone(&mint()).to(2))
+            .tx(TxBuilder::new().fund().runestone(&mint()).to(3))
+            .tx(TxBuilder::new().fund().runestone(&mint()).to(4))]
+    }
+
+    fn mints_within_height_terms() -> Vec<BlockBuilder> {
+        let terms = Some(Terms {
+            amount: Some(100),
+            cap: Some(10),
+            height: (Some(840001), Some(840002)),
+            offset: (None, None),
+        });
+        vec![
+            BlockBuilder::new(840000)
+                .tx(TxBuilder::new().fund().runestone(&Runestone {
+                    etching: Some(etching(0, terms)),
+                    ..Default::default()
+                }))
+                .tx(TxBuilder::new().fund().runestone(&mint()).to(2)),
+            BlockBuilder::new(840001).tx(TxBuilder::new().fund().runestone(&mint()).to(3)),
+            BlockBuilder::new(840002).tx(TxBuilder::new().fund().runestone(&mint()).to(4)),
+        ]
+    }
+
+    fn edict_with_remainder_to_first_output() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 1, 1)
+                .runestone(&edicts(vec![(300, 2)], None))
+                .to(2)
+                .to(3)),
+        ]
+    }
+
+    fn edict_with_remainder_to_pointer() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 1, 1)
+                .runestone(&edicts(vec![(300, 1)], Some(2)))
+                .to(2)
+                .to(3)),
+        ]
+    }
+
+    fn edict_splits_remaining_across_outputs() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 1, 1)
+                .runestone(&edicts(vec![(0, 4)], None))
+                .to(2)
+                .to(3)
+                .to(4)),
+        ]
+    }
+
+    fn edict_splits_amount_across_outputs() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 1, 1)
+                .runestone(&edicts(vec![(100, 3)], None))
+                .to(2)
+                .to(3)),
+        ]
+    }
+
+    fn edict_to_op_return_burns() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 1, 1)
+                .runestone(&edicts(vec![(300, 0)], None))
+                .to(2)),
+        ]
+    }
+
+    fn pointer_to_op_return_burns() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 1, 1)
+                .runestone(&edicts(vec![(300, 1)], Some(0)))
+                .to(2)),
+        ]
+    }
+
+    fn premine_to_op_return_burns() -> Vec<BlockBuilder> {
+        vec![
+            BlockBuilder::new(840000).tx(TxBuilder::new().fund().to(1).runestone(&Runestone {
+                etching: Some(etching(1000, None)),
+                pointer: Some(1),
+                ..Default::default()
+            })),
+        ]
+    }
+
+    fn mint_to_op_return_burns() -> Vec<BlockBuilder> {
+        vec![
+            BlockBuilder::new(840000).tx(TxBuilder::new().fund().runestone(&Runestone {
+                etching: Some(etching(0, open_terms(10))),
+                ..Default::default()
+            })),
+            BlockBuilder::new(840001).tx(TxBuilder::new().fund().to(2).runestone(&Runestone {
+                mint: Some(RUNE_ID),
+                pointer: Some(1),
+                ..Default::default()
+            })),
+        ]
+    }
+
+    fn cenotaph_unrecognized_even_tag_burns_inputs() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 1, 1)
+                .payload(&[126, 0])
+                .to(2)),
+        ]
+    }
+
+    fn cenotaph_edict_output_out_of_range_burns_inputs() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 1, 1)
+                .payload(&[0, 840000, 1, 100, 9])
+                .to(2)),
+        ]
+    }
+
+    fn cenotaph_etching_has_no_supply() -> Vec<BlockBuilder> {
+        let rune = Rune::from_str("ZZZZZFEHUZZZZZ").unwrap().0;
+        // Flags with the etching bit set and a premine, followed by an unrecognized even tag.
+        vec![BlockBuilder::new(840000).tx(TxBuilder::new()
+            .fund()
+            .payload(&[2, 1, 4, rune, 6, 1000, 126, 0])
+            .to(1))]
+    }
+
+    fn cenotaph_mint_is_burned() -> Vec<BlockBuilder> {
+        vec![
+            BlockBuilder::new(840000).tx(TxBuilder::new().fund().runestone(&Runestone {
+                etching: Some(etching(0, open_terms(10))),
+                ..Default::default()
+            })),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .fund()
+                .payload(&[20, 840000, 20, 1, 126, 0])
+                .to(2)),
+        ]
+    }
+
+    fn inputs_are_merged() -> Vec<BlockBuilder> {
+        vec![
+            BlockBuilder::new(840000)
+                .tx(TxBuilder::new().fund().runestone(&Runestone {
+                    etching: Some(etching(0, open_terms(10))),
+                    ..Default::default()
+                }))
+                .tx(TxBuilder::new().fund().runestone(&mint()).to(2))
+                .tx(TxBuilder::new().fund().runestone(&mint()).to(3)),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend(840000, 2, 1)
+                .spend(840000, 3, 1)
+                .to(4)),
+        ]
+    }
+
+    fn premine_to_script_without_address_is_held() -> Vec<BlockBuilder> {
+        vec![
+            BlockBuilder::new(840000).tx(TxBuilder::new()
+                .fund()
+                .runestone(&Runestone {
+                    etching: Some(etching(1000, None)),
+                    ..Default::default()
+                })
+                // A bare 1-of-1 multisig, which has no address.
+                .script(ScriptBuf::from_hex(
+                    "51210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f8179851ae",
+                ).unwrap())),
+            BlockBuilder::new(840001).tx(TxBuilder::new().spend(840000, 1, 1).to(2)),
+        ]
+    }
+
+    fn listed_premine_is_sold() -> Vec<BlockBuilder> {
+        vec![
+            premined_block(),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .spend_listed(840000, 1, 1)
+                .fund()
+                // The seller's payment, at the same index as the listed input.
+                .pay(1, 10_000)
+                .to(2)
+                .runestone(&edicts(vec![], Some(1)))),
+        ]
+    }
+
+    #[test_case("etching_with_premine", etching_with_premine())]
+    #[test_case("premine_to_pointer", premine_to_pointer())]
+    #[test_case("mints_up_to_cap", mints_up_to_cap())]
+    #[test_case("mints_within_height_terms", mints_within_height_terms())]
+    #[test_case(
+        "edict_with_remainder_to_first_output",
+        edict_with_remainder_to_first_output()
+    )]
+    #[test_case("edict_with_remainder_to_pointer", edict_with_remainder_to_pointer())]
+    #[test_case(
+        "edict_splits_remaining_across_outputs",
+        edict_splits_remaining_across_outputs()
+    )]
+    #[test_case(
+        "edict_splits_amount_across_outputs",
+        edict_splits_amount_across_outputs()
+    )]
+    #[test_case("edict_to_op_return_burns", edict_to_op_return_burns())]
+    #[test_case("pointer_to_op_return_burns", pointer_to_op_return_burns())]
+    #[test_case("premine_to_op_return_burns", premine_to_op_return_burns())]
+    #[test_case("mint_to_op_return_burns", mint_to_op_return_burns())]
+    #[test_case(
+        "cenotaph_unrecognized_even_tag_burns_inputs",
+        cenotaph_unrecognized_even_tag_burns_inputs()
+    )]
+    #[test_case(
+        "cenotaph_edict_output_out_of_range_burns_inputs",
+        cenotaph_edict_output_out_of_range_burns_inputs()
+    )]
+    #[test_case("cenotaph_etching_has_no_supply", cenotaph_etching_has_no_supply())]
+    #[test_case("cenotaph_mint_is_burned", cenotaph_mint_is_burned())]
+    #[test_case("inputs_are_merged", inputs_are_merged())]
+    #[test_case(
+        "premine_to_script_without_address_is_held",
+        premine_to_script_without_address_is_held()
+    )]
+    #[test_case("listed_premine_is_sold", listed_premine_is_sold())]
+    #[tokio::test]
+    async fn indexes_spec_vectors(name: &str, blocks: Vec<BlockBuilder>) {
+        let (storage, _) = index_blocks(&blocks).await;
+        assert_golden(name, &tables_json(&storage.tables));
+    }
+
+    fn balance(storage: &MemoryStorage, owner: u8) -> Option<u128> {
+        let address = owner_address(owner);
+        storage
+            .tables
+            .balance_changes
+            .values()
+            .rfind(|row| row.rune_id == RUNE_ID.to_string() && row.address == address)
+            .map(|row| row.balance.0)
+    }
+
+    #[test_case(0; "zero")]
+    #[test_case(499_999_999; "below lock time threshold")]
+    #[test_case(u32::MAX; "max")]
+    fn converts_transactions_with_any_block_timestamp(timestamp: u32) {
+        let mut block = BlockBuilder::new(840000)
+            .tx(TxBuilder::new().fund().to(1).op_return().to(2))
+            .build();
+        block.timestamp = timestamp;
+        let (transaction, eligible_outputs, first_eligible_output, total_outputs) =
+            bitcoin_tx_from_chainhook_tx(&block, &block.transactions[0]);
+        assert_eq!(transaction.output.len(), 3);
+        assert_eq!(eligible_outputs.len(), 2);
+        assert_eq!(first_eligible_output, Some(0));
+        assert_eq!(total_outputs, 3);
+    }
+
+    #[tokio::test]
+    async fn reset_reloads_counters_from_storage() {
+        let ctx = Context::empty();
+        let (_, mut index_cache) = index_blocks(&[premined_block()]).await;
+        // Same as if committing block 840000 had failed.
+        let mut storage = MemoryStorage::new();
+        index_cache.reset(&mut storage, &ctx).await;
+
+        index_block(
+            &mut storage,
+            &mut index_cache,
+            &mut premined_block().build(),
+            &ctx,
+        )
+        .await
+        .unwrap();
+        let rune = storage
+            .tables
+            .runes
+            .iter()
+            .find(|r| r.id == "840000:1")
+            .unwrap();
+        assert_eq!(rune.number.0, 1);
+        assert_eq!(balance(&storage, 1), Some(1000));
+    }
+
+    #[tokio::test]
+    async fn counts_every_balance_operation_in_a_block() {
+        let (storage, _) = index_blocks(&[
+            premined_block(),
+            BlockBuilder::new(840001)
+                .tx(TxBuilder::new()
+                    .spend(840000, 1, 1)
+                    .runestone(&edicts(vec![(300, 1)], Some(2)))
+                    .to(2)
+                    .to(1))
+                .tx(TxBuilder::new()
+                    .spend(840001, 1, 2)
+                    .runestone(&edicts(vec![(200, 1)], Some(2)))
+                    .to(2)
+                    .to(1)),
+        ])
+        .await;
+        let total_operations = |owner: u8| {
+            storage
+                .tables
+                .balance_changes
+                .get(&(RUNE_ID.to_string(), owner_address(owner), 840001))
+                .map(|row| row.total_operations.0)
+        };
+        assert_eq!(balance(&storage, 1), Some(500));
+        assert_eq!(balance(&storage, 2), Some(500));
+        // The premine, then a send to each output and a receive of the remainder in both transactions.
+        assert_eq!(total_operations(1), Some(7));
+        assert_eq!(total_operations(2), Some(2));
+    }
+
+    #[test_case(0; "zero")]
+    #[test_case(499_999_999; "below lock time threshold")]
+    #[tokio::test]
+    async fn indexes_blocks_with_any_timestamp(timestamp: u32) {
+        let ctx = Context::empty();
+        let (mut storage, mut index_cache) = index_blocks(&[]).await;
+        let mut block = premined_block().build();
+        block.timestamp = timestamp;
+        index_block(&mut storage, &mut index_cache, &mut block, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(balance(&storage, 1), Some(1000));
+    }
+
+    #[tokio::test]
+    async fn counts_cenotaph_mints_towards_cap() {
+        let ctx = Context::empty();
+        let mint_to = |block_height: u64, owner: u8| {
+            BlockBuilder::new(block_height)
+                .tx(TxBuilder::new().fund().runestone(&mint()).to(owner))
+                .build()
+        };
+        let (mut storage, mut index_cache) = index_blocks(&[
+            BlockBuilder::new(840000).tx(TxBuilder::new().fund().runestone(&Runestone {
+                etching: Some(etching(0, open_terms(1))),
+                ..Default::default()
+            })),
+            BlockBuilder::new(840001).tx(TxBuilder::new()
+                .fund()
+                .payload(&[20, 840000, 20, 1, 126, 0])
+                .to(2)),
+        ])
+        .await;
+
+        // The counter reloaded from storage still includes the cenotaph mint, which reached the cap.
+        index_cache.reset(&mut storage, &ctx).await;
+        index_block(
+            &mut storage,
+            &mut index_cache,
+            &mut mint_to(840002, 3),
+            &ctx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(balance(&storage, 3), None);
+
+        // Rolling back the cenotaph mint frees the cap again.
+        for block_height in [840002, 840001] {
+            roll_back_block(&mut storage, &mut index_cache, block_height, &ctx)
+                .await
+                .unwrap();
+        }
+        index_block(
+            &mut storage,
+            &mut index_cache,
+            &mut mint_to(840001, 3),
+            &ctx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(balance(&storage, 3), Some(100));
+    }
+
+    #[tokio::test]
+    async fn rolls_back_transfer() {
+        let (mut storage, mut index_cache) = index_blocks(&edict_with_remainder_to_pointer()).await;
+        assert_eq!(balance(&storage, 1), Some(0));
+        assert_eq!(balance(&storage, 2), Some(300));
+        assert_eq!(balance(&storage, 3), Some(700));
+
+        roll_back_block(&mut storage, &mut index_cache, 840001, &Context::empty())
+            .await
+            .unwrap();
+        assert_eq!(balance(&storage, 1), Some(1000));
+        assert_eq!(balance(&storage, 2), None);
+        assert!(storage
+            .tables
+            .ledger
+            .iter()
+            .all(|entry| entry.block_height.0 == 840000));
+    }
 }
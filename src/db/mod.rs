@@ -4,7 +4,7 @@ use cache::input_rune_balance::InputRuneBalance;
 use chainhook_sdk::utils::Context;
 use models::{
     db_balance_change::DbBalanceChange, db_ledger_entry::DbLedgerEntry, db_rune::DbRune,
-    db_supply_change::DbSupplyChange,
+    db_supply_change::DbSupplyChange, db_trade::DbTrade,
 };
 use ordinals::RuneId;
 use refinery::embed_migrations;
@@ -16,8 +16,13 @@ use types::{
 use crate::{config::Config, try_error, try_info};
 
 pub mod cache;
+pub mod consistency;
+#[cfg(test)]
+pub mod fixtures;
 pub mod index;
 pub mod models;
+pub mod rebuild;
+pub mod storage;
 pub mod types;
 
 embed_migrations!("migrations");
@@ -38,8 +43,7 @@ async fn pg_run_migrations(pg_client: &mut Client, ctx: &Context) {
     try_info!(ctx, "Postgres migrations complete");
 }
 
-#[cfg_attr(test, mutants::skip)]
-pub async fn pg_connect(config: &Config, run_migrations: bool, ctx: &Context) -> Client {
+fn pg_config(config: &Config) -> tokio_postgres::Config {
     let mut pg_config = tokio_postgres::Config::new();
     pg_config
         .dbname(&config.postgres.database)
@@ -49,6 +53,58 @@ pub async fn pg_connect(config: &Config, run_migrations: bool, ctx: &Context) ->
     if let Some(password) = config.postgres.password.as_ref() {
         pg_config.password(password);
     }
+    pg_config
+}
+
+/// Makes a single connection attempt to postgres without running migrations.
+#[cfg_attr(test, mutants::skip)]
+pub async fn pg_try_connect(config: &Config) -> Result<Client, String> {
+    let (client, connection) = pg_config(config)
+        .connect_timeout(std::time::Duration::from_secs(5))
+        .connect(NoTls)
+        .await
+        .map_err(|e| format!("unable to connect to postgres: {e}"))?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    Ok(client)
+}
+
+async fn pg_migrations_table_exists(client: &Client) -> Result<bool, String> {
+    client
+        .query_one("SELECT to_regclass('pgmigrations') IS NOT NULL", &[])
+        .await
+        .map(|row| row.get(0))
+        .map_err(|e| format!("unable to check migrations table: {e}"))
+}
+
+/// Returns the names of embedded migrations that have not been applied to the database yet.
+pub async fn pg_get_pending_migrations(client: &Client) -> Result<Vec<String>, String> {
+    let table_exists = pg_migrations_table_exists(client).await?;
+    let applied: Vec<i32> = if table_exists {
+        client
+            .query("SELECT version FROM pgmigrations", &[])
+            .await
+            .map_err(|e| format!("unable to read applied migrations: {e}"))?
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    } else {
+        vec![]
+    };
+    let mut pending: Vec<_> = migrations::runner()
+        .get_migrations()
+        .iter()
+        .filter(|m| !applied.contains(&(m.version() as i32)))
+        .cloned()
+        .collect();
+    pending.sort_by_key(|m| m.version());
+    Ok(pending.iter().map(|m| m.to_string()).collect())
+}
+
+#[cfg_attr(test, mutants::skip)]
+pub async fn pg_connect(config: &Config, run_migrations: bool, ctx: &Context) -> Client {
+    let pg_config = pg_config(config);
 
     try_info!(
         ctx,
@@ -82,7 +138,7 @@ pub async fn pg_connect(config: &Config, run_migrations: bool, ctx: &Context) ->
 }
 
 pub async fn pg_insert_runes(
-    rows: &Vec<DbRune>,
+    rows: &[DbRune],
     db_tx: &mut Transaction<'_>,
     ctx: &Context,
 ) -> Result<bool, Error> {
@@ -141,7 +197,7 @@ pub async fn pg_insert_runes(
 }
 
 pub async fn pg_insert_supply_changes(
-    rows: &Vec<DbSupplyChange>,
+    rows: &[DbSupplyChange],
     db_tx: &mut Transaction<'_>,
     ctx: &Context,
 ) -> Result<bool, Error> {
@@ -218,7 +274,7 @@ pub async fn pg_insert_supply_changes(
 }
 
 pub async fn pg_insert_balance_changes(
-    rows: &Vec<DbBalanceChange>,
+    rows: &[DbBalanceChange],
     increase: bool,
     db_tx: &mut Transaction<'_>,
     ctx: &Context,
@@ -283,7 +339,7 @@ pub async fn pg_insert_balance_changes(
 }
 
 pub async fn pg_insert_ledger_entries(
-    rows: &Vec<DbLedgerEntry>,
+    rows: &[DbLedgerEntry],
     db_tx: &mut Transaction<'_>,
     ctx: &Context,
 ) -> Result<bool, Error> {
@@ -293,12 +349,12 @@ pub async fn pg_insert_ledger_entries(
         let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
         for row in chunk.iter() {
             arg_str.push_str("(");
-            for i in 0..12 {
+            for i in 0..17 {
                 arg_str.push_str(format!("${},", arg_num + i).as_str());
             }
             arg_str.pop();
             arg_str.push_str("),");
-            arg_num += 12;
+            arg_num += 17;
             params.push(&row.rune_id);
             params.push(&row.block_hash);
             params.push(&row.block_height);
@@ -311,13 +367,18 @@ pub async fn pg_insert_ledger_entries(
             params.push(&row.amount);
             params.push(&row.operation);
             params.push(&row.timestamp);
+            params.push(&row.script_pubkey);
+            params.push(&row.script_type);
+            params.push(&row.output_value);
+            params.push(&row.tx_fee);
+            params.push(&row.tx_vsize);
         }
         arg_str.pop();
         match db_tx
             .query(
                 &format!("INSERT INTO ledger
                     (rune_id, block_hash, block_height, tx_index, event_index, tx_id, output, address, receiver_address, amount,
-                    operation, timestamp)
+                    operation, timestamp, script_pubkey, script_type, output_value, tx_fee, tx_vsize)
                     VALUES {}", arg_str),
                 &params,
             )
@@ -333,6 +394,56 @@ pub async fn pg_insert_ledger_entries(
     Ok(true)
 }
 
+pub async fn pg_insert_trades(
+    rows: &[DbTrade],
+    db_tx: &mut Transaction<'_>,
+    ctx: &Context,
+) -> Result<bool, Error> {
+    for chunk in rows.chunks(500) {
+        let mut arg_num = 1;
+        let mut arg_str = String::new();
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+        for row in chunk.iter() {
+            arg_str.push('(');
+            for i in 0..11 {
+                arg_str.push_str(format!("${},", arg_num + i).as_str());
+            }
+            arg_str.pop();
+            arg_str.push_str("),");
+            arg_num += 11;
+            params.push(&row.rune_id);
+            params.push(&row.block_hash);
+            params.push(&row.block_height);
+            params.push(&row.tx_index);
+            params.push(&row.tx_id);
+            params.push(&row.seller_address);
+            params.push(&row.buyer_address);
+            params.push(&row.amount);
+            params.push(&row.price);
+            params.push(&row.price_per_unit);
+            params.push(&row.timestamp);
+        }
+        arg_str.pop();
+        match db_tx
+            .query(
+                &format!("INSERT INTO trades
+                    (rune_id, block_hash, block_height, tx_index, tx_id, seller_address, buyer_address, amount, price,
+                    price_per_unit, timestamp)
+                    VALUES {}", arg_str),
+                &params,
+            )
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                try_error!(ctx, "Error inserting trades: {:?}", e);
+                process::exit(1);
+            }
+        };
+    }
+    Ok(true)
+}
+
 pub async fn pg_roll_back_block(block_height: u64, db_tx: &mut Transaction<'_>, _ctx: &Context) {
     db_tx
         .execute(
@@ -355,6 +466,13 @@ pub async fn pg_roll_back_block(block_height: u64, db_tx: &mut Transaction<'_>,
         )
         .await
         .expect("error rolling back ledger");
+    db_tx
+        .execute(
+            "DELETE FROM trades WHERE block_height = $1",
+            &[&PgNumericU64(block_height)],
+        )
+        .await
+        .expect("error rolling back trades");
     db_tx
         .execute(
             "DELETE FROM runes WHERE block_height = $1",
@@ -362,6 +480,176 @@ pub async fn pg_roll_back_block(block_height: u64, db_tx: &mut Transaction<'_>,
         )
         .await
         .expect("error rolling back runes");
+    pg_update_mint_statuses(block_height.saturating_sub(1), true, db_tx)
+        .await
+        .expect("error rolling back mint statuses");
+}
+
+/// Deletes every block above `block_height` from all tables. Rune number 0 (`UNCOMMON•GOODS`) is inserted by migrations
+/// instead of the indexer, so it is always kept.
+pub async fn pg_roll_back_to_block(
+    block_height: u64,
+    db_tx: &mut Transaction<'_>,
+    ctx: &Context,
+) -> Result<(), String> {
+    for (table, query) in [
+        (
+            "balance_changes",
+            "DELETE FROM balance_changes WHERE block_height > $1",
+        ),
+        (
+            "supply_changes",
+            "DELETE FROM supply_changes WHERE block_height > $1",
+        ),
+        ("ledger", "DELETE FROM ledger WHERE block_height > $1"),
+        ("trades", "DELETE FROM trades WHERE block_height > $1"),
+        (
+            "runes",
+            "DELETE FROM runes WHERE block_height > $1 AND number > 0",
+        ),
+    ] {
+        let rows = db_tx
+            .execute(query, &[&PgNumericU64(block_height)])
+            .await
+            .map_err(|e| format!("error rolling back {table}: {e}"))?;
+        try_info!(
+            ctx,
+            "Deleted {rows} {table} rows above block {block_height}"
+        );
+    }
+    pg_update_mint_statuses(block_height, true, db_tx).await
+}
+
+/// Recomputes `runes.mint_status` for mints included in the block after `block_height`. Only runes whose status may have
+/// changed at that height are visited: new runes, runes minted in `block_height` and runes crossing a window boundary. After
+/// a roll back `include_all` recomputes every rune with mint terms, since `cap_reached` and `window_closed` are no longer
+/// final. Mints are counted from `supply_changes.total_mints`, cenotaph mints included, which is the count the indexer
+/// loads to check `is_rune_mintable`.
+pub async fn pg_update_mint_statuses<T: GenericClient>(
+    block_height: u64,
+    include_all: bool,
+    client: &T,
+) -> Result<(), String> {
+    let next_block_height = PgNumericU64(block_height + 1);
+    let tip_block_height = PgNumericU64(block_height);
+    // Postgres can't infer the type of unused parameters, so the tip height is only sent when filtering candidates.
+    let (candidates, params): (&str, &[&(dyn ToSql + Sync)]) = if include_all {
+        ("", &[&next_block_height])
+    } else {
+        (
+            "AND (
+            r.mint_status IS NULL
+            OR (r.mint_status = 'not_yet_open' AND r.mint_start_height <= $1)
+            OR (r.mint_status IN ('not_yet_open', 'open') AND r.mint_end_height < $1)
+            OR (
+                r.mint_status = 'open'
+                AND r.id IN (SELECT rune_id FROM supply_changes WHERE block_height = $2)
+            )
+        )",
+            &[&next_block_height, &tip_block_height],
+        )
+    };
+    client
+        .execute(
+            &format!(
+                "WITH statuses AS (
+                    SELECT r.id,
+                        (CASE
+                            WHEN COALESCE(m.total_mints, 0) >= r.terms_cap THEN 'cap_reached'
+                            WHEN $1 > r.mint_end_height THEN 'window_closed'
+                            WHEN $1 < r.mint_start_height THEN 'not_yet_open'
+                            ELSE 'open'
+                        END)::mint_status AS mint_status
+                    FROM runes AS r
+                    LEFT JOIN LATERAL (
+                        SELECT total_mints FROM supply_changes
+                        WHERE rune_id = r.id
+                        ORDER BY block_height DESC
+                        LIMIT 1
+                    ) AS m ON TRUE
+                    WHERE r.terms_amount IS NOT NULL AND NOT r.cenotaph {candidates}
+                )
+                UPDATE runes SET mint_status = s.mint_status
+                FROM statuses AS s
+                WHERE runes.id = s.id AND runes.mint_status IS DISTINCT FROM s.mint_status"
+            ),
+            params,
+        )
+        .await
+        .map_err(|e| format!("error updating mint statuses: {e}"))?;
+    Ok(())
+}
+
+/// Size information about one of the indexer tables.
+pub struct DbTableStats {
+    pub table: String,
+    pub estimated_rows: i64,
+    pub total_bytes: i64,
+}
+
+/// Returns estimated row counts and on-disk sizes (including indexes) of the indexer tables.
+pub async fn pg_get_table_stats(client: &Client) -> Result<Vec<DbTableStats>, String> {
+    let rows = client
+        .query(
+            "SELECT relname::TEXT AS table, n_live_tup AS estimated_rows,
+                pg_total_relation_size(relid) AS total_bytes
+            FROM pg_stat_user_tables
+            WHERE relname IN ('runes', 'ledger', 'supply_changes', 'balance_changes', 'trades', 'pending_ledger')
+            ORDER BY relname",
+            &[],
+        )
+        .await
+        .map_err(|e| format!("error getting table stats: {e}"))?;
+    Ok(rows
+        .iter()
+        .map(|row| DbTableStats {
+            table: row.get("table"),
+            estimated_rows: row.get("estimated_rows"),
+            total_bytes: row.get("total_bytes"),
+        })
+        .collect())
+}
+
+/// Version of the latest migration embedded in this binary.
+pub fn latest_migration_version() -> i32 {
+    migrations::runner()
+        .get_migrations()
+        .iter()
+        .map(|m| m.version() as i32)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the version of the last migration applied to the database, if any.
+pub async fn pg_get_migration_version(client: &Client) -> Result<Option<i32>, String> {
+    let table_exists = pg_migrations_table_exists(client).await?;
+    if !table_exists {
+        return Ok(None);
+    }
+    client
+        .query_one("SELECT MAX(version) FROM pgmigrations", &[])
+        .await
+        .map(|row| row.get(0))
+        .map_err(|e| format!("unable to read migration version: {e}"))
+}
+
+/// Runs `VACUUM ANALYZE` on every indexer table. Cannot be called inside a transaction.
+#[cfg_attr(test, mutants::skip)]
+pub async fn pg_vacuum_analyze(client: &Client, ctx: &Context) -> Result<(), String> {
+    for table in [
+        "runes",
+        "ledger",
+        "supply_changes",
+        "balance_changes",
+        "trades",
+    ] {
+        try_info!(ctx, "Running VACUUM ANALYZE on {}", table);
+        client
+            .batch_execute(&format!("VACUUM ANALYZE {table}"))
+            .await
+            .map_err(|e| format!("error running VACUUM ANALYZE on {table}: {e}"))?;
+    }
+    Ok(())
 }
 
 pub async fn pg_get_max_rune_number<T: GenericClient>(client: &T, _ctx: &Context) -> u32 {
@@ -410,33 +698,92 @@ pub async fn pg_get_rune_by_id(
     Some(DbRune::from_pg_row(&row))
 }
 
-pub async fn pg_get_rune_total_mints(
-    id: &RuneId,
-    db_tx: &mut Transaction<'_>,
+/// Retrieves the latest total mint count for every rune that has supply changes.
+pub async fn pg_get_rune_total_mints<T: GenericClient>(
+    client: &T,
     ctx: &Context,
-) -> Option<u128> {
-    let row = match db_tx
-        .query_opt(
-            "SELECT total_mints FROM supply_changes WHERE rune_id = $1 ORDER BY block_height DESC LIMIT 1",
-            &[&id.to_string()],
+) -> HashMap<RuneId, u128> {
+    let rows = match client
+        .query(
+            "SELECT DISTINCT ON (rune_id) rune_id, total_mints
+            FROM supply_changes
+            ORDER BY rune_id, block_height DESC",
+            &[],
         )
         .await
     {
-        Ok(row) => row,
+        Ok(rows) => rows,
         Err(e) => {
             try_error!(
                 ctx,
-                "error retrieving rune minted total: {}",
+                "error retrieving rune minted totals: {}",
                 e.to_string()
             );
             process::exit(1);
         }
     };
-    let Some(row) = row else {
-        return None;
+    let mut results = HashMap::new();
+    for row in rows.iter() {
+        let rune_str: String = row.get("rune_id");
+        let total_mints: PgNumericU128 = row.get("total_mints");
+        results.insert(RuneId::from_str(rune_str.as_str()).unwrap(), total_mints.0);
+    }
+    results
+}
+
+/// Retrieves the runes etched and the number of mints per rune recorded in a block. Used to reconcile in-memory counters when
+/// the block is rolled back.
+pub async fn pg_get_block_rune_counts(
+    block_height: u64,
+    db_tx: &mut Transaction<'_>,
+    ctx: &Context,
+) -> (Vec<RuneId>, HashMap<RuneId, u128>) {
+    let etched_rows = match db_tx
+        .query(
+            "SELECT id FROM runes WHERE block_height = $1",
+            &[&PgNumericU64(block_height)],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            try_error!(ctx, "error retrieving block etchings: {}", e.to_string());
+            process::exit(1);
+        }
     };
-    let minted: PgNumericU128 = row.get("total_mints");
-    Some(minted.0)
+    let etched_runes = etched_rows
+        .iter()
+        .map(|row| RuneId::from_str(row.get::<_, String>("id").as_str()).unwrap())
+        .collect();
+    let mint_rows = match db_tx
+        .query(
+            "SELECT c.rune_id, c.total_mints - COALESCE(
+                (
+                    SELECT p.total_mints
+                    FROM supply_changes AS p
+                    WHERE p.rune_id = c.rune_id AND p.block_height < c.block_height
+                    ORDER BY p.block_height DESC
+                    LIMIT 1
+                ), 0) AS total_mints
+            FROM supply_changes AS c
+            WHERE c.block_height = $1",
+            &[&PgNumericU64(block_height)],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            try_error!(ctx, "error retrieving block mints: {}", e.to_string());
+            process::exit(1);
+        }
+    };
+    let mut block_mints = HashMap::new();
+    for row in mint_rows.iter() {
+        let rune_str: String = row.get("rune_id");
+        let total_mints: PgNumericU128 = row.get("total_mints");
+        block_mints.insert(RuneId::from_str(rune_str.as_str()).unwrap(), total_mints.0);
+    }
+    (etched_runes, block_mints)
 }
 
 /// Retrieves the rune balance for an array of transaction inputs represented by `(vin, tx_id, vout)` where `vin` is the index of
@@ -476,7 +823,7 @@ pub async fn pg_get_input_rune_balances(
         .query(
             format!(
                 "WITH inputs (index, tx_id, output) AS (VALUES {})
-                SELECT i.index, l.rune_id, l.address, l.amount
+                SELECT i.index, l.rune_id, l.address, l.amount, l.script_pubkey
                 FROM ledger AS l
                 INNER JOIN inputs AS i USING (tx_id, output)
                 WHERE l.operation = 'receive'",
@@ -506,6 +853,7 @@ pub async fn pg_get_input_rune_balances(
         let amount: PgNumericU128 = row.get("amount");
         let input_bal = InputRuneBalance {
             address,
+            script_pubkey: row.get("script_pubkey"),
             amount: amount.0,
         };
         if let Some(input) = results.get_mut(&key.0) {
@@ -540,6 +888,29 @@ pub async fn pg_test_client(run_migrations: bool, ctx: &Context) -> Client {
     client
 }
 
+/// Connects to the test database using a fresh `schema` as search path and runs migrations in it, so the test does not
+/// interfere with other tests running concurrently. Drop it with `pg_test_drop_schema`.
+#[cfg(test)]
+pub async fn pg_test_schema_client(schema: &str, ctx: &Context) -> Client {
+    let mut client = pg_test_client(false, ctx).await;
+    client
+        .batch_execute(&format!(
+            "DROP SCHEMA IF EXISTS {schema} CASCADE; CREATE SCHEMA {schema}; SET search_path TO {schema};"
+        ))
+        .await
+        .unwrap();
+    pg_run_migrations(&mut client, ctx).await;
+    client
+}
+
+#[cfg(test)]
+pub async fn pg_test_drop_schema(client: &Client, schema: &str) {
+    client
+        .batch_execute(&format!("DROP SCHEMA IF EXISTS {schema} CASCADE"))
+        .await
+        .unwrap();
+}
+
 #[cfg(test)]
 pub async fn pg_test_roll_back_migrations(pg_client: &mut Client, ctx: &Context) {
     match pg_client
@@ -1,3 +1,4 @@
+use bitcoin::Script;
 use ordinals::RuneId;
 use tokio_postgres::Row;
 
@@ -5,7 +6,7 @@ use crate::db::types::{
     pg_bigint_u32::PgBigIntU32, pg_numeric_u128::PgNumericU128, pg_numeric_u64::PgNumericU64,
 };
 
-use super::db_ledger_operation::DbLedgerOperation;
+use super::{db_ledger_operation::DbLedgerOperation, db_script_type::DbScriptType};
 
 /// A row in the `ledger` table.
 #[derive(Debug, Clone, Default)]
@@ -22,6 +23,13 @@ pub struct DbLedgerEntry {
     pub amount: Option<PgNumericU128>,
     pub operation: DbLedgerOperation,
     pub timestamp: PgBigIntU32,
+    /// Hex encoded `script_pubkey` of `output`, if runes can be held there.
+    pub script_pubkey: Option<String>,
+    pub script_type: Option<DbScriptType>,
+    /// Sats held by `output`.
+    pub output_value: Option<PgNumericU64>,
+    pub tx_fee: Option<PgNumericU64>,
+    pub tx_vsize: Option<PgBigIntU32>,
 }
 
 impl DbLedgerEntry {
@@ -52,9 +60,21 @@ impl DbLedgerEntry {
             amount: amount.map(|i| PgNumericU128(i)),
             operation,
             timestamp: PgBigIntU32(timestamp),
+            script_pubkey: None,
+            script_type: None,
+            output_value: None,
+            tx_fee: None,
+            tx_vsize: None,
         }
     }
 
+    /// Records the script of the output this entry refers to.
+    pub fn with_script(mut self, script: &Script) -> Self {
+        self.script_pubkey = Some(script.to_hex_string());
+        self.script_type = Some(DbScriptType::from_script(script));
+        self
+    }
+
     pub fn from_pg_row(row: &Row) -> Self {
         DbLedgerEntry {
             rune_id: row.get("rune_id"),
@@ -69,6 +89,11 @@ impl DbLedgerEntry {
             amount: row.get("amount"),
             operation: row.get("operation"),
             timestamp: row.get("timestamp"),
+            script_pubkey: row.get("script_pubkey"),
+            script_type: row.get("script_type"),
+            output_value: row.get("output_value"),
+            tx_fee: row.get("tx_fee"),
+            tx_vsize: row.get("tx_vsize"),
         }
     }
 }
@@ -0,0 +1,78 @@
+use std::{error::Error, fmt};
+
+use bytes::BytesMut;
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+/// A value from the `mint_status` enum type. Describes whether a mint included in the block after the indexed chain tip
+/// would be valid, and why not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DbMintStatus {
+    /// The height or offset window starts at a later block.
+    NotYetOpen,
+    Open,
+    /// Every mint allowed by `terms_cap` has been made.
+    CapReached,
+    /// The height or offset window has ended.
+    WindowClosed,
+}
+
+impl fmt::Display for DbMintStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl DbMintStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::NotYetOpen => "not_yet_open",
+            Self::Open => "open",
+            Self::CapReached => "cap_reached",
+            Self::WindowClosed => "window_closed",
+        }
+    }
+}
+
+impl std::str::FromStr for DbMintStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "not_yet_open" => Ok(DbMintStatus::NotYetOpen),
+            "open" => Ok(DbMintStatus::Open),
+            "cap_reached" => Ok(DbMintStatus::CapReached),
+            "window_closed" => Ok(DbMintStatus::WindowClosed),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql for DbMintStatus {
+    #[cfg_attr(test, mutants::skip)]
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.extend_from_slice(self.as_str().as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == "mint_status"
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for DbMintStatus {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<DbMintStatus, Box<dyn Error + Sync + Send>> {
+        let s = std::str::from_utf8(raw)?;
+        s.parse::<DbMintStatus>()
+            .map_err(|_| "failed to parse enum variant".into())
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == "mint_status"
+    }
+}
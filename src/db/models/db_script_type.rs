@@ -0,0 +1,171 @@
+use std::{error::Error, fmt};
+
+use bitcoin::{
+    blockdata::script::Instruction,
+    opcodes::{
+        all::{OP_CHECKMULTISIG, OP_PUSHNUM_1, OP_PUSHNUM_16},
+        All,
+    },
+    Script,
+};
+use bytes::BytesMut;
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+/// A value from the `script_type` enum type, the standard template (if any) matched by an output's `script_pubkey`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DbScriptType {
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    /// Bare `m`-of-`n` multisig.
+    Multisig,
+    /// Witness program of a version or length without a standard template.
+    WitnessUnknown,
+    NonStandard,
+}
+
+impl fmt::Display for DbScriptType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl DbScriptType {
+    pub fn from_script(script: &Script) -> Self {
+        if script.is_p2pk() {
+            Self::P2pk
+        } else if script.is_p2pkh() {
+            Self::P2pkh
+        } else if script.is_p2sh() {
+            Self::P2sh
+        } else if script.is_v0_p2wpkh() {
+            Self::P2wpkh
+        } else if script.is_v0_p2wsh() {
+            Self::P2wsh
+        } else if script.is_v1_p2tr() {
+            Self::P2tr
+        } else if script.is_witness_program() {
+            Self::WitnessUnknown
+        } else if is_multisig(script) {
+            Self::Multisig
+        } else {
+            Self::NonStandard
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::P2pk => "p2pk",
+            Self::P2pkh => "p2pkh",
+            Self::P2sh => "p2sh",
+            Self::P2wpkh => "p2wpkh",
+            Self::P2wsh => "p2wsh",
+            Self::P2tr => "p2tr",
+            Self::Multisig => "multisig",
+            Self::WitnessUnknown => "witness_unknown",
+            Self::NonStandard => "nonstandard",
+        }
+    }
+}
+
+/// Value of an `OP_PUSHNUM_1` to `OP_PUSHNUM_16` opcode.
+fn pushnum(opcode: &All) -> Option<usize> {
+    let value = opcode.to_u8();
+    (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8())
+        .contains(&value)
+        .then(|| (value - OP_PUSHNUM_1.to_u8() + 1) as usize)
+}
+
+/// Matches `<m> <key>... <n> OP_CHECKMULTISIG`. `bitcoin` only learns this template in later versions.
+fn is_multisig(script: &Script) -> bool {
+    let Ok(instructions) = script.instructions().collect::<Result<Vec<_>, _>>() else {
+        return false;
+    };
+    match instructions.as_slice() {
+        [Instruction::Op(required), keys @ .., Instruction::Op(total), Instruction::Op(check)] => {
+            *check == OP_CHECKMULTISIG
+                && keys
+                    .iter()
+                    .all(|key| matches!(key, Instruction::PushBytes(_)))
+                && match (pushnum(required), pushnum(total)) {
+                    (Some(required), Some(total)) => required <= total && total == keys.len(),
+                    _ => false,
+                }
+        }
+        _ => false,
+    }
+}
+
+impl std::str::FromStr for DbScriptType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p2pk" => Ok(DbScriptType::P2pk),
+            "p2pkh" => Ok(DbScriptType::P2pkh),
+            "p2sh" => Ok(DbScriptType::P2sh),
+            "p2wpkh" => Ok(DbScriptType::P2wpkh),
+            "p2wsh" => Ok(DbScriptType::P2wsh),
+            "p2tr" => Ok(DbScriptType::P2tr),
+            "multisig" => Ok(DbScriptType::Multisig),
+            "witness_unknown" => Ok(DbScriptType::WitnessUnknown),
+            "nonstandard" => Ok(DbScriptType::NonStandard),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql for DbScriptType {
+    #[cfg_attr(test, mutants::skip)]
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.extend_from_slice(self.as_str().as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == "script_type"
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for DbScriptType {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<DbScriptType, Box<dyn Error + Sync + Send>> {
+        let s = std::str::from_utf8(raw)?;
+        s.parse::<DbScriptType>()
+            .map_err(|_| "failed to parse enum variant".into())
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == "script_type"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::ScriptBuf;
+    use test_case::test_case;
+
+    use super::DbScriptType;
+
+    #[test_case("210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac" => DbScriptType::P2pk; "p2pk")]
+    #[test_case("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac" => DbScriptType::P2pkh; "p2pkh")]
+    #[test_case("a914748284390f9e263a4b766a75d0633c50426eb87587" => DbScriptType::P2sh; "p2sh")]
+    #[test_case("0014751e76e8199196d454941c45d1b3a323f1433bd6" => DbScriptType::P2wpkh; "p2wpkh")]
+    #[test_case("00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262" => DbScriptType::P2wsh; "p2wsh")]
+    #[test_case("5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c" => DbScriptType::P2tr; "p2tr")]
+    #[test_case("51210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f817982103111111111111111111111111111111111111111111111111111111111111111152ae" => DbScriptType::Multisig; "multisig")]
+    #[test_case("5202751e" => DbScriptType::WitnessUnknown; "witness v2")]
+    #[test_case("51" => DbScriptType::NonStandard; "op true")]
+    #[test_case("52ae" => DbScriptType::NonStandard; "multisig without keys")]
+    fn classifies_scripts(script: &str) -> DbScriptType {
+        DbScriptType::from_script(&ScriptBuf::from_hex(script).unwrap())
+    }
+}
@@ -0,0 +1,22 @@
+use crate::db::types::{
+    pg_bigint_u32::PgBigIntU32, pg_numeric_u128::PgNumericU128, pg_numeric_u64::PgNumericU64,
+};
+
+/// A row in the `trades` table, a likely sale of runes listed through a PSBT marketplace.
+#[derive(Debug, Clone, Default)]
+pub struct DbTrade {
+    pub rune_id: String,
+    pub block_hash: String,
+    pub block_height: PgNumericU64,
+    pub tx_index: PgBigIntU32,
+    pub tx_id: String,
+    pub seller_address: String,
+    pub buyer_address: String,
+    /// Runes received by the buyer, in base units.
+    pub amount: PgNumericU128,
+    /// Sats paid to the seller.
+    pub price: PgNumericU64,
+    /// Sats paid per whole rune, adjusted by the rune's divisibility.
+    pub price_per_unit: f64,
+    pub timestamp: PgBigIntU32,
+}
@@ -1,5 +1,8 @@
 pub mod db_balance_change;
 pub mod db_ledger_entry;
 pub mod db_ledger_operation;
+pub mod db_mint_status;
 pub mod db_rune;
+pub mod db_script_type;
 pub mod db_supply_change;
+pub mod db_trade;
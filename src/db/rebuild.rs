@@ -0,0 +1,271 @@
+use std::sync::{Arc, Mutex};
+
+use chainhook_sdk::utils::Context;
+use tokio::task::JoinSet;
+use tokio_postgres::{Client, GenericClient, Transaction};
+
+use crate::{config::Config, try_info};
+
+use super::{
+    consistency::{expected_balance_changes, expected_supply_changes},
+    pg_connect,
+};
+
+/// Number of runes whose derived rows are rebuilt in a single transaction.
+const RUNES_PER_BATCH: usize = 500;
+
+/// A cumulative table that can be computed from `ledger` alone. Every new aggregate table must be listed in
+/// `DERIVED_TABLES` so it gets rebuilt along with the others.
+pub struct DerivedTable {
+    pub name: &'static str,
+    columns: &'static str,
+    order_by: &'static str,
+    expected: fn(&str) -> String,
+}
+
+pub const DERIVED_TABLES: [DerivedTable; 2] = [
+    DerivedTable {
+        name: "supply_changes",
+        columns:
+            "rune_id, block_height, minted, total_mints, burned, total_burns, total_operations",
+        order_by: "block_height, rune_id",
+        expected: expected_supply_changes,
+    },
+    DerivedTable {
+        name: "balance_changes",
+        columns: "rune_id, block_height, address, balance, total_operations",
+        order_by: "block_height, rune_id, address",
+        expected: expected_balance_changes,
+    },
+];
+
+impl DerivedTable {
+    /// Table the rows are rebuilt into before they replace the ones in `name`.
+    fn shadow_name(&self) -> String {
+        format!("{}_rebuild", self.name)
+    }
+}
+
+/// Creates an empty shadow table for each of `DERIVED_TABLES`, dropping any left behind by a failed rebuild.
+pub async fn pg_create_shadow_tables<T: GenericClient>(client: &T) -> Result<(), String> {
+    for table in DERIVED_TABLES.iter() {
+        client
+            .batch_execute(&format!(
+                "DROP TABLE IF EXISTS {shadow}; CREATE UNLOGGED TABLE {shadow} (LIKE {name} INCLUDING DEFAULTS)",
+                shadow = table.shadow_name(),
+                name = table.name
+            ))
+            .await
+            .map_err(|e| format!("error creating {}: {e}", table.shadow_name()))?;
+    }
+    Ok(())
+}
+
+/// Replaces the rows of every derived table with the ones rebuilt into its shadow table, then drops the shadow tables. Must
+/// run in a single transaction so readers never see the tables empty or partially rebuilt.
+pub async fn pg_swap_in_shadow_tables(db_tx: &mut Transaction<'_>) -> Result<(), String> {
+    for table in DERIVED_TABLES.iter() {
+        db_tx
+            .batch_execute(&format!(
+                "TRUNCATE {name};
+                INSERT INTO {name} ({columns}) SELECT {columns} FROM {shadow} ORDER BY {order_by};
+                DROP TABLE {shadow};",
+                name = table.name,
+                columns = table.columns,
+                shadow = table.shadow_name(),
+                order_by = table.order_by
+            ))
+            .await
+            .map_err(|e| format!("error replacing {}: {e}", table.name))?;
+    }
+    Ok(())
+}
+
+/// Returns every rune with at least one ledger entry, in a stable order.
+pub async fn pg_get_ledger_rune_ids<T: GenericClient>(client: &T) -> Result<Vec<String>, String> {
+    let rows = client
+        .query("SELECT DISTINCT rune_id FROM ledger ORDER BY rune_id", &[])
+        .await
+        .map_err(|e| format!("error reading ledger rune ids: {e}"))?;
+    Ok(rows.iter().map(|row| row.get("rune_id")).collect())
+}
+
+/// Inserts the derived rows of `rune_ids` computed from their whole ledger history into the shadow tables, in height order.
+/// Rows of these runes must not exist there yet. Returns the number of rows written for each table of `DERIVED_TABLES`.
+pub async fn pg_rebuild_derived_tables_for_runes(
+    rune_ids: &[String],
+    db_tx: &mut Transaction<'_>,
+) -> Result<Vec<u64>, String> {
+    let mut written = vec![];
+    for table in DERIVED_TABLES.iter() {
+        let rows = db_tx
+            .execute(
+                &format!(
+                    "WITH {}
+                    INSERT INTO {} ({})
+                    SELECT {} FROM expected ORDER BY {}",
+                    (table.expected)("rune_id = ANY($1)"),
+                    table.shadow_name(),
+                    table.columns,
+                    table.columns,
+                    table.order_by
+                ),
+                &[&rune_ids],
+            )
+            .await
+            .map_err(|e| format!("error rebuilding {}: {e}", table.name))?;
+        written.push(rows);
+    }
+    Ok(written)
+}
+
+/// Rebuilds every derived table from `ledger`, spreading runes across `jobs` postgres connections. Rows are written to
+/// shadow tables first and only replace the current ones once every rune is rebuilt, in a single transaction. The indexer
+/// must be stopped while this runs. If it fails midway the derived tables are left untouched and the command should simply
+/// be run again.
+#[cfg_attr(test, mutants::skip)]
+pub async fn rebuild_derived_tables(
+    config: &Config,
+    jobs: usize,
+    ctx: &Context,
+) -> Result<Vec<u64>, String> {
+    let mut pg_client = pg_connect(config, false, ctx).await;
+    let rune_ids = pg_get_ledger_rune_ids(&pg_client).await?;
+    try_info!(
+        ctx,
+        "Rebuilding {} from the ledger entries of {} runes with {} jobs",
+        DERIVED_TABLES.map(|t| t.name).join(", "),
+        rune_ids.len(),
+        jobs
+    );
+    pg_create_shadow_tables(&pg_client).await?;
+
+    let batches: Vec<Vec<String>> = rune_ids
+        .chunks(RUNES_PER_BATCH)
+        .rev()
+        .map(|c| c.to_vec())
+        .collect();
+    let batch_count = batches.len();
+    let queue = Arc::new(Mutex::new(batches));
+    let mut workers = JoinSet::new();
+    for _ in 0..jobs.max(1) {
+        let mut client = pg_connect(config, false, ctx).await;
+        let queue = queue.clone();
+        let ctx = ctx.clone();
+        workers.spawn(async move {
+            let mut written = vec![0; DERIVED_TABLES.len()];
+            loop {
+                let (batch, remaining) = {
+                    let mut queue = queue.lock().unwrap();
+                    (queue.pop(), queue.len())
+                };
+                let Some(batch) = batch else {
+                    return Ok::<_, String>(written);
+                };
+                let rows = rebuild_batch(&mut client, &batch).await?;
+                written.iter_mut().zip(rows).for_each(|(w, r)| *w += r);
+                try_info!(
+                    ctx,
+                    "Rebuilt derived tables for {} runes, {} of {} batches left",
+                    batch.len(),
+                    remaining,
+                    batch_count
+                );
+            }
+        });
+    }
+
+    let mut written = vec![0; DERIVED_TABLES.len()];
+    while let Some(result) = workers.join_next().await {
+        let rows: Vec<u64> = result.map_err(|e| format!("rebuild job failed: {e}"))??;
+        written.iter_mut().zip(rows).for_each(|(w, r)| *w += r);
+    }
+
+    let mut db_tx = pg_client
+        .transaction()
+        .await
+        .map_err(|e| format!("unable to begin transaction: {e}"))?;
+    pg_swap_in_shadow_tables(&mut db_tx).await?;
+    db_tx
+        .commit()
+        .await
+        .map_err(|e| format!("unable to commit rebuilt tables: {e}"))?;
+    Ok(written)
+}
+
+async fn rebuild_batch(client: &mut Client, rune_ids: &[String]) -> Result<Vec<u64>, String> {
+    let mut db_tx = client
+        .transaction()
+        .await
+        .map_err(|e| format!("unable to begin transaction: {e}"))?;
+    let written = pg_rebuild_derived_tables_for_runes(rune_ids, &mut db_tx).await?;
+    db_tx
+        .commit()
+        .await
+        .map_err(|e| format!("unable to commit rebuilt rows: {e}"))?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use chainhook_sdk::utils::Context;
+
+    use crate::db::{
+        consistency::pg_check_derived_tables, pg_test_drop_schema, pg_test_schema_client,
+    };
+
+    use super::{
+        pg_create_shadow_tables, pg_get_ledger_rune_ids, pg_rebuild_derived_tables_for_runes,
+        pg_swap_in_shadow_tables,
+    };
+
+    const SCHEMA: &str = "test_rebuild";
+
+    #[tokio::test]
+    async fn rebuilds_derived_tables_per_rune() {
+        let ctx = Context::empty();
+        let mut client = pg_test_schema_client(SCHEMA, &ctx).await;
+        client
+            .batch_execute(
+                "INSERT INTO ledger (rune_id, block_hash, block_height, tx_index, event_index, tx_id, output, address,
+                    amount, operation, timestamp)
+                VALUES
+                    ('1:0', 'h1', 840001, 0, 0, 't1', NULL, NULL, 100, 'mint', 0),
+                    ('1:0', 'h1', 840001, 0, 1, 't1', 0, 'a', 100, 'receive', 0),
+                    ('2:1', 'h2', 840002, 0, 0, 't2', NULL, NULL, 5, 'mint', 0),
+                    ('2:1', 'h2', 840002, 0, 1, 't2', 0, 'b', 5, 'receive', 0),
+                    ('1:0', 'h2', 840002, 1, 0, 't3', NULL, 'a', 40, 'send', 0),
+                    ('1:0', 'h2', 840002, 1, 1, 't3', 0, 'b', 40, 'receive', 0);
+                INSERT INTO supply_changes VALUES ('1:0', 840001, 1, 1, 0, 0, 1);
+                INSERT INTO balance_changes VALUES ('3:0', 840001, 'c', -5, 1);",
+            )
+            .await
+            .unwrap();
+
+        let rune_ids = pg_get_ledger_rune_ids(&client).await.unwrap();
+        assert_eq!(rune_ids, vec!["1:0".to_string(), "2:1".to_string()]);
+        pg_create_shadow_tables(&client).await.unwrap();
+        let mut written = vec![];
+        for rune_id in rune_ids.iter() {
+            let mut db_tx = client.transaction().await.unwrap();
+            written.push(
+                pg_rebuild_derived_tables_for_runes(std::slice::from_ref(rune_id), &mut db_tx)
+                    .await
+                    .unwrap(),
+            );
+            db_tx.commit().await.unwrap();
+        }
+        assert_eq!(written, vec![vec![2, 3], vec![1, 1]]);
+        // Nothing changes until the shadow tables are swapped in.
+        let report = pg_check_derived_tables(&client, 0, 900000).await.unwrap();
+        assert!(!report.is_consistent());
+
+        let mut db_tx = client.transaction().await.unwrap();
+        pg_swap_in_shadow_tables(&mut db_tx).await.unwrap();
+        db_tx.commit().await.unwrap();
+
+        let report = pg_check_derived_tables(&client, 0, 900000).await.unwrap();
+        pg_test_drop_schema(&client, SCHEMA).await;
+        assert!(report.is_consistent());
+    }
+}
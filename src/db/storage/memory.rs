@@ -0,0 +1,370 @@
+use std::{collections::BTreeMap, collections::HashMap, str::FromStr};
+
+use chainhook_sdk::utils::Context;
+use ordinals::RuneId;
+
+use crate::{
+    db::{
+        cache::input_rune_balance::InputRuneBalance,
+        models::{
+            db_balance_change::DbBalanceChange, db_ledger_entry::DbLedgerEntry,
+            db_ledger_operation::DbLedgerOperation, db_mint_status::DbMintStatus, db_rune::DbRune,
+            db_supply_change::DbSupplyChange, db_trade::DbTrade,
+        },
+        types::{
+            pg_bigint_u32::PgBigIntU32, pg_numeric_u128::PgNumericU128,
+            pg_numeric_u64::PgNumericU64, pg_smallint_u8::PgSmallIntU8,
+        },
+    },
+    mints::mint_status,
+};
+
+use super::{Storage, StorageTransaction};
+
+/// Contents of every table. Supply and balance changes are keyed the same way as their primary keys so the latest row of a
+/// rune or address is the last one in its key range.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTables {
+    pub runes: Vec<DbRune>,
+    pub supply_changes: BTreeMap<(String, u64), DbSupplyChange>,
+    pub balance_changes: BTreeMap<(String, String, u64), DbBalanceChange>,
+    pub ledger: Vec<DbLedgerEntry>,
+    pub trades: Vec<DbTrade>,
+    /// `runes.mint_status`, keyed by rune id. Runes without a status are absent.
+    pub mint_statuses: BTreeMap<String, DbMintStatus>,
+}
+
+impl MemoryTables {
+    fn latest_supply_change(&self, rune_id: &str) -> Option<&DbSupplyChange> {
+        self.supply_changes
+            .range((rune_id.to_string(), 0)..=(rune_id.to_string(), u64::MAX))
+            .next_back()
+            .map(|(_, row)| row)
+    }
+
+    fn latest_balance_change(&self, rune_id: &str, address: &str) -> Option<&DbBalanceChange> {
+        self.balance_changes
+            .range(
+                (rune_id.to_string(), address.to_string(), 0)
+                    ..=(rune_id.to_string(), address.to_string(), u64::MAX),
+            )
+            .next_back()
+            .map(|(_, row)| row)
+    }
+
+    /// Same as `pg_update_mint_statuses`, minus the candidate filter: every status that isn't final is recomputed.
+    fn update_mint_statuses(&mut self, block_height: u64, include_all: bool) {
+        for rune in self.runes.iter() {
+            let current = self.mint_statuses.get(&rune.id).copied();
+            if !include_all
+                && matches!(
+                    current,
+                    Some(DbMintStatus::CapReached | DbMintStatus::WindowClosed)
+                )
+            {
+                continue;
+            }
+            let total_mints = self
+                .latest_supply_change(&rune.id)
+                .map_or(0, |row| row.total_mints.0);
+            match mint_status(rune, total_mints, block_height + 1) {
+                Some(status) => self.mint_statuses.insert(rune.id.clone(), status),
+                None => self.mint_statuses.remove(&rune.id),
+            };
+        }
+        let runes = &self.runes;
+        self.mint_statuses
+            .retain(|id, _| runes.iter().any(|rune| &rune.id == id));
+    }
+}
+
+/// Storage backed by plain collections, used to run the indexer in unit tests without any external service. Behaves like the
+/// Postgres functions in `db/mod.rs`, except that balances going negative are reported as a panic instead of being stored.
+pub struct MemoryStorage {
+    pub tables: MemoryTables,
+}
+
+impl MemoryStorage {
+    /// Creates an empty index holding only `UNCOMMON•GOODS`, which is inserted by migrations in the other backends.
+    pub fn new() -> Self {
+        MemoryStorage {
+            tables: MemoryTables {
+                runes: vec![DbRune {
+                    id: "1:0".to_string(),
+                    number: PgBigIntU32(0),
+                    name: "UNCOMMONGOODS".to_string(),
+                    spaced_name: "UNCOMMON•GOODS".to_string(),
+                    block_hash: "0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5"
+                        .to_string(),
+                    block_height: PgNumericU64(840000),
+                    tx_index: PgBigIntU32(0),
+                    tx_id: "".to_string(),
+                    divisibility: PgSmallIntU8(0),
+                    premine: PgNumericU128(0),
+                    symbol: "⧉".to_string(),
+                    terms_amount: Some(PgNumericU128(1)),
+                    terms_cap: Some(PgNumericU128(u128::MAX)),
+                    terms_height_start: Some(PgNumericU64(840000)),
+                    terms_height_end: Some(PgNumericU64(1050000)),
+                    terms_offset_start: None,
+                    terms_offset_end: None,
+                    turbo: true,
+                    cenotaph: false,
+                    timestamp: PgBigIntU32(0),
+                }],
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Storage for MemoryStorage {
+    type Transaction<'a> = MemoryTransaction<'a>;
+
+    async fn begin(&mut self) -> Result<MemoryTransaction<'_>, String> {
+        Ok(MemoryTransaction {
+            tables: self.tables.clone(),
+            committed: &mut self.tables,
+        })
+    }
+
+    async fn get_max_rune_number(&mut self, _ctx: &Context) -> u32 {
+        self.tables
+            .runes
+            .iter()
+            .map(|rune| rune.number.0)
+            .max()
+            .unwrap_or(0)
+    }
+
+    async fn get_rune_total_mints(&mut self, _ctx: &Context) -> HashMap<RuneId, u128> {
+        let mut results = HashMap::new();
+        // Keys are sorted by height within each rune, so the last value inserted for a rune is its latest.
+        for ((rune_id, _), row) in self.tables.supply_changes.iter() {
+            results.insert(RuneId::from_str(rune_id).unwrap(), row.total_mints.0);
+        }
+        results
+    }
+
+    async fn get_block_height(&mut self, _ctx: &Context) -> Option<u64> {
+        self.tables
+            .ledger
+            .iter()
+            .map(|entry| entry.block_height.0)
+            .max()
+    }
+}
+
+/// Works on a copy of the tables, which replaces the storage contents on commit. Dropping it without committing discards every
+/// change, like a rolled back database transaction.
+pub struct MemoryTransaction<'a> {
+    tables: MemoryTables,
+    committed: &'a mut MemoryTables,
+}
+
+impl StorageTransaction for MemoryTransaction<'_> {
+    async fn insert_runes(&mut self, rows: &[DbRune], _ctx: &Context) {
+        for row in rows.iter() {
+            if self.tables.runes.iter().all(|rune| rune.name != row.name) {
+                self.tables.runes.push(row.clone());
+            }
+        }
+    }
+
+    async fn insert_supply_changes(&mut self, rows: &[DbSupplyChange], _ctx: &Context) {
+        for row in rows.iter() {
+            let previous = self
+                .tables
+                .latest_supply_change(&row.rune_id)
+                .cloned()
+                .unwrap_or_default();
+            self.tables.supply_changes.insert(
+                (row.rune_id.clone(), row.block_height.0),
+                DbSupplyChange {
+                    rune_id: row.rune_id.clone(),
+                    block_height: row.block_height,
+                    minted: PgNumericU128(previous.minted.0 + row.minted.0),
+                    total_mints: PgNumericU128(previous.total_mints.0 + row.total_mints.0),
+                    burned: PgNumericU128(previous.burned.0 + row.burned.0),
+                    total_burns: PgNumericU128(previous.total_burns.0 + row.total_burns.0),
+                    total_operations: PgNumericU128(
+                        previous.total_operations.0 + row.total_operations.0,
+                    ),
+                },
+            );
+        }
+    }
+
+    async fn insert_balance_changes(
+        &mut self,
+        rows: &[DbBalanceChange],
+        increase: bool,
+        _ctx: &Context,
+    ) {
+        for row in rows.iter() {
+            let previous = self
+                .tables
+                .latest_balance_change(&row.rune_id, &row.address)
+                .cloned()
+                .unwrap_or_default();
+            let balance = if increase {
+                previous.balance.0 + row.balance.0
+            } else {
+                previous
+                    .balance
+                    .0
+                    .checked_sub(row.balance.0)
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "balance of {} for rune {} would become negative at block {}",
+                            row.address, row.rune_id, row.block_height.0
+                        )
+                    })
+            };
+            self.tables.balance_changes.insert(
+                (row.rune_id.clone(), row.address.clone(), row.block_height.0),
+                DbBalanceChange {
+                    rune_id: row.rune_id.clone(),
+                    block_height: row.block_height,
+                    address: row.address.clone(),
+                    balance: PgNumericU128(balance),
+                    total_operations: PgBigIntU32(
+                        previous.total_operations.0 + row.total_operations.0,
+                    ),
+                },
+            );
+        }
+    }
+
+    async fn insert_ledger_entries(&mut self, rows: &[DbLedgerEntry], _ctx: &Context) {
+        self.tables.ledger.extend(rows.iter().cloned());
+    }
+
+    async fn insert_trades(&mut self, rows: &[DbTrade], _ctx: &Context) {
+        self.tables.trades.extend(rows.iter().cloned());
+    }
+
+    async fn roll_back_block(&mut self, block_height: u64, _ctx: &Context) {
+        let tables = &mut self.tables;
+        tables
+            .balance_changes
+            .retain(|_, row| row.block_height.0 != block_height);
+        tables
+            .supply_changes
+            .retain(|_, row| row.block_height.0 != block_height);
+        tables
+            .ledger
+            .retain(|row| row.block_height.0 != block_height);
+        tables
+            .trades
+            .retain(|row| row.block_height.0 != block_height);
+        tables
+            .runes
+            .retain(|row| row.block_height.0 != block_height);
+        tables.update_mint_statuses(block_height.saturating_sub(1), true);
+    }
+
+    async fn roll_back_to_block(
+        &mut self,
+        block_height: u64,
+        _ctx: &Context,
+    ) -> Result<(), String> {
+        let tables = &mut self.tables;
+        tables
+            .balance_changes
+            .retain(|_, row| row.block_height.0 <= block_height);
+        tables
+            .supply_changes
+            .retain(|_, row| row.block_height.0 <= block_height);
+        tables
+            .ledger
+            .retain(|row| row.block_height.0 <= block_height);
+        tables
+            .trades
+            .retain(|row| row.block_height.0 <= block_height);
+        tables
+            .runes
+            .retain(|row| row.block_height.0 <= block_height || row.number.0 == 0);
+        tables.update_mint_statuses(block_height, true);
+        Ok(())
+    }
+
+    async fn update_mint_statuses(&mut self, block_height: u64, _ctx: &Context) {
+        self.tables.update_mint_statuses(block_height, false);
+    }
+
+    async fn get_rune_by_id(&mut self, id: &RuneId, _ctx: &Context) -> Option<DbRune> {
+        let id = id.to_string();
+        self.tables.runes.iter().find(|rune| rune.id == id).cloned()
+    }
+
+    async fn get_block_rune_counts(
+        &mut self,
+        block_height: u64,
+        _ctx: &Context,
+    ) -> (Vec<RuneId>, HashMap<RuneId, u128>) {
+        let etched_runes = self
+            .tables
+            .runes
+            .iter()
+            .filter(|rune| rune.block_height.0 == block_height)
+            .map(|rune| RuneId::from_str(&rune.id).unwrap())
+            .collect();
+        let mut block_mints = HashMap::new();
+        for ((rune_id, height), row) in self.tables.supply_changes.iter() {
+            if *height != block_height {
+                continue;
+            }
+            let previous = self
+                .tables
+                .supply_changes
+                .range((rune_id.clone(), 0)..(rune_id.clone(), block_height))
+                .next_back()
+                .map(|(_, previous)| previous.total_mints.0)
+                .unwrap_or(0);
+            block_mints.insert(
+                RuneId::from_str(rune_id).unwrap(),
+                row.total_mints.0 - previous,
+            );
+        }
+        (etched_runes, block_mints)
+    }
+
+    async fn get_input_rune_balances(
+        &mut self,
+        outputs: Vec<(u32, String, u32)>,
+        _ctx: &Context,
+    ) -> HashMap<u32, HashMap<RuneId, Vec<InputRuneBalance>>> {
+        let mut results: HashMap<u32, HashMap<RuneId, Vec<InputRuneBalance>>> = HashMap::new();
+        for (input_index, tx_id, output) in outputs.iter() {
+            for entry in self.tables.ledger.iter().filter(|entry| {
+                entry.operation == DbLedgerOperation::Receive
+                    && entry.tx_id == *tx_id
+                    && entry.output.as_ref().map(|o| o.0) == Some(*output)
+            }) {
+                results
+                    .entry(*input_index)
+                    .or_default()
+                    .entry(RuneId::from_str(&entry.rune_id).unwrap())
+                    .or_default()
+                    .push(InputRuneBalance {
+                        address: entry.address.clone(),
+                        script_pubkey: entry.script_pubkey.clone(),
+                        amount: entry.amount.as_ref().map(|a| a.0).unwrap_or(0),
+                    });
+            }
+        }
+        results
+    }
+
+    async fn commit(self) -> Result<(), String> {
+        *self.committed = self.tables;
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! Persistence used by the indexer. `IndexCache`, `DbCache` and `index_block` only talk to the database through these traits,
+//! so blocks can be indexed into any implementation: Postgres for production deployments and SQLite for single-node setups,
+//! development and CI.
+//!
+//! The indexer drives storage from a single task at a time, so the futures returned by these traits are not required to be
+//! `Send`.
+#![allow(async_fn_in_trait)]
+
+use std::collections::HashMap;
+
+use chainhook_sdk::utils::Context;
+use ordinals::RuneId;
+
+use crate::config::Config;
+
+use super::{
+    cache::input_rune_balance::InputRuneBalance,
+    models::{
+        db_balance_change::DbBalanceChange, db_ledger_entry::DbLedgerEntry, db_rune::DbRune,
+        db_supply_change::DbSupplyChange, db_trade::DbTrade,
+    },
+};
+
+#[cfg(test)]
+pub mod memory;
+pub mod postgres;
+pub mod sqlite;
+
+/// A connection to the index database.
+pub trait Storage {
+    type Transaction<'a>: StorageTransaction
+    where
+        Self: 'a;
+
+    /// Starts a transaction. Rows written through it are only visible to other connections once it is committed.
+    async fn begin(&mut self) -> Result<Self::Transaction<'_>, String>;
+
+    /// Returns the highest rune number assigned so far.
+    async fn get_max_rune_number(&mut self, ctx: &Context) -> u32;
+
+    /// Returns the latest total mint count for every rune that has supply changes.
+    async fn get_rune_total_mints(&mut self, ctx: &Context) -> HashMap<RuneId, u128>;
+
+    /// Returns the height of the highest block with ledger activity.
+    async fn get_block_height(&mut self, ctx: &Context) -> Option<u64>;
+}
+
+/// A storage backend that can be opened from the indexer config.
+pub trait ConnectStorage: Storage + Sized {
+    /// Opens a new connection, creating or migrating the schema first if `run_migrations` is set.
+    async fn connect(config: &Config, run_migrations: bool, ctx: &Context) -> Self;
+}
+
+/// Reads and writes performed while indexing or rolling back a block. Errors are fatal, same as with the Postgres functions
+/// in `db/mod.rs`: the indexer cannot continue with a partially written block.
+pub trait StorageTransaction {
+    async fn insert_runes(&mut self, rows: &[DbRune], ctx: &Context);
+
+    /// Adds each change to the latest cumulative supply of its rune.
+    async fn insert_supply_changes(&mut self, rows: &[DbSupplyChange], ctx: &Context);
+
+    /// Adds (if `increase` is set) or subtracts each change to the latest cumulative balance of its address.
+    async fn insert_balance_changes(
+        &mut self,
+        rows: &[DbBalanceChange],
+        increase: bool,
+        ctx: &Context,
+    );
+
+    async fn insert_ledger_entries(&mut self, rows: &[DbLedgerEntry], ctx: &Context);
+
+    async fn insert_trades(&mut self, rows: &[DbTrade], ctx: &Context);
+
+    /// Deletes every row produced by the block at `block_height`.
+    async fn roll_back_block(&mut self, block_height: u64, ctx: &Context);
+
+    /// Deletes every row produced by blocks above `block_height`, keeping the runes inserted by migrations.
+    async fn roll_back_to_block(&mut self, block_height: u64, ctx: &Context) -> Result<(), String>;
+
+    /// Updates the mint status of runes for mints included in the block after `block_height`. Called once every block has
+    /// been written; roll backs recompute statuses on their own.
+    async fn update_mint_statuses(&mut self, block_height: u64, ctx: &Context);
+
+    async fn get_rune_by_id(&mut self, id: &RuneId, ctx: &Context) -> Option<DbRune>;
+
+    /// Returns the runes etched and the number of mints per rune recorded in a block.
+    async fn get_block_rune_counts(
+        &mut self,
+        block_height: u64,
+        ctx: &Context,
+    ) -> (Vec<RuneId>, HashMap<RuneId, u128>);
+
+    /// Returns the rune balances held by each `(vin, tx_id, vout)` input, keyed by `vin`.
+    async fn get_input_rune_balances(
+        &mut self,
+        outputs: Vec<(u32, String, u32)>,
+        ctx: &Context,
+    ) -> HashMap<u32, HashMap<RuneId, Vec<InputRuneBalance>>>;
+
+    async fn commit(self) -> Result<(), String>;
+}
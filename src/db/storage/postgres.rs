@@ -0,0 +1,120 @@
+use std::{collections::HashMap, process};
+
+use chainhook_sdk::utils::Context;
+use ordinals::RuneId;
+use tokio_postgres::{Client, Transaction};
+
+use crate::{
+    config::Config,
+    db::{
+        cache::input_rune_balance::InputRuneBalance,
+        models::{
+            db_balance_change::DbBalanceChange, db_ledger_entry::DbLedgerEntry, db_rune::DbRune,
+            db_supply_change::DbSupplyChange, db_trade::DbTrade,
+        },
+        pg_connect, pg_get_block_height, pg_get_block_rune_counts, pg_get_input_rune_balances,
+        pg_get_max_rune_number, pg_get_rune_by_id, pg_get_rune_total_mints,
+        pg_insert_balance_changes, pg_insert_ledger_entries, pg_insert_runes,
+        pg_insert_supply_changes, pg_insert_trades, pg_roll_back_block, pg_roll_back_to_block,
+        pg_update_mint_statuses,
+    },
+    try_error,
+};
+
+use super::{ConnectStorage, Storage, StorageTransaction};
+
+impl Storage for Client {
+    type Transaction<'a> = Transaction<'a>;
+
+    async fn begin(&mut self) -> Result<Transaction<'_>, String> {
+        self.transaction()
+            .await
+            .map_err(|e| format!("unable to begin pg transaction: {e}"))
+    }
+
+    async fn get_max_rune_number(&mut self, ctx: &Context) -> u32 {
+        pg_get_max_rune_number(self, ctx).await
+    }
+
+    async fn get_rune_total_mints(&mut self, ctx: &Context) -> HashMap<RuneId, u128> {
+        pg_get_rune_total_mints(self, ctx).await
+    }
+
+    async fn get_block_height(&mut self, ctx: &Context) -> Option<u64> {
+        pg_get_block_height(self, ctx).await
+    }
+}
+
+impl ConnectStorage for Client {
+    async fn connect(config: &Config, run_migrations: bool, ctx: &Context) -> Self {
+        pg_connect(config, run_migrations, ctx).await
+    }
+}
+
+impl StorageTransaction for Transaction<'_> {
+    async fn insert_runes(&mut self, rows: &[DbRune], ctx: &Context) {
+        let _ = pg_insert_runes(rows, self, ctx).await;
+    }
+
+    async fn insert_supply_changes(&mut self, rows: &[DbSupplyChange], ctx: &Context) {
+        let _ = pg_insert_supply_changes(rows, self, ctx).await;
+    }
+
+    async fn insert_balance_changes(
+        &mut self,
+        rows: &[DbBalanceChange],
+        increase: bool,
+        ctx: &Context,
+    ) {
+        let _ = pg_insert_balance_changes(rows, increase, self, ctx).await;
+    }
+
+    async fn insert_ledger_entries(&mut self, rows: &[DbLedgerEntry], ctx: &Context) {
+        let _ = pg_insert_ledger_entries(rows, self, ctx).await;
+    }
+
+    async fn insert_trades(&mut self, rows: &[DbTrade], ctx: &Context) {
+        let _ = pg_insert_trades(rows, self, ctx).await;
+    }
+
+    async fn roll_back_block(&mut self, block_height: u64, ctx: &Context) {
+        pg_roll_back_block(block_height, self, ctx).await
+    }
+
+    async fn roll_back_to_block(&mut self, block_height: u64, ctx: &Context) -> Result<(), String> {
+        pg_roll_back_to_block(block_height, self, ctx).await
+    }
+
+    async fn update_mint_statuses(&mut self, block_height: u64, ctx: &Context) {
+        if let Err(e) = pg_update_mint_statuses(block_height, false, self).await {
+            try_error!(ctx, "{}", e);
+            process::exit(1);
+        }
+    }
+
+    async fn get_rune_by_id(&mut self, id: &RuneId, ctx: &Context) -> Option<DbRune> {
+        pg_get_rune_by_id(id, self, ctx).await
+    }
+
+    async fn get_block_rune_counts(
+        &mut self,
+        block_height: u64,
+        ctx: &Context,
+    ) -> (Vec<RuneId>, HashMap<RuneId, u128>) {
+        pg_get_block_rune_counts(block_height, self, ctx).await
+    }
+
+    async fn get_input_rune_balances(
+        &mut self,
+        outputs: Vec<(u32, String, u32)>,
+        ctx: &Context,
+    ) -> HashMap<u32, HashMap<RuneId, Vec<InputRuneBalance>>> {
+        pg_get_input_rune_balances(outputs, self, ctx).await
+    }
+
+    async fn commit(self) -> Result<(), String> {
+        Transaction::commit(self)
+            .await
+            .map_err(|e| format!("unable to commit pg transaction: {e}"))
+    }
+}
@@ -0,0 +1,1095 @@
+use std::{collections::HashMap, path::Path, process, str::FromStr, time::Duration};
+
+use chainhook_sdk::utils::Context;
+use ordinals::RuneId;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+
+use crate::{
+    config::Config,
+    db::{
+        cache::input_rune_balance::InputRuneBalance,
+        models::{
+            db_balance_change::DbBalanceChange, db_ledger_entry::DbLedgerEntry, db_rune::DbRune,
+            db_supply_change::DbSupplyChange, db_trade::DbTrade,
+        },
+        types::{
+            pg_bigint_u32::PgBigIntU32, pg_numeric_u128::PgNumericU128,
+            pg_numeric_u64::PgNumericU64, pg_smallint_u8::PgSmallIntU8,
+        },
+    },
+    try_error, try_info, try_warn,
+};
+
+use super::{ConnectStorage, Storage, StorageTransaction};
+
+/// Bumped whenever `SCHEMA` changes or an upgrade is added. Stored in the database's `user_version`.
+pub const SCHEMA_VERSION: i32 = 5;
+
+/// Mirrors the Postgres migrations. `u128` amounts don't fit in SQLite integers so they are stored as decimal text, as are
+/// rune terms which can hold any `u64`.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runes (
+        id                      TEXT NOT NULL PRIMARY KEY,
+        number                  INTEGER NOT NULL UNIQUE,
+        name                    TEXT NOT NULL UNIQUE,
+        spaced_name             TEXT NOT NULL UNIQUE,
+        block_hash              TEXT NOT NULL,
+        block_height            INTEGER NOT NULL,
+        tx_index                INTEGER NOT NULL,
+        tx_id                   TEXT NOT NULL,
+        divisibility            INTEGER NOT NULL DEFAULT 0,
+        premine                 TEXT NOT NULL DEFAULT '0',
+        symbol                  TEXT NOT NULL DEFAULT '¤',
+        terms_amount            TEXT,
+        terms_cap               TEXT,
+        terms_height_start      TEXT,
+        terms_height_end        TEXT,
+        terms_offset_start      TEXT,
+        terms_offset_end        TEXT,
+        turbo                   INTEGER NOT NULL DEFAULT 0,
+        cenotaph                INTEGER NOT NULL DEFAULT 0,
+        timestamp               INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runes_block_height_index ON runes (block_height);
+
+    INSERT OR IGNORE INTO runes (
+        id, number, name, spaced_name, block_hash, block_height, tx_index, tx_id, symbol, terms_amount,
+        terms_cap, terms_height_start, terms_height_end, timestamp
+    )
+    VALUES (
+        '1:0', 0, 'UNCOMMONGOODS', 'UNCOMMON•GOODS',
+        '0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5', 840000, 0, '', '⧉', '1',
+        '340282366920938463463374607431768211455', '840000', '1050000', 0
+    );
+
+    CREATE TABLE IF NOT EXISTS supply_changes (
+        rune_id                 TEXT NOT NULL,
+        block_height            INTEGER NOT NULL,
+        minted                  TEXT NOT NULL DEFAULT '0',
+        total_mints             TEXT NOT NULL DEFAULT '0',
+        burned                  TEXT NOT NULL DEFAULT '0',
+        total_burns             TEXT NOT NULL DEFAULT '0',
+        total_operations        TEXT NOT NULL DEFAULT '0',
+        PRIMARY KEY (rune_id, block_height)
+    );
+    CREATE INDEX IF NOT EXISTS supply_changes_block_height_index ON supply_changes (block_height);
+
+    CREATE TABLE IF NOT EXISTS ledger (
+        rune_id                 TEXT NOT NULL,
+        block_hash              TEXT NOT NULL,
+        block_height            INTEGER NOT NULL,
+        tx_index                INTEGER NOT NULL,
+        event_index             INTEGER NOT NULL,
+        tx_id                   TEXT NOT NULL,
+        output                  INTEGER,
+        address                 TEXT,
+        receiver_address        TEXT,
+        amount                  TEXT,
+        operation               TEXT NOT NULL CHECK (operation IN ('etching', 'mint', 'burn', 'send', 'receive')),
+        timestamp               INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS ledger_rune_id_index ON ledger (rune_id);
+    CREATE INDEX IF NOT EXISTS ledger_block_height_index ON ledger (block_height);
+    CREATE INDEX IF NOT EXISTS ledger_tx_id_output_index ON ledger (tx_id, output);
+
+    CREATE TABLE IF NOT EXISTS balance_changes (
+        rune_id                 TEXT NOT NULL,
+        block_height            INTEGER NOT NULL,
+        address                 TEXT NOT NULL,
+        balance                 TEXT NOT NULL,
+        total_operations        INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (rune_id, address, block_height)
+    );
+    CREATE INDEX IF NOT EXISTS balance_changes_block_height_index ON balance_changes (block_height);
+";
+
+/// Changes applied on top of `SCHEMA`, in order. The upgrade at index `i` brings a database from version `i + 1` to `i + 2`;
+/// new databases run all of them.
+const SCHEMA_UPGRADES: &[&str] = &[
+    // Absolute mint window and mint status of each rune, see `V6__mint_status.sql`. Terms are stored as text, so they are
+    // cast back to integers here.
+    "
+    ALTER TABLE runes ADD COLUMN mint_start_height INTEGER GENERATED ALWAYS AS (
+        CASE
+            WHEN terms_offset_start IS NULL THEN CAST(terms_height_start AS INTEGER)
+            WHEN terms_height_start IS NULL THEN block_height + CAST(terms_offset_start AS INTEGER)
+            ELSE MAX(CAST(terms_height_start AS INTEGER), block_height + CAST(terms_offset_start AS INTEGER))
+        END
+    ) VIRTUAL;
+    ALTER TABLE runes ADD COLUMN mint_end_height INTEGER GENERATED ALWAYS AS (
+        CASE
+            WHEN terms_offset_end IS NULL THEN CAST(terms_height_end AS INTEGER)
+            WHEN terms_height_end IS NULL THEN block_height + CAST(terms_offset_end AS INTEGER)
+            ELSE MIN(CAST(terms_height_end AS INTEGER), block_height + CAST(terms_offset_end AS INTEGER))
+        END
+    ) VIRTUAL;
+    ALTER TABLE runes ADD COLUMN mint_status TEXT
+        CHECK (mint_status IN ('not_yet_open', 'open', 'cap_reached', 'window_closed'));
+    CREATE INDEX IF NOT EXISTS runes_mint_status_index ON runes (mint_status);
+    ",
+    // Script of the output each ledger row refers to, see `V7__ledger_scripts.sql`.
+    "
+    ALTER TABLE ledger ADD COLUMN script_pubkey TEXT;
+    ALTER TABLE ledger ADD COLUMN script_type TEXT CHECK (script_type IN (
+        'p2pk', 'p2pkh', 'p2sh', 'p2wpkh', 'p2wsh', 'p2tr', 'multisig', 'witness_unknown', 'nonstandard'
+    ));
+    CREATE INDEX IF NOT EXISTS ledger_script_pubkey_rune_id_index ON ledger (script_pubkey, rune_id);
+    ",
+    // Sat values of the transaction that produced each ledger row, see `V8__ledger_values.sql`.
+    "
+    ALTER TABLE ledger ADD COLUMN output_value INTEGER;
+    ALTER TABLE ledger ADD COLUMN tx_fee INTEGER;
+    ALTER TABLE ledger ADD COLUMN tx_vsize INTEGER;
+    ",
+    // Likely marketplace sales, see `V9__trades.sql`.
+    "
+    CREATE TABLE IF NOT EXISTS trades (
+        rune_id                 TEXT NOT NULL,
+        block_hash              TEXT NOT NULL,
+        block_height            INTEGER NOT NULL,
+        tx_index                INTEGER NOT NULL,
+        tx_id                   TEXT NOT NULL,
+        seller_address          TEXT NOT NULL,
+        buyer_address           TEXT NOT NULL,
+        amount                  TEXT NOT NULL,
+        price                   INTEGER NOT NULL,
+        price_per_unit          REAL NOT NULL,
+        timestamp               INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS trades_rune_id_block_height_index ON trades (rune_id, block_height);
+    CREATE INDEX IF NOT EXISTS trades_block_height_index ON trades (block_height);
+    CREATE INDEX IF NOT EXISTS trades_seller_address_index ON trades (seller_address);
+    CREATE INDEX IF NOT EXISTS trades_buyer_address_index ON trades (buyer_address);
+    ",
+];
+
+/// Single file index database for deployments that don't want to run Postgres.
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+/// Exits the process if a SQLite call failed, like the Postgres functions do.
+fn ok_or_exit<T>(result: rusqlite::Result<T>, action: &str, ctx: &Context) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            try_error!(ctx, "Error {}: {}", action, e.to_string());
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_u128(value: String) -> u128 {
+    value.parse().expect("invalid u128 stored in sqlite")
+}
+
+fn parse_u64(value: String) -> u64 {
+    value.parse().expect("invalid u64 stored in sqlite")
+}
+
+fn rune_from_row(row: &Row) -> rusqlite::Result<DbRune> {
+    Ok(DbRune {
+        id: row.get("id")?,
+        number: PgBigIntU32(row.get("number")?),
+        name: row.get("name")?,
+        spaced_name: row.get("spaced_name")?,
+        block_hash: row.get("block_hash")?,
+        block_height: PgNumericU64(row.get("block_height")?),
+        tx_index: PgBigIntU32(row.get("tx_index")?),
+        tx_id: row.get("tx_id")?,
+        divisibility: PgSmallIntU8(row.get("divisibility")?),
+        premine: PgNumericU128(parse_u128(row.get("premine")?)),
+        symbol: row.get("symbol")?,
+        terms_amount: row
+            .get::<_, Option<String>>("terms_amount")?
+            .map(|v| PgNumericU128(parse_u128(v))),
+        terms_cap: row
+            .get::<_, Option<String>>("terms_cap")?
+            .map(|v| PgNumericU128(parse_u128(v))),
+        terms_height_start: row
+            .get::<_, Option<String>>("terms_height_start")?
+            .map(|v| PgNumericU64(parse_u64(v))),
+        terms_height_end: row
+            .get::<_, Option<String>>("terms_height_end")?
+            .map(|v| PgNumericU64(parse_u64(v))),
+        terms_offset_start: row
+            .get::<_, Option<String>>("terms_offset_start")?
+            .map(|v| PgNumericU64(parse_u64(v))),
+        terms_offset_end: row
+            .get::<_, Option<String>>("terms_offset_end")?
+            .map(|v| PgNumericU64(parse_u64(v))),
+        turbo: row.get("turbo")?,
+        cenotaph: row.get("cenotaph")?,
+        timestamp: PgBigIntU32(row.get("timestamp")?),
+    })
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, use `:memory:` for a private in-memory database.
+    pub fn open(path: &str, run_migrations: bool) -> Result<Self, String> {
+        let conn = Connection::open(path)
+            .map_err(|e| format!("unable to open sqlite database {path}: {e}"))?;
+        conn.busy_timeout(Duration::from_secs(30))
+            .map_err(|e| format!("unable to configure sqlite database: {e}"))?;
+        let mut storage = SqliteStorage { conn };
+        if run_migrations {
+            storage.migrate()?;
+        }
+        Ok(storage)
+    }
+
+    /// Reads the schema version of the database at `path` without creating, locking or migrating it. Returns `None` if the
+    /// file does not exist yet.
+    pub fn schema_version(path: &str) -> Result<Option<i32>, String> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("unable to open sqlite database {path}: {e}"))?;
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map(Some)
+            .map_err(|e| format!("unable to read schema version of {path}: {e}"))
+    }
+
+    fn migrate(&mut self) -> Result<(), String> {
+        let version: i32 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| format!("unable to read sqlite schema version: {e}"))?;
+        if version > SCHEMA_VERSION {
+            return Err(format!(
+                "sqlite schema version {version} is newer than the supported version {SCHEMA_VERSION}"
+            ));
+        }
+        let upgrades = SCHEMA_UPGRADES
+            .iter()
+            .skip((version.max(1) - 1) as usize)
+            .copied()
+            .collect::<Vec<_>>()
+            .join("");
+        self.conn
+            .execute_batch(&format!(
+                "PRAGMA journal_mode = WAL; BEGIN; {SCHEMA} {upgrades} PRAGMA user_version = {SCHEMA_VERSION}; COMMIT;"
+            ))
+            .map_err(|e| format!("unable to create sqlite schema: {e}"))
+    }
+}
+
+impl Storage for SqliteStorage {
+    type Transaction<'a> = SqliteTransaction<'a>;
+
+    async fn begin(&mut self) -> Result<SqliteTransaction<'_>, String> {
+        self.conn
+            .transaction()
+            .map(|tx| SqliteTransaction { tx })
+            .map_err(|e| format!("unable to begin sqlite transaction: {e}"))
+    }
+
+    async fn get_max_rune_number(&mut self, ctx: &Context) -> u32 {
+        let max: Option<u32> = ok_or_exit(
+            self.conn
+                .query_row("SELECT MAX(number) FROM runes", [], |row| row.get(0)),
+            "getting max rune number",
+            ctx,
+        );
+        max.unwrap_or(0)
+    }
+
+    async fn get_rune_total_mints(&mut self, ctx: &Context) -> HashMap<RuneId, u128> {
+        let mut stmt = ok_or_exit(
+            self.conn.prepare(
+                "SELECT rune_id, total_mints FROM supply_changes AS s
+                WHERE block_height = (SELECT MAX(block_height) FROM supply_changes WHERE rune_id = s.rune_id)",
+            ),
+            "retrieving rune minted totals",
+            ctx,
+        );
+        let rows = ok_or_exit(
+            stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>()),
+            "retrieving rune minted totals",
+            ctx,
+        );
+        rows.into_iter()
+            .map(|(rune_id, total_mints)| {
+                (RuneId::from_str(&rune_id).unwrap(), parse_u128(total_mints))
+            })
+            .collect()
+    }
+
+    async fn get_block_height(&mut self, ctx: &Context) -> Option<u64> {
+        ok_or_exit(
+            self.conn
+                .query_row("SELECT MAX(block_height) FROM ledger", [], |row| row.get(0)),
+            "getting max block height",
+            ctx,
+        )
+    }
+}
+
+impl ConnectStorage for SqliteStorage {
+    #[cfg_attr(test, mutants::skip)]
+    async fn connect(config: &Config, run_migrations: bool, ctx: &Context) -> Self {
+        let path = &config.storage.sqlite_path;
+        try_info!(ctx, "Opening sqlite database at {}", path);
+        match SqliteStorage::open(path, run_migrations) {
+            Ok(storage) => storage,
+            Err(e) => {
+                try_error!(ctx, "{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Zeros prepended to `u128` text columns so they compare as numbers, since casting them to `INTEGER` saturates at
+/// `i64::MAX`. `u128::MAX` has 39 digits.
+const U128_PADDING: &str = "'000000000000000000000000000000000000000'";
+
+/// SQLite version of `pg_update_mint_statuses`.
+fn sqlite_update_mint_statuses(
+    tx: &rusqlite::Transaction<'_>,
+    block_height: u64,
+    include_all: bool,
+) -> rusqlite::Result<usize> {
+    let candidates = if include_all {
+        ""
+    } else {
+        "AND (
+            r.mint_status IS NULL
+            OR (r.mint_status = 'not_yet_open' AND r.mint_start_height <= ?1)
+            OR (r.mint_status IN ('not_yet_open', 'open') AND r.mint_end_height < ?1)
+            OR (
+                r.mint_status = 'open'
+                AND r.id IN (SELECT rune_id FROM supply_changes WHERE block_height = ?1 - 1)
+            )
+        )"
+    };
+    tx.execute(
+        &format!(
+            "WITH statuses AS (
+                SELECT r.id,
+                    CASE
+                        WHEN substr({U128_PADDING} || COALESCE((
+                            SELECT total_mints FROM supply_changes
+                            WHERE rune_id = r.id
+                            ORDER BY block_height DESC
+                            LIMIT 1
+                        ), '0'), -39) >= substr({U128_PADDING} || r.terms_cap, -39) THEN 'cap_reached'
+                        WHEN ?1 > r.mint_end_height THEN 'window_closed'
+                        WHEN ?1 < r.mint_start_height THEN 'not_yet_open'
+                        ELSE 'open'
+                    END AS mint_status
+                FROM runes AS r
+                WHERE r.terms_amount IS NOT NULL AND NOT r.cenotaph {candidates}
+            )
+            UPDATE runes SET mint_status = s.mint_status
+            FROM statuses AS s
+            WHERE runes.id = s.id AND runes.mint_status IS NOT s.mint_status"
+        ),
+        [block_height + 1],
+    )
+}
+
+pub struct SqliteTransaction<'a> {
+    tx: rusqlite::Transaction<'a>,
+}
+
+impl StorageTransaction for SqliteTransaction<'_> {
+    async fn insert_runes(&mut self, rows: &[DbRune], ctx: &Context) {
+        let mut stmt = ok_or_exit(
+            self.tx.prepare_cached(
+                "INSERT INTO runes
+                (id, number, name, spaced_name, block_hash, block_height, tx_index, tx_id, divisibility, premine, symbol,
+                terms_amount, terms_cap, terms_height_start, terms_height_end, terms_offset_start, terms_offset_end, turbo,
+                cenotaph, timestamp)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)
+                ON CONFLICT (name) DO NOTHING",
+            ),
+            "inserting runes",
+            ctx,
+        );
+        for row in rows.iter() {
+            ok_or_exit(
+                stmt.execute(params![
+                    row.id,
+                    row.number.0,
+                    row.name,
+                    row.spaced_name,
+                    row.block_hash,
+                    row.block_height.0,
+                    row.tx_index.0,
+                    row.tx_id,
+                    row.divisibility.0,
+                    row.premine.0.to_string(),
+                    row.symbol,
+                    row.terms_amount.as_ref().map(|v| v.0.to_string()),
+                    row.terms_cap.as_ref().map(|v| v.0.to_string()),
+                    row.terms_height_start.as_ref().map(|v| v.0.to_string()),
+                    row.terms_height_end.as_ref().map(|v| v.0.to_string()),
+                    row.terms_offset_start.as_ref().map(|v| v.0.to_string()),
+                    row.terms_offset_end.as_ref().map(|v| v.0.to_string()),
+                    row.turbo,
+                    row.cenotaph,
+                    row.timestamp.0,
+                ]),
+                "inserting runes",
+                ctx,
+            );
+        }
+    }
+
+    async fn insert_supply_changes(&mut self, rows: &[DbSupplyChange], ctx: &Context) {
+        for row in rows.iter() {
+            let previous = ok_or_exit(
+                self.tx
+                    .prepare_cached(
+                        "SELECT minted, total_mints, burned, total_burns, total_operations FROM supply_changes
+                        WHERE rune_id = ?1 ORDER BY block_height DESC LIMIT 1",
+                    )
+                    .and_then(|mut stmt| {
+                        stmt.query_row([&row.rune_id], |r| {
+                            let mut values = [0u128; 5];
+                            for (i, value) in values.iter_mut().enumerate() {
+                                *value = parse_u128(r.get(i)?);
+                            }
+                            Ok(values)
+                        })
+                        .optional()
+                    }),
+                "inserting supply changes",
+                ctx,
+            )
+            .unwrap_or_default();
+            let totals = [
+                row.minted.0,
+                row.total_mints.0,
+                row.burned.0,
+                row.total_burns.0,
+                row.total_operations.0,
+            ]
+            .iter()
+            .zip(previous)
+            .map(|(change, previous)| (previous + change).to_string())
+            .collect::<Vec<_>>();
+            ok_or_exit(
+                self.tx
+                    .prepare_cached(
+                        "INSERT INTO supply_changes
+                        (rune_id, block_height, minted, total_mints, burned, total_burns, total_operations)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                        ON CONFLICT (rune_id, block_height) DO UPDATE SET
+                            minted = excluded.minted,
+                            total_mints = excluded.total_mints,
+                            burned = excluded.burned,
+                            total_burns = excluded.total_burns,
+                            total_operations = excluded.total_operations",
+                    )
+                    .and_then(|mut stmt| {
+                        stmt.execute(params![
+                            row.rune_id,
+                            row.block_height.0,
+                            totals[0],
+                            totals[1],
+                            totals[2],
+                            totals[3],
+                            totals[4],
+                        ])
+                    }),
+                "inserting supply changes",
+                ctx,
+            );
+        }
+    }
+
+    async fn insert_balance_changes(
+        &mut self,
+        rows: &[DbBalanceChange],
+        increase: bool,
+        ctx: &Context,
+    ) {
+        for row in rows.iter() {
+            let (balance, total_operations) = ok_or_exit(
+                self.tx
+                    .prepare_cached(
+                        "SELECT balance, total_operations FROM balance_changes
+                        WHERE rune_id = ?1 AND address = ?2 ORDER BY block_height DESC LIMIT 1",
+                    )
+                    .and_then(|mut stmt| {
+                        stmt.query_row([&row.rune_id, &row.address], |r| {
+                            Ok((parse_u128(r.get(0)?), r.get::<_, u64>(1)?))
+                        })
+                        .optional()
+                    }),
+                "inserting balance changes",
+                ctx,
+            )
+            .unwrap_or_default();
+            let balance = if increase {
+                balance + row.balance.0
+            } else {
+                balance.checked_sub(row.balance.0).unwrap_or_else(|| {
+                    try_warn!(
+                        ctx,
+                        "Balance of {} for rune {} would become negative, storing 0",
+                        row.address,
+                        row.rune_id
+                    );
+                    0
+                })
+            };
+            ok_or_exit(
+                self.tx
+                    .prepare_cached(
+                        "INSERT INTO balance_changes (rune_id, block_height, address, balance, total_operations)
+                        VALUES (?1, ?2, ?3, ?4, ?5)
+                        ON CONFLICT (rune_id, address, block_height) DO UPDATE SET
+                            balance = excluded.balance,
+                            total_operations = excluded.total_operations",
+                    )
+                    .and_then(|mut stmt| {
+                        stmt.execute(params![
+                            row.rune_id,
+                            row.block_height.0,
+                            row.address,
+                            balance.to_string(),
+                            total_operations + row.total_operations.0 as u64,
+                        ])
+                    }),
+                "inserting balance changes",
+                ctx,
+            );
+        }
+    }
+
+    async fn insert_ledger_entries(&mut self, rows: &[DbLedgerEntry], ctx: &Context) {
+        let mut stmt = ok_or_exit(
+            self.tx.prepare_cached(
+                "INSERT INTO ledger
+                (rune_id, block_hash, block_height, tx_index, event_index, tx_id, output, address, receiver_address, amount,
+                operation, timestamp, script_pubkey, script_type, output_value, tx_fee, tx_vsize)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+            ),
+            "inserting ledger entries",
+            ctx,
+        );
+        for row in rows.iter() {
+            ok_or_exit(
+                stmt.execute(params![
+                    row.rune_id,
+                    row.block_hash,
+                    row.block_height.0,
+                    row.tx_index.0,
+                    row.event_index.0,
+                    row.tx_id,
+                    row.output.as_ref().map(|v| v.0),
+                    row.address,
+                    row.receiver_address,
+                    row.amount.as_ref().map(|v| v.0.to_string()),
+                    row.operation.as_str(),
+                    row.timestamp.0,
+                    row.script_pubkey,
+                    row.script_type.as_ref().map(|v| v.as_str()),
+                    row.output_value.as_ref().map(|v| v.0),
+                    row.tx_fee.as_ref().map(|v| v.0),
+                    row.tx_vsize.as_ref().map(|v| v.0),
+                ]),
+                "inserting ledger entries",
+                ctx,
+            );
+        }
+    }
+
+    async fn insert_trades(&mut self, rows: &[DbTrade], ctx: &Context) {
+        let mut stmt = ok_or_exit(
+            self.tx.prepare_cached(
+                "INSERT INTO trades
+                (rune_id, block_hash, block_height, tx_index, tx_id, seller_address, buyer_address, amount, price,
+                price_per_unit, timestamp)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            ),
+            "inserting trades",
+            ctx,
+        );
+        for row in rows.iter() {
+            ok_or_exit(
+                stmt.execute(params![
+                    row.rune_id,
+                    row.block_hash,
+                    row.block_height.0,
+                    row.tx_index.0,
+                    row.tx_id,
+                    row.seller_address,
+                    row.buyer_address,
+                    row.amount.0.to_string(),
+                    row.price.0,
+                    row.price_per_unit,
+                    row.timestamp.0,
+                ]),
+                "inserting trades",
+                ctx,
+            );
+        }
+    }
+
+    async fn roll_back_block(&mut self, block_height: u64, ctx: &Context) {
+        for table in [
+            "balance_changes",
+            "supply_changes",
+            "ledger",
+            "trades",
+            "runes",
+        ] {
+            ok_or_exit(
+                self.tx.execute(
+                    &format!("DELETE FROM {table} WHERE block_height = ?1"),
+                    [block_height],
+                ),
+                &format!("rolling back {table}"),
+                ctx,
+            );
+        }
+        ok_or_exit(
+            sqlite_update_mint_statuses(&self.tx, block_height.saturating_sub(1), true),
+            "rolling back mint statuses",
+            ctx,
+        );
+    }
+
+    async fn roll_back_to_block(&mut self, block_height: u64, ctx: &Context) -> Result<(), String> {
+        for (table, query) in [
+            (
+                "balance_changes",
+                "DELETE FROM balance_changes WHERE block_height > ?1",
+            ),
+            (
+                "supply_changes",
+                "DELETE FROM supply_changes WHERE block_height > ?1",
+            ),
+            ("ledger", "DELETE FROM ledger WHERE block_height > ?1"),
+            ("trades", "DELETE FROM trades WHERE block_height > ?1"),
+            (
+                "runes",
+                "DELETE FROM runes WHERE block_height > ?1 AND number > 0",
+            ),
+        ] {
+            let rows = self
+                .tx
+                .execute(query, [block_height])
+                .map_err(|e| format!("error rolling back {table}: {e}"))?;
+            try_info!(
+                ctx,
+                "Deleted {rows} {table} rows above block {block_height}"
+            );
+        }
+        sqlite_update_mint_statuses(&self.tx, block_height, true)
+            .map_err(|e| format!("error rolling back mint statuses: {e}"))?;
+        Ok(())
+    }
+
+    async fn update_mint_statuses(&mut self, block_height: u64, ctx: &Context) {
+        ok_or_exit(
+            sqlite_update_mint_statuses(&self.tx, block_height, false),
+            "updating mint statuses",
+            ctx,
+        );
+    }
+
+    async fn get_rune_by_id(&mut self, id: &RuneId, ctx: &Context) -> Option<DbRune> {
+        ok_or_exit(
+            self.tx
+                .prepare_cached("SELECT * FROM runes WHERE id = ?1")
+                .and_then(|mut stmt| stmt.query_row([id.to_string()], rune_from_row).optional()),
+            "retrieving rune",
+            ctx,
+        )
+    }
+
+    async fn get_block_rune_counts(
+        &mut self,
+        block_height: u64,
+        ctx: &Context,
+    ) -> (Vec<RuneId>, HashMap<RuneId, u128>) {
+        let etched_runes = ok_or_exit(
+            self.tx
+                .prepare_cached("SELECT id FROM runes WHERE block_height = ?1")
+                .and_then(|mut stmt| {
+                    stmt.query_map([block_height], |row| row.get::<_, String>(0))?
+                        .collect::<rusqlite::Result<Vec<_>>>()
+                }),
+            "retrieving block etchings",
+            ctx,
+        )
+        .iter()
+        .map(|id| RuneId::from_str(id).unwrap())
+        .collect();
+        let mint_rows = ok_or_exit(
+            self.tx
+                .prepare_cached(
+                    "SELECT c.rune_id, c.total_mints, (
+                        SELECT p.total_mints FROM supply_changes AS p
+                        WHERE p.rune_id = c.rune_id AND p.block_height < c.block_height
+                        ORDER BY p.block_height DESC LIMIT 1
+                    )
+                    FROM supply_changes AS c
+                    WHERE c.block_height = ?1",
+                )
+                .and_then(|mut stmt| {
+                    stmt.query_map([block_height], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, Option<String>>(2)?,
+                        ))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+                }),
+            "retrieving block mints",
+            ctx,
+        );
+        let block_mints = mint_rows
+            .into_iter()
+            .map(|(rune_id, total, previous)| {
+                (
+                    RuneId::from_str(&rune_id).unwrap(),
+                    parse_u128(total) - previous.map(parse_u128).unwrap_or(0),
+                )
+            })
+            .collect();
+        (etched_runes, block_mints)
+    }
+
+    async fn get_input_rune_balances(
+        &mut self,
+        outputs: Vec<(u32, String, u32)>,
+        ctx: &Context,
+    ) -> HashMap<u32, HashMap<RuneId, Vec<InputRuneBalance>>> {
+        let mut stmt = ok_or_exit(
+            self.tx.prepare_cached(
+                "SELECT rune_id, address, amount, script_pubkey FROM ledger
+                WHERE tx_id = ?1 AND output = ?2 AND operation = 'receive'",
+            ),
+            "retrieving output rune balances",
+            ctx,
+        );
+        let mut results: HashMap<u32, HashMap<RuneId, Vec<InputRuneBalance>>> = HashMap::new();
+        for (input_index, tx_id, output) in outputs.iter() {
+            let rows = ok_or_exit(
+                stmt.query_map(params![tx_id, output], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })
+                .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>()),
+                "retrieving output rune balances",
+                ctx,
+            );
+            for (rune_id, address, amount, script_pubkey) in rows.into_iter() {
+                results
+                    .entry(*input_index)
+                    .or_default()
+                    .entry(RuneId::from_str(&rune_id).unwrap())
+                    .or_default()
+                    .push(InputRuneBalance {
+                        address,
+                        script_pubkey,
+                        amount: parse_u128(amount),
+                    });
+            }
+        }
+        results
+    }
+
+    async fn commit(self) -> Result<(), String> {
+        self.tx
+            .commit()
+            .map_err(|e| format!("unable to commit sqlite transaction: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, str::FromStr};
+
+    use chainhook_sdk::utils::Context;
+    use ordinals::RuneId;
+
+    use crate::db::{
+        models::{
+            db_balance_change::DbBalanceChange, db_ledger_entry::DbLedgerEntry,
+            db_ledger_operation::DbLedgerOperation, db_rune::DbRune,
+            db_supply_change::DbSupplyChange, db_trade::DbTrade,
+        },
+        storage::{Storage, StorageTransaction},
+        types::{pg_numeric_u128::PgNumericU128, pg_numeric_u64::PgNumericU64},
+    };
+
+    use super::SqliteStorage;
+
+    fn receive(block_height: u64, tx_id: &str, amount: u128) -> DbLedgerEntry {
+        DbLedgerEntry::from_values(
+            Some(amount),
+            RuneId::from_str("840000:1").unwrap(),
+            &"0x0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5".to_string(),
+            block_height,
+            0,
+            0,
+            &format!("0x{tx_id}"),
+            Some(0),
+            Some(&"bc1qabc".to_string()),
+            None,
+            DbLedgerOperation::Receive,
+            0,
+        )
+    }
+
+    async fn index_mint(
+        storage: &mut SqliteStorage,
+        block_height: u64,
+        tx_id: &str,
+        ctx: &Context,
+    ) {
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx
+            .insert_supply_changes(
+                &[DbSupplyChange::from_mint(
+                    "840000:1".to_string(),
+                    PgNumericU64(block_height),
+                    PgNumericU128(100),
+                )],
+                ctx,
+            )
+            .await;
+        db_tx
+            .insert_ledger_entries(&[receive(block_height, tx_id, 100)], ctx)
+            .await;
+        db_tx
+            .insert_balance_changes(
+                &[DbBalanceChange::from_operation(
+                    "840000:1".to_string(),
+                    PgNumericU64(block_height),
+                    "bc1qabc".to_string(),
+                    PgNumericU128(100),
+                )],
+                true,
+                ctx,
+            )
+            .await;
+        db_tx.update_mint_statuses(block_height, ctx).await;
+        db_tx.commit().await.unwrap();
+    }
+
+    fn mint_status(storage: &SqliteStorage, id: &str) -> Option<String> {
+        storage
+            .conn
+            .query_row("SELECT mint_status FROM runes WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn indexes_and_rolls_back_blocks() {
+        let ctx = Context::empty();
+        let mut storage = SqliteStorage::open(":memory:", true).unwrap();
+        let rune_id = RuneId::from_str("840000:1").unwrap();
+
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx.insert_runes(&[DbRune::factory()], &ctx).await;
+        db_tx.commit().await.unwrap();
+        index_mint(&mut storage, 840000, "aa", &ctx).await;
+        index_mint(&mut storage, 840001, "bb", &ctx).await;
+
+        assert_eq!(storage.get_max_rune_number(&ctx).await, 1);
+        assert_eq!(storage.get_block_height(&ctx).await, Some(840001));
+        assert_eq!(
+            storage.get_rune_total_mints(&ctx).await,
+            HashMap::from([(rune_id, 2)])
+        );
+
+        let mut db_tx = storage.begin().await.unwrap();
+        let rune = db_tx.get_rune_by_id(&rune_id, &ctx).await.unwrap();
+        assert_eq!(rune.spaced_name, DbRune::factory().spaced_name);
+        assert_eq!(rune.premine, DbRune::factory().premine);
+        let balances = db_tx
+            .get_input_rune_balances(vec![(0, "bb".to_string(), 0)], &ctx)
+            .await;
+        let input = balances.get(&0).unwrap().get(&rune_id).unwrap();
+        assert_eq!(input.len(), 1);
+        assert_eq!(input[0].address, Some("bc1qabc".to_string()));
+        assert_eq!(input[0].amount, 100);
+        assert_eq!(
+            db_tx.get_block_rune_counts(840001, &ctx).await,
+            (vec![], HashMap::from([(rune_id, 1)]))
+        );
+        db_tx.roll_back_block(840001, &ctx).await;
+        db_tx.commit().await.unwrap();
+
+        assert_eq!(storage.get_block_height(&ctx).await, Some(840000));
+        assert_eq!(
+            storage.get_rune_total_mints(&ctx).await,
+            HashMap::from([(rune_id, 1)])
+        );
+
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx.roll_back_to_block(839999, &ctx).await.unwrap();
+        db_tx.commit().await.unwrap();
+        assert_eq!(storage.get_block_height(&ctx).await, None);
+        // The rune inserted by the schema survives roll backs.
+        assert_eq!(storage.get_max_rune_number(&ctx).await, 0);
+    }
+
+    #[tokio::test]
+    async fn accumulates_balances() {
+        let ctx = Context::empty();
+        let mut storage = SqliteStorage::open(":memory:", true).unwrap();
+        let change = |block_height: u64, balance: u128| {
+            vec![DbBalanceChange::from_operation(
+                "840000:1".to_string(),
+                PgNumericU64(block_height),
+                "bc1qabc".to_string(),
+                PgNumericU128(balance),
+            )]
+        };
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx
+            .insert_balance_changes(&change(840000, 100), true, &ctx)
+            .await;
+        db_tx
+            .insert_balance_changes(&change(840001, 30), false, &ctx)
+            .await;
+        db_tx
+            .insert_balance_changes(&change(840001, 5), true, &ctx)
+            .await;
+        db_tx.commit().await.unwrap();
+
+        let (balance, total_operations): (String, u64) = storage
+            .conn
+            .query_row(
+                "SELECT balance, total_operations FROM balance_changes WHERE block_height = 840001",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(balance, "75");
+        assert_eq!(total_operations, 3);
+    }
+
+    #[tokio::test]
+    async fn tracks_mint_statuses() {
+        let ctx = Context::empty();
+        let mut storage = SqliteStorage::open(":memory:", true).unwrap();
+        let mut rune = DbRune::factory();
+        rune.terms_cap(Some(PgNumericU128(2)));
+        rune.terms_offset_start(Some(PgNumericU64(2)));
+
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx.insert_runes(&[rune], &ctx).await;
+        db_tx.update_mint_statuses(840000, &ctx).await;
+        db_tx.commit().await.unwrap();
+        let window: (i64, Option<i64>) = storage
+            .conn
+            .query_row(
+                "SELECT mint_start_height, mint_end_height FROM runes WHERE id = '840000:1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(window, (840002, None));
+        assert_eq!(
+            mint_status(&storage, "840000:1").as_deref(),
+            Some("not_yet_open")
+        );
+        assert_eq!(mint_status(&storage, "1:0").as_deref(), Some("open"));
+
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx.update_mint_statuses(840001, &ctx).await;
+        db_tx.commit().await.unwrap();
+        assert_eq!(mint_status(&storage, "840000:1").as_deref(), Some("open"));
+
+        index_mint(&mut storage, 840002, "aa", &ctx).await;
+        assert_eq!(mint_status(&storage, "840000:1").as_deref(), Some("open"));
+        index_mint(&mut storage, 840003, "bb", &ctx).await;
+        assert_eq!(
+            mint_status(&storage, "840000:1").as_deref(),
+            Some("cap_reached")
+        );
+
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx.roll_back_block(840003, &ctx).await;
+        db_tx.commit().await.unwrap();
+        assert_eq!(mint_status(&storage, "840000:1").as_deref(), Some("open"));
+
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx.roll_back_to_block(840000, &ctx).await.unwrap();
+        db_tx.commit().await.unwrap();
+        assert_eq!(
+            mint_status(&storage, "840000:1").as_deref(),
+            Some("not_yet_open")
+        );
+    }
+
+    #[tokio::test]
+    async fn compares_caps_above_i64() {
+        let ctx = Context::empty();
+        let mut storage = SqliteStorage::open(":memory:", true).unwrap();
+        let mut rune = DbRune::factory();
+        rune.terms_cap(Some(PgNumericU128(u128::MAX)));
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx.insert_runes(&[rune], &ctx).await;
+        db_tx.commit().await.unwrap();
+        storage
+            .conn
+            .execute(
+                "INSERT INTO supply_changes (rune_id, block_height, total_mints)
+                VALUES ('840000:1', 840000, '10000000000000000000')",
+                [],
+            )
+            .unwrap();
+
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx.update_mint_statuses(840000, &ctx).await;
+        db_tx.commit().await.unwrap();
+        assert_eq!(mint_status(&storage, "840000:1").as_deref(), Some("open"));
+    }
+
+    #[tokio::test]
+    async fn stores_and_rolls_back_trades() {
+        let ctx = Context::empty();
+        let mut storage = SqliteStorage::open(":memory:", true).unwrap();
+        let trade = |block_height: u64| DbTrade {
+            rune_id: "840000:1".to_string(),
+            block_height: PgNumericU64(block_height),
+            seller_address: "bc1qseller".to_string(),
+            buyer_address: "bc1qbuyer".to_string(),
+            amount: PgNumericU128(u128::MAX),
+            price: PgNumericU64(10_000),
+            price_per_unit: 0.5,
+            ..Default::default()
+        };
+        let mut db_tx = storage.begin().await.unwrap();
+        db_tx
+            .insert_trades(&[trade(840000), trade(840001), trade(840002)], &ctx)
+            .await;
+        db_tx.roll_back_block(840002, &ctx).await;
+        db_tx.roll_back_to_block(840000, &ctx).await.unwrap();
+        db_tx.commit().await.unwrap();
+
+        let rows: Vec<(i64, String, i64, f64)> = storage
+            .conn
+            .prepare("SELECT block_height, amount, price, price_per_unit FROM trades")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows, vec![(840000, u128::MAX.to_string(), 10_000, 0.5)]);
+    }
+}
@@ -0,0 +1,651 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{
+        ArrayRef, BooleanArray, Decimal256Array, Float64Array, Int16Array, Int64Array, StringArray,
+        UInt64Array,
+    },
+    datatypes::{i256, DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use chainhook_sdk::utils::Context;
+use parquet::arrow::ArrowWriter;
+use tokio_postgres::{Client, Row};
+
+use crate::{
+    db::types::{pg_numeric_u128::PgNumericU128, pg_numeric_u64::PgNumericU64},
+    try_info,
+};
+
+/// Number of rows fetched from postgres at a time while streaming a partition to disk.
+const FETCH_SIZE: i32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!(
+                "invalid export format {s}, expected `csv` or `parquet`"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnKind {
+    Text,
+    /// Postgres enum, exported as its label.
+    Enum,
+    Bool,
+    SmallInt,
+    BigInt,
+    /// `NUMERIC` column holding a `u64` such as a block height.
+    NumericU64,
+    /// `NUMERIC` column holding a `u128` rune amount, which does not fit in any native postgres or CSV-friendly float type.
+    /// Parquet files store it as a 39 digit decimal, the smallest precision that holds `u128::MAX`.
+    NumericU128,
+    Double,
+}
+
+struct ExportColumn {
+    name: &'static str,
+    kind: ColumnKind,
+}
+
+const fn col(name: &'static str, kind: ColumnKind) -> ExportColumn {
+    ExportColumn { name, kind }
+}
+
+/// A table that can be exported. Rows are partitioned by their `block_height` column.
+pub struct ExportTable {
+    pub name: &'static str,
+    columns: &'static [ExportColumn],
+    order_by: &'static str,
+}
+
+pub const EXPORT_TABLES: [ExportTable; 5] = [
+    ExportTable {
+        name: "ledger",
+        columns: &[
+            col("rune_id", ColumnKind::Text),
+            col("block_hash", ColumnKind::Text),
+            col("block_height", ColumnKind::NumericU64),
+            col("tx_index", ColumnKind::BigInt),
+            col("event_index", ColumnKind::BigInt),
+            col("tx_id", ColumnKind::Text),
+            col("output", ColumnKind::BigInt),
+            col("address", ColumnKind::Text),
+            col("receiver_address", ColumnKind::Text),
+            col("amount", ColumnKind::NumericU128),
+            col("operation", ColumnKind::Enum),
+            col("timestamp", ColumnKind::BigInt),
+            col("script_pubkey", ColumnKind::Text),
+            col("script_type", ColumnKind::Enum),
+            col("output_value", ColumnKind::NumericU64),
+            col("tx_fee", ColumnKind::NumericU64),
+            col("tx_vsize", ColumnKind::BigInt),
+        ],
+        order_by: "block_height, tx_index, event_index",
+    },
+    ExportTable {
+        name: "runes",
+        columns: &[
+            col("id", ColumnKind::Text),
+            col("number", ColumnKind::BigInt),
+            col("name", ColumnKind::Text),
+            col("spaced_name", ColumnKind::Text),
+            col("block_hash", ColumnKind::Text),
+            col("block_height", ColumnKind::NumericU64),
+            col("tx_index", ColumnKind::BigInt),
+            col("tx_id", ColumnKind::Text),
+            col("divisibility", ColumnKind::SmallInt),
+            col("premine", ColumnKind::NumericU128),
+            col("symbol", ColumnKind::Text),
+            col("terms_amount", ColumnKind::NumericU128),
+            col("terms_cap", ColumnKind::NumericU128),
+            col("terms_height_start", ColumnKind::NumericU64),
+            col("terms_height_end", ColumnKind::NumericU64),
+            col("terms_offset_start", ColumnKind::NumericU64),
+            col("terms_offset_end", ColumnKind::NumericU64),
+            col("turbo", ColumnKind::Bool),
+            col("cenotaph", ColumnKind::Bool),
+            col("timestamp", ColumnKind::BigInt),
+            // Offsets are added to the etching height, so the window can go past `u64::MAX`.
+            col("mint_start_height", ColumnKind::NumericU128),
+            col("mint_end_height", ColumnKind::NumericU128),
+            col("mint_status", ColumnKind::Enum),
+        ],
+        order_by: "block_height, tx_index",
+    },
+    ExportTable {
+        name: "supply_changes",
+        columns: &[
+            col("rune_id", ColumnKind::Text),
+            col("block_height", ColumnKind::NumericU64),
+            col("minted", ColumnKind::NumericU128),
+            col("total_mints", ColumnKind::NumericU128),
+            col("burned", ColumnKind::NumericU128),
+            col("total_burns", ColumnKind::NumericU128),
+            col("total_operations", ColumnKind::NumericU128),
+        ],
+        order_by: "block_height, rune_id",
+    },
+    ExportTable {
+        name: "balance_changes",
+        columns: &[
+            col("rune_id", ColumnKind::Text),
+            col("block_height", ColumnKind::NumericU64),
+            col("address", ColumnKind::Text),
+            col("balance", ColumnKind::NumericU128),
+            col("total_operations", ColumnKind::BigInt),
+        ],
+        order_by: "block_height, rune_id, address",
+    },
+    ExportTable {
+        name: "trades",
+        columns: &[
+            col("rune_id", ColumnKind::Text),
+            col("block_hash", ColumnKind::Text),
+            col("block_height", ColumnKind::NumericU64),
+            col("tx_index", ColumnKind::BigInt),
+            col("tx_id", ColumnKind::Text),
+            col("seller_address", ColumnKind::Text),
+            col("buyer_address", ColumnKind::Text),
+            col("amount", ColumnKind::NumericU128),
+            col("price", ColumnKind::NumericU64),
+            col("price_per_unit", ColumnKind::Double),
+            col("timestamp", ColumnKind::BigInt),
+        ],
+        order_by: "block_height, tx_index, rune_id, seller_address",
+    },
+];
+
+/// Parses a comma separated list of table names such as `ledger,runes`.
+pub fn parse_export_tables(tables: &str) -> Result<Vec<&'static ExportTable>, String> {
+    tables
+        .split(',')
+        .map(|name| {
+            EXPORT_TABLES
+                .iter()
+                .find(|t| t.name == name.trim())
+                .ok_or(format!(
+                    "unknown table {name}, expected one of {}",
+                    EXPORT_TABLES.map(|t| t.name).join(", ")
+                ))
+        })
+        .collect()
+}
+
+/// Splits `start_block..=end_block` into consecutive ranges of at most `partition_size` blocks.
+pub fn block_partitions(start_block: u64, end_block: u64, partition_size: u64) -> Vec<(u64, u64)> {
+    let partition_size = partition_size.max(1);
+    let mut partitions = vec![];
+    let mut start = start_block;
+    while start <= end_block {
+        let end = end_block.min(start.saturating_add(partition_size - 1));
+        partitions.push((start, end));
+        if end == u64::MAX {
+            break;
+        }
+        start = end + 1;
+    }
+    partitions
+}
+
+/// Path of the file holding the rows of `table` between `start_block` and `end_block`. Heights are zero padded so files
+/// sort in block order.
+pub fn partition_path(
+    out_dir: &Path,
+    table: &ExportTable,
+    start_block: u64,
+    end_block: u64,
+    format: ExportFormat,
+) -> PathBuf {
+    out_dir.join(table.name).join(format!(
+        "{}_{start_block:010}_{end_block:010}.{}",
+        table.name,
+        format.extension()
+    ))
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn column_value(row: &Row, index: usize, kind: ColumnKind) -> Option<String> {
+    match kind {
+        ColumnKind::Text | ColumnKind::Enum => row.get::<_, Option<String>>(index),
+        ColumnKind::Bool => row.get::<_, Option<bool>>(index).map(|v| v.to_string()),
+        ColumnKind::SmallInt => row.get::<_, Option<i16>>(index).map(|v| v.to_string()),
+        ColumnKind::BigInt => row.get::<_, Option<i64>>(index).map(|v| v.to_string()),
+        ColumnKind::NumericU64 => row
+            .get::<_, Option<PgNumericU64>>(index)
+            .map(|v| v.0.to_string()),
+        ColumnKind::NumericU128 => row
+            .get::<_, Option<PgNumericU128>>(index)
+            .map(|v| v.0.to_string()),
+        ColumnKind::Double => row.get::<_, Option<f64>>(index).map(|v| v.to_string()),
+    }
+}
+
+/// Precision of the parquet decimal that holds `ColumnKind::NumericU128` values.
+const U128_DECIMAL_PRECISION: u8 = 39;
+
+fn parquet_schema(table: &ExportTable) -> Schema {
+    Schema::new(
+        table
+            .columns
+            .iter()
+            .map(|c| {
+                let data_type = match c.kind {
+                    ColumnKind::Text | ColumnKind::Enum => DataType::Utf8,
+                    ColumnKind::Bool => DataType::Boolean,
+                    ColumnKind::SmallInt => DataType::Int16,
+                    ColumnKind::BigInt => DataType::Int64,
+                    ColumnKind::NumericU64 => DataType::UInt64,
+                    ColumnKind::NumericU128 => DataType::Decimal256(U128_DECIMAL_PRECISION, 0),
+                    ColumnKind::Double => DataType::Float64,
+                };
+                Field::new(c.name, data_type, true)
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn parquet_column(rows: &[Row], index: usize, kind: ColumnKind) -> Result<ArrayRef, String> {
+    let array: ArrayRef = match kind {
+        ColumnKind::Text | ColumnKind::Enum => Arc::new(
+            rows.iter()
+                .map(|row| row.get::<_, Option<String>>(index))
+                .collect::<StringArray>(),
+        ),
+        ColumnKind::Bool => Arc::new(
+            rows.iter()
+                .map(|row| row.get::<_, Option<bool>>(index))
+                .collect::<BooleanArray>(),
+        ),
+        ColumnKind::SmallInt => Arc::new(
+            rows.iter()
+                .map(|row| row.get::<_, Option<i16>>(index))
+                .collect::<Int16Array>(),
+        ),
+        ColumnKind::BigInt => Arc::new(
+            rows.iter()
+                .map(|row| row.get::<_, Option<i64>>(index))
+                .collect::<Int64Array>(),
+        ),
+        ColumnKind::NumericU64 => Arc::new(
+            rows.iter()
+                .map(|row| row.get::<_, Option<PgNumericU64>>(index).map(|v| v.0))
+                .collect::<UInt64Array>(),
+        ),
+        ColumnKind::NumericU128 => Arc::new(
+            rows.iter()
+                .map(|row| {
+                    row.get::<_, Option<PgNumericU128>>(index)
+                        .map(|v| i256::from_parts(v.0, 0))
+                })
+                .collect::<Decimal256Array>()
+                .with_precision_and_scale(U128_DECIMAL_PRECISION, 0)
+                .map_err(|e| format!("invalid decimal column: {e}"))?,
+        ),
+        ColumnKind::Double => Arc::new(
+            rows.iter()
+                .map(|row| row.get::<_, Option<f64>>(index))
+                .collect::<Float64Array>(),
+        ),
+    };
+    Ok(array)
+}
+
+/// Writes the rows of a single partition file in the requested `ExportFormat`.
+enum PartitionWriter {
+    Csv(BufWriter<File>),
+    Parquet {
+        // Boxed since it holds the buffered row group, much larger than the CSV writer.
+        writer: Box<ArrowWriter<File>>,
+        schema: Arc<Schema>,
+    },
+}
+
+impl PartitionWriter {
+    fn new(file: File, table: &ExportTable, format: ExportFormat) -> Result<Self, String> {
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = BufWriter::new(file);
+                let header = table.columns.iter().map(|c| c.name).collect::<Vec<_>>();
+                writeln!(writer, "{}", header.join(",")).map_err(|e| e.to_string())?;
+                Ok(PartitionWriter::Csv(writer))
+            }
+            ExportFormat::Parquet => {
+                let schema = Arc::new(parquet_schema(table));
+                let writer =
+                    ArrowWriter::try_new(file, schema.clone(), None).map_err(|e| e.to_string())?;
+                Ok(PartitionWriter::Parquet {
+                    writer: Box::new(writer),
+                    schema,
+                })
+            }
+        }
+    }
+
+    fn write_rows(&mut self, table: &ExportTable, rows: &[Row]) -> Result<(), String> {
+        match self {
+            PartitionWriter::Csv(writer) => {
+                for row in rows.iter() {
+                    let values = table
+                        .columns
+                        .iter()
+                        .enumerate()
+                        .map(|(i, c)| {
+                            column_value(row, i, c.kind)
+                                .map(|v| csv_field(&v))
+                                .unwrap_or_default()
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(writer, "{}", values.join(",")).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+            PartitionWriter::Parquet { writer, schema } => {
+                if rows.is_empty() {
+                    return Ok(());
+                }
+                let columns = table
+                    .columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| parquet_column(rows, i, c.kind))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let batch =
+                    RecordBatch::try_new(schema.clone(), columns).map_err(|e| e.to_string())?;
+                writer.write(&batch).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            PartitionWriter::Csv(mut writer) => writer.flush().map_err(|e| e.to_string()),
+            PartitionWriter::Parquet { writer, .. } => {
+                writer.close().map(|_| ()).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Streams the rows of `table` between `start_block` and `end_block` into `path`. The file is written next to its final
+/// location first and renamed once complete, so readers never pick up a partial partition. Returns the number of rows.
+pub async fn export_partition(
+    client: &mut Client,
+    table: &ExportTable,
+    start_block: u64,
+    end_block: u64,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<u64, String> {
+    let columns = table
+        .columns
+        .iter()
+        .map(|c| match c.kind {
+            ColumnKind::Enum => format!("{}::TEXT", c.name),
+            _ => c.name.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let query = format!(
+        "SELECT {columns} FROM {} WHERE block_height BETWEEN $1 AND $2 ORDER BY {}",
+        table.name, table.order_by
+    );
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("unable to create {}: {e}", dir.display()))?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let file = File::create(&tmp_path)
+        .map_err(|e| format!("unable to create {}: {e}", tmp_path.display()))?;
+    let write_err = |e: String| format!("unable to write {}: {e}", tmp_path.display());
+    let mut writer = PartitionWriter::new(file, table, format).map_err(write_err)?;
+
+    let db_tx = client
+        .transaction()
+        .await
+        .map_err(|e| format!("unable to begin transaction: {e}"))?;
+    let portal = db_tx
+        .bind(
+            &query,
+            &[&PgNumericU64(start_block), &PgNumericU64(end_block)],
+        )
+        .await
+        .map_err(|e| format!("error querying {}: {e}", table.name))?;
+    let mut count = 0;
+    loop {
+        let rows = db_tx
+            .query_portal(&portal, FETCH_SIZE)
+            .await
+            .map_err(|e| format!("error reading {}: {e}", table.name))?;
+        writer.write_rows(table, &rows).map_err(write_err)?;
+        count += rows.len() as u64;
+        if rows.len() < FETCH_SIZE as usize {
+            break;
+        }
+    }
+    db_tx
+        .commit()
+        .await
+        .map_err(|e| format!("unable to close transaction: {e}"))?;
+
+    writer.finish().map_err(write_err)?;
+    fs::rename(&tmp_path, path)
+        .map_err(|e| format!("unable to move export to {}: {e}", path.display()))?;
+    Ok(count)
+}
+
+/// Exports `tables` into `out_dir`, one file per table and block partition.
+#[cfg_attr(test, mutants::skip)]
+pub async fn export_tables(
+    client: &mut Client,
+    tables: &[&ExportTable],
+    partitions: &[(u64, u64)],
+    format: ExportFormat,
+    out_dir: &Path,
+    ctx: &Context,
+) -> Result<(), String> {
+    for &(start, end) in partitions.iter() {
+        for table in tables.iter() {
+            let path = partition_path(out_dir, table, start, end, format);
+            let count = export_partition(client, table, start, end, format, &path).await?;
+            try_info!(
+                ctx,
+                "Exported {} {} rows for blocks {}-{} to {}",
+                count,
+                table.name,
+                start,
+                end,
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use arrow::{
+        array::{Array, Decimal256Array, StringArray, UInt64Array},
+        datatypes::i256,
+    };
+    use chainhook_sdk::utils::Context;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use test_case::test_case;
+    use tokio_postgres::Client;
+
+    use crate::db::{pg_test_drop_schema, pg_test_schema_client};
+
+    use super::{
+        block_partitions, csv_field, export_partition, parse_export_tables, partition_path,
+        ExportFormat,
+    };
+
+    #[test_case(1, 10, 5 => vec![(1, 5), (6, 10)]; "even")]
+    #[test_case(1, 11, 5 => vec![(1, 5), (6, 10), (11, 11)]; "remainder")]
+    #[test_case(7, 7, 100 => vec![(7, 7)]; "single block")]
+    #[test_case(8, 7, 100 => Vec::<(u64, u64)>::new(); "empty")]
+    fn partitions_block_ranges(start: u64, end: u64, size: u64) -> Vec<(u64, u64)> {
+        block_partitions(start, end, size)
+    }
+
+    #[test_case("bc1q" => "bc1q"; "plain")]
+    #[test_case("a,b" => "\"a,b\""; "comma")]
+    #[test_case("say \"hi\"" => "\"say \"\"hi\"\"\""; "quotes")]
+    fn escapes_csv_fields(value: &str) -> String {
+        csv_field(value)
+    }
+
+    #[test]
+    fn parses_tables() {
+        let tables = parse_export_tables("ledger, runes").unwrap();
+        assert_eq!(
+            tables.iter().map(|t| t.name).collect::<Vec<_>>(),
+            vec!["ledger", "runes"]
+        );
+        assert!(parse_export_tables("ledger,inscriptions").is_err());
+    }
+
+    #[test_case("csv" => Ok(ExportFormat::Csv); "csv")]
+    #[test_case("Parquet" => Ok(ExportFormat::Parquet); "parquet")]
+    #[test_case("json" => Err("invalid export format json, expected `csv` or `parquet`".to_string()); "unknown")]
+    fn parses_formats(format: &str) -> Result<ExportFormat, String> {
+        format.parse()
+    }
+
+    async fn insert_test_ledger(client: &Client) {
+        client
+            .batch_execute(
+                "INSERT INTO ledger (rune_id, block_hash, block_height, tx_index, event_index, tx_id, output, address,
+                    amount, operation, timestamp)
+                VALUES
+                    ('1:0', 'h1', 840001, 0, 0, 't1', NULL, NULL, 340282366920938463463374607431768211455, 'mint', 1),
+                    ('1:0', 'h1', 840001, 0, 1, 't1', 0, 'a', 100, 'receive', 1),
+                    ('1:0', 'h2', 840002, 0, 0, 't2', NULL, 'a', 40, 'send', 2);",
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn exports_partition_to_csv() {
+        const SCHEMA: &str = "test_export";
+        let ctx = Context::empty();
+        let mut client = pg_test_schema_client(SCHEMA, &ctx).await;
+        insert_test_ledger(&client).await;
+
+        let table = &parse_export_tables("ledger").unwrap()[0];
+        let dir = std::env::temp_dir().join("runehook_test_export");
+        let path = partition_path(&dir, table, 840000, 840001, ExportFormat::Csv);
+        let count = export_partition(&mut client, table, 840000, 840001, ExportFormat::Csv, &path)
+            .await
+            .unwrap();
+        pg_test_drop_schema(&client, SCHEMA).await;
+
+        assert_eq!(count, 2);
+        assert!(path.ends_with("ledger/ledger_0000840000_0000840001.csv"));
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(
+            contents,
+            "rune_id,block_hash,block_height,tx_index,event_index,tx_id,output,address,receiver_address,amount,operation,timestamp,script_pubkey,script_type,output_value,tx_fee,tx_vsize
+1:0,h1,840001,0,0,t1,,,,340282366920938463463374607431768211455,mint,1,,,,,
+1:0,h1,840001,0,1,t1,0,a,,100,receive,1,,,,,
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn exports_partition_to_parquet() {
+        const SCHEMA: &str = "test_export_parquet";
+        let ctx = Context::empty();
+        let mut client = pg_test_schema_client(SCHEMA, &ctx).await;
+        insert_test_ledger(&client).await;
+
+        let table = &parse_export_tables("ledger").unwrap()[0];
+        let dir = std::env::temp_dir().join("runehook_test_export_parquet");
+        let path = partition_path(&dir, table, 840000, 840001, ExportFormat::Parquet);
+        let count = export_partition(
+            &mut client,
+            table,
+            840000,
+            840001,
+            ExportFormat::Parquet,
+            &path,
+        )
+        .await
+        .unwrap();
+        pg_test_drop_schema(&client, SCHEMA).await;
+
+        assert_eq!(count, 2);
+        assert!(path.ends_with("ledger/ledger_0000840000_0000840001.parquet"));
+        let batches = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_columns(), table.columns.len());
+        assert_eq!(batch.num_rows(), 2);
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        let amounts = column("amount");
+        let amounts = amounts.as_any().downcast_ref::<Decimal256Array>().unwrap();
+        assert_eq!(amounts.value(0), i256::from_parts(u128::MAX, 0));
+        assert_eq!(amounts.value(1), i256::from_parts(100, 0));
+        let heights = column("block_height");
+        let heights = heights.as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(heights.values().to_vec(), vec![840001, 840001]);
+        let operations = column("operation");
+        let operations = operations.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(operations.value(0), "mint");
+        let addresses = column("address");
+        assert!(addresses.is_null(0));
+        assert_eq!(
+            addresses
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .value(1),
+            "a"
+        );
+    }
+}
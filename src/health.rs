@@ -0,0 +1,203 @@
+use chainhook_sdk::utils::Context;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server,
+};
+
+use crate::{monitoring::PrometheusMonitoring, try_debug, try_info, try_warn};
+
+/// Lifecycle state of `service start`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServiceState {
+    /// Connecting to dependencies, nothing has been indexed yet.
+    Starting = 0,
+    /// Scanning blocks via RPC until the indexer reaches the bitcoind chain tip.
+    CatchingUp = 1,
+    /// At the chain tip and receiving new blocks from the Chainhook SDK observer.
+    Streaming = 2,
+    /// Sleeping because `MAINTENANCE_MODE` is set.
+    Maintenance = 3,
+    /// A termination signal was received and the service is finishing its current block.
+    ShuttingDown = 4,
+}
+
+impl ServiceState {
+    pub fn from_u64(value: u64) -> Self {
+        match value {
+            1 => ServiceState::CatchingUp,
+            2 => ServiceState::Streaming,
+            3 => ServiceState::Maintenance,
+            4 => ServiceState::ShuttingDown,
+            _ => ServiceState::Starting,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            ServiceState::Starting => "starting",
+            ServiceState::CatchingUp => "catching-up",
+            ServiceState::Streaming => "streaming",
+            ServiceState::Maintenance => "maintenance",
+            ServiceState::ShuttingDown => "shutting-down",
+        }
+    }
+}
+
+/// Body returned by the health and readiness endpoints.
+#[derive(Serialize, Debug, Clone)]
+pub struct HealthStatus {
+    pub state: String,
+    pub ready: bool,
+    pub indexed_block_height: u64,
+    pub bitcoind_block_height: u64,
+    pub blocks_behind: u64,
+    pub last_block_indexed_timestamp: Option<u64>,
+}
+
+impl HealthStatus {
+    /// Builds a health report from the indexer metrics. The service is considered ready while it is catching up or streaming
+    /// and it is not behind bitcoind by more than `max_blocks_behind` blocks.
+    pub fn from_monitoring(monitoring: &PrometheusMonitoring, max_blocks_behind: u64) -> Self {
+        let state = ServiceState::from_u64(monitoring.service_state.get());
+        let indexed_block_height = monitoring.indexed_block_height.get();
+        let bitcoind_block_height = monitoring.bitcoind_block_height.get();
+        let blocks_behind = bitcoind_block_height.saturating_sub(indexed_block_height);
+        let last_block_indexed_timestamp = match monitoring.last_block_indexed_timestamp.get() {
+            0 => None,
+            timestamp => Some(timestamp),
+        };
+        let ready = matches!(state, ServiceState::CatchingUp | ServiceState::Streaming)
+            && bitcoind_block_height > 0
+            && blocks_behind <= max_blocks_behind;
+        HealthStatus {
+            state: state.as_str().to_string(),
+            ready,
+            indexed_block_height,
+            bitcoind_block_height,
+            blocks_behind,
+            last_block_indexed_timestamp,
+        }
+    }
+}
+
+async fn serve_req(
+    req: Request<Body>,
+    monitoring: PrometheusMonitoring,
+    max_blocks_behind: u64,
+    ctx: Context,
+) -> Result<Response<Body>, hyper::Error> {
+    let status = HealthStatus::from_monitoring(&monitoring, max_blocks_behind);
+    let code = match (req.method(), req.uri().path()) {
+        // Liveness: the process is up and answering requests.
+        (&Method::GET, "/health") => 200,
+        // Readiness: the index is close enough to the chain tip to be served.
+        (&Method::GET, "/ready") => {
+            if status.ready {
+                200
+            } else {
+                503
+            }
+        }
+        (_, _) => {
+            try_debug!(
+                ctx,
+                "Health check: received request with invalid method/route: {}/{}",
+                req.method(),
+                req.uri().path()
+            );
+            return Ok(Response::builder().status(404).body(Body::empty()).unwrap());
+        }
+    };
+    let body = serde_json::to_vec(&status).unwrap_or_default();
+    Ok(Response::builder()
+        .status(code)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap())
+}
+
+#[cfg_attr(test, mutants::skip)]
+pub async fn start_serving_health_checks(
+    port: u16,
+    monitoring: PrometheusMonitoring,
+    max_blocks_behind: u64,
+    ctx: Context,
+) {
+    let addr = ([0, 0, 0, 0], port).into();
+    let ctx_clone = ctx.clone();
+    let make_svc = make_service_fn(|_| {
+        let monitoring = monitoring.clone();
+        let ctx_clone = ctx_clone.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |r| {
+                serve_req(r, monitoring.clone(), max_blocks_behind, ctx_clone.clone())
+            }))
+        }
+    });
+    let serve_future = Server::bind(&addr).serve(make_svc);
+    try_info!(ctx, "Health check: listening on port {}", port);
+    if let Err(err) = serve_future.await {
+        try_warn!(ctx, "Health check: server error: {}", err);
+    }
+}
+
+/// Starts the health check server in its own thread.
+#[cfg_attr(test, mutants::skip)]
+pub fn start_health_server_runloop(
+    port: u16,
+    monitoring: &PrometheusMonitoring,
+    max_blocks_behind: u64,
+    ctx: &Context,
+) {
+    let monitoring = monitoring.clone();
+    let ctx = ctx.clone();
+    let _ = hiro_system_kit::thread_named("Health Check Runloop").spawn(move || {
+        hiro_system_kit::nestable_block_on(start_serving_health_checks(
+            port,
+            monitoring,
+            max_blocks_behind,
+            ctx,
+        ));
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::monitoring::PrometheusMonitoring;
+
+    use super::{HealthStatus, ServiceState};
+
+    #[test_case(ServiceState::Starting, 840000, 840000 => false; "starting")]
+    #[test_case(ServiceState::Maintenance, 840000, 840000 => false; "maintenance")]
+    #[test_case(ServiceState::ShuttingDown, 840000, 840000 => false; "shutting down")]
+    #[test_case(ServiceState::CatchingUp, 839990, 840000 => false; "catching up far behind")]
+    #[test_case(ServiceState::CatchingUp, 839997, 840000 => true; "catching up within threshold")]
+    #[test_case(ServiceState::Streaming, 840000, 840000 => true; "streaming at tip")]
+    #[test_case(ServiceState::Streaming, 0, 0 => false; "bitcoind height unknown")]
+    fn readiness_depends_on_state_and_lag(
+        state: ServiceState,
+        indexed_block_height: u64,
+        bitcoind_block_height: u64,
+    ) -> bool {
+        let monitoring = PrometheusMonitoring::new();
+        monitoring.metrics_set_service_state(state);
+        monitoring.metrics_set_indexed_block_height(indexed_block_height);
+        monitoring.metrics_set_bitcoind_block_height(bitcoind_block_height);
+        HealthStatus::from_monitoring(&monitoring, 3).ready
+    }
+
+    #[test]
+    fn reports_state_and_lag() {
+        let monitoring = PrometheusMonitoring::new();
+        monitoring.metrics_set_service_state(ServiceState::CatchingUp);
+        monitoring.metrics_set_bitcoind_block_height(840010);
+        monitoring.metrics_block_indexed(840000, 0.1);
+        let status = HealthStatus::from_monitoring(&monitoring, 3);
+        assert_eq!(status.state, "catching-up");
+        assert_eq!(status.blocks_behind, 10);
+        assert!(status.last_block_indexed_timestamp.is_some());
+    }
+}
@@ -0,0 +1,147 @@
+use std::{str::FromStr, sync::Mutex};
+
+use chainhook_sdk::utils::Context;
+use hiro_system_kit::{
+    slog::{self, o, Drain, Level, Logger},
+    slog_async, slog_term,
+};
+
+use crate::config::LogConfig;
+
+/// Output format of the log lines written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Human readable lines, structured fields are appended as `key: value` pairs.
+    Text,
+    /// One JSON object per line with `ts`, `level`, `module`, `msg` and any structured fields.
+    Json,
+}
+
+impl LogFormat {
+    pub fn as_str(&self) -> &str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("invalid log format {s}, expected `text` or `json`")),
+        }
+    }
+}
+
+/// Parses a log level name such as `info` or `debug`.
+pub fn parse_log_level(level: &str) -> Result<Level, String> {
+    match level.to_lowercase().as_str() {
+        "critical" => Ok(Level::Critical),
+        "error" => Ok(Level::Error),
+        "warn" | "warning" => Ok(Level::Warning),
+        "info" => Ok(Level::Info),
+        "debug" => Ok(Level::Debug),
+        "trace" => Ok(Level::Trace),
+        _ => Err(format!(
+            "invalid log level {level}, expected one of `critical`, `error`, `warning`, `info`, `debug`, `trace`"
+        )),
+    }
+}
+
+#[cfg_attr(test, mutants::skip)]
+fn build_logger(format: LogFormat, level: Level, module: &'static str) -> Logger {
+    match format {
+        LogFormat::Json => {
+            let drain = slog_json::Json::new(std::io::stderr())
+                .add_default_keys()
+                .build();
+            let drain = Mutex::new(drain).map(slog::Fuse);
+            let drain = slog::LevelFilter::new(drain, level).fuse();
+            Logger::root(drain, o!("module" => module))
+        }
+        LogFormat::Text => {
+            let decorator = slog_term::TermDecorator::new().build();
+            let drain = slog_term::FullFormat::new(decorator)
+                .use_custom_header_print(hiro_system_kit::log::custom_print_msg_header)
+                .build()
+                .fuse();
+            let drain = slog_async::Async::new(drain).build().fuse();
+            let drain = slog::LevelFilter::new(drain, level).fuse();
+            Logger::root(drain, o!())
+        }
+    }
+}
+
+impl LogConfig {
+    /// Context used by the runes indexer. Logs are discarded if `runes_internals` is disabled.
+    #[cfg_attr(test, mutants::skip)]
+    pub fn runes_context(&self) -> Context {
+        Context {
+            logger: self
+                .runes_internals
+                .then(|| build_logger(self.format, self.level, "runehook")),
+            tracer: false,
+        }
+    }
+
+    /// Context handed to the Chainhook SDK event observer. Logs are discarded if `chainhook_internals` is disabled.
+    #[cfg_attr(test, mutants::skip)]
+    pub fn chainhook_context(&self) -> Context {
+        Context {
+            logger: self
+                .chainhook_internals
+                .then(|| build_logger(self.format, self.chainhook_level, "chainhook")),
+            tracer: false,
+        }
+    }
+}
+
+/// Returns a copy of `ctx` whose log lines carry the given block height as a structured field.
+pub fn with_block_height(ctx: &Context, block_height: u64) -> Context {
+    Context {
+        logger: ctx
+            .logger
+            .as_ref()
+            .map(|l| l.new(o!("block_height" => block_height))),
+        tracer: ctx.tracer,
+    }
+}
+
+/// Returns a copy of `ctx` whose log lines carry the given transaction id as a structured field.
+pub fn with_tx_id(ctx: &Context, tx_id: &str) -> Context {
+    Context {
+        logger: ctx
+            .logger
+            .as_ref()
+            .map(|l| l.new(o!("tx_id" => tx_id.to_string()))),
+        tracer: ctx.tracer,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hiro_system_kit::slog::Level;
+    use test_case::test_case;
+
+    use super::{parse_log_level, LogFormat};
+
+    #[test_case("info" => Ok(Level::Info); "info")]
+    #[test_case("DEBUG" => Ok(Level::Debug); "uppercase")]
+    #[test_case("warning" => Ok(Level::Warning); "warning")]
+    #[test_case("verbose" => matches Err(_); "invalid")]
+    fn parses_log_levels(level: &str) -> Result<Level, String> {
+        parse_log_level(level)
+    }
+
+    #[test_case("text" => Ok(LogFormat::Text); "text")]
+    #[test_case("JSON" => Ok(LogFormat::Json); "json")]
+    #[test_case("xml" => matches Err(_); "invalid")]
+    fn parses_log_formats(format: &str) -> Result<LogFormat, String> {
+        format.parse()
+    }
+}
@@ -0,0 +1,558 @@
+//! Optional tracking of unconfirmed rune activity. Transactions published by bitcoind on its ZeroMQ `rawtx` topic are run
+//! through `TransactionCache` against the confirmed index, and the resulting movements are written to `pending_ledger` so
+//! wallets can show incoming runes before they confirm. Nothing in this module touches the confirmed tables.
+//!
+//! Pending movements are provisional:
+//! * Inputs are only resolved against confirmed outputs, so a transaction spending another unconfirmed transaction only
+//!   shows the runes of its confirmed inputs.
+//! * Etchings are ignored because the id of the etched rune depends on the block that confirms it.
+//! * Mints are checked against the confirmed mint count, so pending mints may exceed the cap of a rune.
+//!
+//! Rows are deleted once their transaction is indexed from a block, or `mempool.expiry_seconds` after it was first seen.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bitcoin::{consensus::deserialize, Transaction};
+use chainhook_sdk::utils::Context;
+use ordinals::{Artifact, RuneId, Runestone};
+use tokio_postgres::{types::ToSql, Client, GenericClient};
+
+use crate::{
+    config::Config,
+    db::{
+        cache::{
+            transaction_cache::TransactionCache, transaction_location::TransactionLocation,
+            utils::merge_input_rune_balances,
+        },
+        index::get_eligible_outputs,
+        models::db_ledger_entry::DbLedgerEntry,
+        pg_connect, pg_get_block_height, pg_get_rune_total_mints,
+        storage::StorageTransaction,
+    },
+    logging::with_tx_id,
+    shutdown::ShutdownSignal,
+    try_debug, try_info, try_warn,
+};
+
+/// How often rows of transactions that never confirmed are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Computes the rune movements `transaction` would produce if it was included in the block after the confirmed chain tip,
+/// which `location` describes.
+pub async fn pending_ledger_entries(
+    transaction: &Transaction,
+    location: TransactionLocation,
+    rune_total_mints: &HashMap<RuneId, u128>,
+    db_tx: &mut impl StorageTransaction,
+    ctx: &Context,
+) -> Vec<DbLedgerEntry> {
+    let outputs: Vec<(u32, String, u32)> = transaction
+        .input
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            (
+                i as u32,
+                input.previous_output.txid.to_string(),
+                input.previous_output.vout,
+            )
+        })
+        .collect();
+    let input_runes = if outputs.is_empty() {
+        HashMap::new()
+    } else {
+        merge_input_rune_balances(&db_tx.get_input_rune_balances(outputs, ctx).await)
+    };
+    let artifact = Runestone::decipher(transaction);
+    if input_runes.is_empty() && artifact.is_none() {
+        return vec![];
+    }
+
+    let (eligible_outputs, first_eligible_output) = get_eligible_outputs(&transaction.output);
+    let mut tx_cache = TransactionCache::new(
+        location,
+        input_runes,
+        eligible_outputs,
+        first_eligible_output,
+        transaction.output.len() as u32,
+    );
+    let mut entries = vec![];
+    match artifact {
+        Some(Artifact::Runestone(runestone)) => {
+            if let Some(pointer) = runestone.pointer {
+                tx_cache.output_pointer = Some(pointer);
+            }
+            if let Some(rune_id) = runestone.mint {
+                if let Some(db_rune) = db_tx.get_rune_by_id(&rune_id, ctx).await {
+                    let total_mints = rune_total_mints
+                        .get(&db_rune.rune_id())
+                        .copied()
+                        .unwrap_or(0);
+                    entries.extend(tx_cache.apply_mint(&rune_id, total_mints, &db_rune, ctx));
+                }
+            }
+            for edict in runestone.edicts.iter() {
+                // Also skips edicts for the rune etched by this transaction, see the module docs.
+                if db_tx.get_rune_by_id(&edict.id, ctx).await.is_some() {
+                    entries.extend(tx_cache.apply_edict(edict, ctx));
+                }
+            }
+        }
+        Some(Artifact::Cenotaph(cenotaph)) => {
+            entries.extend(tx_cache.apply_cenotaph_input_burn(&cenotaph));
+            if let Some(rune_id) = cenotaph.mint {
+                if let Some(db_rune) = db_tx.get_rune_by_id(&rune_id, ctx).await {
+                    let total_mints = rune_total_mints
+                        .get(&db_rune.rune_id())
+                        .copied()
+                        .unwrap_or(0);
+                    entries.extend(tx_cache.apply_cenotaph_mint(
+                        &rune_id,
+                        total_mints,
+                        &db_rune,
+                        ctx,
+                    ));
+                }
+            }
+        }
+        None => {}
+    }
+    entries.extend(tx_cache.allocate_remaining_balances(ctx));
+    entries
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Inserts the movements of a pending transaction, ignoring the ones already recorded when bitcoind announces it again.
+pub async fn pg_insert_pending_ledger_entries<T: GenericClient>(
+    rows: &[DbLedgerEntry],
+    expires_at: i64,
+    client: &T,
+) -> Result<(), String> {
+    for chunk in rows.chunks(500) {
+        let mut arg_num = 1;
+        let mut arg_str = String::new();
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+        for row in chunk.iter() {
+            arg_str.push('(');
+            for i in 0..12 {
+                arg_str.push_str(format!("${},", arg_num + i).as_str());
+            }
+            arg_str.pop();
+            arg_str.push_str("),");
+            arg_num += 12;
+            params.push(&row.rune_id);
+            params.push(&row.tx_id);
+            params.push(&row.event_index);
+            params.push(&row.output);
+            params.push(&row.address);
+            params.push(&row.receiver_address);
+            params.push(&row.amount);
+            params.push(&row.operation);
+            params.push(&row.timestamp);
+            params.push(&row.script_pubkey);
+            params.push(&row.script_type);
+            params.push(&expires_at);
+        }
+        arg_str.pop();
+        client
+            .query(
+                &format!("INSERT INTO pending_ledger
+                    (rune_id, tx_id, event_index, output, address, receiver_address, amount, operation, timestamp, script_pubkey,
+                    script_type, expires_at)
+                    VALUES {}
+                    ON CONFLICT (tx_id, event_index) DO NOTHING", arg_str),
+                &params,
+            )
+            .await
+            .map_err(|e| format!("error inserting pending ledger entries: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Returns true if `tx_id` already has confirmed rune activity.
+async fn pg_is_transaction_indexed<T: GenericClient>(
+    tx_id: &String,
+    client: &T,
+) -> Result<bool, String> {
+    let row = client
+        .query_one(
+            "SELECT EXISTS(SELECT 1 FROM ledger WHERE tx_id = $1)",
+            &[tx_id],
+        )
+        .await
+        .map_err(|e| format!("error looking up transaction {tx_id}: {e}"))?;
+    Ok(row.get(0))
+}
+
+/// Deletes the pending rows of transactions that have since been indexed from a block.
+pub async fn pg_delete_confirmed_pending_ledger_entries<T: GenericClient>(
+    client: &T,
+) -> Result<u64, String> {
+    client
+        .execute(
+            "DELETE FROM pending_ledger AS p USING ledger AS l WHERE p.tx_id = l.tx_id",
+            &[],
+        )
+        .await
+        .map_err(|e| format!("error deleting confirmed pending ledger entries: {e}"))
+}
+
+/// Deletes the pending rows that expired at or before `now`.
+pub async fn pg_delete_expired_pending_ledger_entries<T: GenericClient>(
+    now: i64,
+    client: &T,
+) -> Result<u64, String> {
+    client
+        .execute("DELETE FROM pending_ledger WHERE expires_at <= $1", &[&now])
+        .await
+        .map_err(|e| format!("error deleting expired pending ledger entries: {e}"))
+}
+
+/// Counts the pending mints of every rune that has any.
+pub async fn pg_get_pending_mint_counts<T: GenericClient>(
+    client: &T,
+) -> Result<HashMap<RuneId, u128>, String> {
+    let rows = client
+        .query(
+            "SELECT rune_id, COUNT(*) AS mints FROM pending_ledger WHERE operation = 'mint' GROUP BY rune_id",
+            &[],
+        )
+        .await
+        .map_err(|e| format!("error counting pending mints: {e}"))?;
+    let mut results = HashMap::new();
+    for row in rows.iter() {
+        let rune_id: String = row.get("rune_id");
+        let mints: i64 = row.get("mints");
+        results.insert(RuneId::from_str(&rune_id).unwrap(), mints as u128);
+    }
+    Ok(results)
+}
+
+/// Decodes a `rawtx` notification and records its pending rune movements. Returns the number of rows computed.
+#[cfg_attr(test, mutants::skip)]
+async fn index_pending_transaction(
+    raw_tx: &[u8],
+    chain_tip: u64,
+    rune_total_mints: &HashMap<RuneId, u128>,
+    config: &Config,
+    client: &mut Client,
+    ctx: &Context,
+) -> Result<usize, String> {
+    let transaction: Transaction =
+        deserialize(raw_tx).map_err(|e| format!("unable to decode transaction: {e}"))?;
+    if transaction.is_coin_base() {
+        return Ok(0);
+    }
+    let tx_id = transaction.txid().to_string();
+    let tx_ctx = with_tx_id(ctx, &tx_id);
+    let ctx = &tx_ctx;
+    let mut db_tx = client
+        .transaction()
+        .await
+        .map_err(|e| format!("unable to begin pg transaction: {e}"))?;
+    // bitcoind also announces the transactions of every block it connects.
+    if pg_is_transaction_indexed(&tx_id, &db_tx).await? {
+        return Ok(0);
+    }
+    let now = unix_timestamp();
+    let location = TransactionLocation {
+        network: config.get_bitcoin_network(),
+        block_hash: "0x".to_string(),
+        block_height: chain_tip + 1,
+        timestamp: now as u32,
+        tx_index: 0,
+        tx_id: format!("0x{tx_id}"),
+    };
+    let entries =
+        pending_ledger_entries(&transaction, location, rune_total_mints, &mut db_tx, ctx).await;
+    if !entries.is_empty() {
+        let expires_at = (now + config.mempool.expiry_seconds) as i64;
+        pg_insert_pending_ledger_entries(&entries, expires_at, &db_tx).await?;
+        db_tx
+            .commit()
+            .await
+            .map_err(|e| format!("unable to commit pending ledger entries: {e}"))?;
+        try_debug!(ctx, "Recorded {} pending ledger entries", entries.len());
+    }
+    Ok(entries.len())
+}
+
+/// Confirmed state pending movements are computed against. Reloaded whenever the indexer commits a new block.
+struct ConfirmedState {
+    chain_tip: Option<u64>,
+    rune_total_mints: HashMap<RuneId, u128>,
+}
+
+#[cfg_attr(test, mutants::skip)]
+async fn refresh_confirmed_state(
+    state: &mut ConfirmedState,
+    client: &mut Client,
+    ctx: &Context,
+) -> Result<(), String> {
+    let chain_tip = pg_get_block_height(client, ctx).await;
+    if chain_tip == state.chain_tip {
+        return Ok(());
+    }
+    state.rune_total_mints = pg_get_rune_total_mints(client, ctx).await;
+    state.chain_tip = chain_tip;
+    let deleted = pg_delete_confirmed_pending_ledger_entries(client).await?;
+    if deleted > 0 {
+        try_debug!(ctx, "Deleted {} confirmed pending ledger entries", deleted);
+    }
+    Ok(())
+}
+
+/// Subscribes to `rawtx` notifications from `mempool.zmq_url` and records pending rune movements from a dedicated thread
+/// until shutdown is requested. Only supported with the postgres storage backend.
+#[cfg_attr(test, mutants::skip)]
+pub fn start_mempool_runloop(
+    config: &Config,
+    shutdown: &ShutdownSignal,
+    ctx: &Context,
+) -> Result<(), String> {
+    let Some(zmq_url) = config.mempool.zmq_url.clone() else {
+        return Err("mempool.zmq_url is not set".to_string());
+    };
+    let socket = zmq::Context::new()
+        .socket(zmq::SUB)
+        .map_err(|e| format!("unable to create zmq socket: {e}"))?;
+    socket
+        .connect(&zmq_url)
+        .map_err(|e| format!("unable to connect to {zmq_url}: {e}"))?;
+    socket
+        .set_subscribe(b"rawtx")
+        .map_err(|e| format!("unable to subscribe to rawtx: {e}"))?;
+    // Wake up regularly to notice new blocks, expired rows and shutdown requests while the mempool is quiet.
+    socket
+        .set_rcvtimeo(1000)
+        .map_err(|e| format!("unable to set zmq receive timeout: {e}"))?;
+
+    let config = config.clone();
+    let shutdown = shutdown.clone();
+    let ctx = ctx.clone();
+    let _ = hiro_system_kit::thread_named("Mempool Runloop").spawn(move || {
+        hiro_system_kit::nestable_block_on(async {
+            let mut client = pg_connect(&config, false, &ctx).await;
+            let mut state = ConfirmedState {
+                chain_tip: None,
+                rune_total_mints: HashMap::new(),
+            };
+            let mut next_prune = Instant::now();
+            try_info!(ctx, "Listening for mempool transactions on {}", zmq_url);
+            while !shutdown.is_requested() {
+                if let Err(e) = refresh_confirmed_state(&mut state, &mut client, &ctx).await {
+                    try_warn!(ctx, "{}", e);
+                }
+                if Instant::now() >= next_prune {
+                    match pg_delete_expired_pending_ledger_entries(unix_timestamp() as i64, &client)
+                        .await
+                    {
+                        Ok(deleted) if deleted > 0 => {
+                            try_info!(ctx, "Deleted {} expired pending ledger entries", deleted);
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            try_warn!(ctx, "{}", e);
+                        }
+                    }
+                    next_prune = Instant::now() + PRUNE_INTERVAL;
+                }
+                let message = match socket.recv_multipart(0) {
+                    Ok(message) => message,
+                    Err(zmq::Error::EAGAIN) => continue,
+                    Err(e) => {
+                        try_warn!(ctx, "Error receiving mempool transaction: {}", e);
+                        continue;
+                    }
+                };
+                // Messages are made of the topic, the serialized transaction and a sequence number.
+                let (Some(topic), Some(raw_tx)) = (message.first(), message.get(1)) else {
+                    continue;
+                };
+                if topic.as_slice() != b"rawtx" {
+                    continue;
+                }
+                // Nothing can be pending before the first block with rune activity is indexed.
+                let Some(chain_tip) = state.chain_tip else {
+                    continue;
+                };
+                if let Err(e) = index_pending_transaction(
+                    raw_tx,
+                    chain_tip,
+                    &state.rune_total_mints,
+                    &config,
+                    &mut client,
+                    &ctx,
+                )
+                .await
+                {
+                    try_warn!(ctx, "Error indexing mempool transaction: {}", e);
+                }
+            }
+            try_info!(ctx, "Mempool runloop stopped");
+        });
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, str::FromStr};
+
+    use bitcoin::{
+        absolute::LockTime, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    };
+    use chainhook_sdk::utils::Context;
+    use ordinals::{Edict, Etching, Rune, RuneId, Runestone};
+
+    use crate::db::{
+        cache::transaction_location::TransactionLocation,
+        fixtures::{index_blocks, owner_address, owner_script, tx_id, BlockBuilder, TxBuilder},
+        models::{db_ledger_entry::DbLedgerEntry, db_ledger_operation::DbLedgerOperation},
+        storage::Storage,
+    };
+
+    use super::pending_ledger_entries;
+
+    const RUNE_ID: RuneId = RuneId {
+        block: 840000,
+        tx: 1,
+    };
+
+    /// Block 840000 etching `RUNE_ID` with 1000 units premined to `owner_1` in output 1 of `840000:1`.
+    fn premined_block() -> BlockBuilder {
+        BlockBuilder::new(840000).tx(TxBuilder::new()
+            .fund()
+            .runestone(&Runestone {
+                etching: Some(Etching {
+                    divisibility: Some(0),
+                    premine: Some(1000),
+                    rune: Some(Rune::from_str("ZZZZZFEHUZZZZZ").unwrap()),
+                    spacers: None,
+                    symbol: None,
+                    terms: None,
+                    turbo: false,
+                }),
+                ..Default::default()
+            })
+            .to(1))
+    }
+
+    fn transaction(inputs: Vec<(String, u32)>, outputs: Vec<ScriptBuf>) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .into_iter()
+                .map(|(tx_id, vout)| TxIn {
+                    previous_output: OutPoint {
+                        txid: Txid::from_str(&tx_id).unwrap(),
+                        vout,
+                    },
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs
+                .into_iter()
+                .map(|script_pubkey| TxOut {
+                    value: 546,
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    fn location() -> TransactionLocation {
+        TransactionLocation {
+            network: bitcoin::Network::Bitcoin,
+            block_hash: "0x".to_string(),
+            block_height: 840001,
+            timestamp: 0,
+            tx_index: 0,
+            tx_id: format!("0x{}", "a".repeat(64)),
+        }
+    }
+
+    async fn pending(transaction: &Transaction) -> Vec<DbLedgerEntry> {
+        let ctx = Context::empty();
+        let (mut storage, _) = index_blocks(&[premined_block()]).await;
+        let mut db_tx = storage.begin().await.unwrap();
+        pending_ledger_entries(transaction, location(), &HashMap::new(), &mut db_tx, &ctx).await
+    }
+
+    #[tokio::test]
+    async fn transfers_confirmed_balance() {
+        let entries = pending(&transaction(
+            vec![(tx_id(840000, 1), 1)],
+            vec![owner_script(2)],
+        ))
+        .await;
+        let moves: Vec<_> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.operation.clone(),
+                    e.address.clone(),
+                    e.amount.map(|a| a.0),
+                )
+            })
+            .collect();
+        assert_eq!(
+            moves,
+            vec![
+                (
+                    DbLedgerOperation::Receive,
+                    Some(owner_address(2)),
+                    Some(1000)
+                ),
+                (DbLedgerOperation::Send, Some(owner_address(1)), Some(1000)),
+            ]
+        );
+        assert!(entries.iter().all(|e| e.tx_id == "a".repeat(64)));
+    }
+
+    #[tokio::test]
+    async fn splits_confirmed_balance_with_edicts() {
+        let runestone = Runestone {
+            edicts: vec![Edict {
+                id: RUNE_ID,
+                amount: 400,
+                output: 1,
+            }],
+            ..Default::default()
+        };
+        let entries = pending(&transaction(
+            vec![(tx_id(840000, 1), 1)],
+            vec![runestone.encipher(), owner_script(2), owner_script(3)],
+        ))
+        .await;
+        let received: Vec<_> = entries
+            .iter()
+            .filter(|e| e.operation == DbLedgerOperation::Receive)
+            .map(|e| (e.output.map(|o| o.0), e.amount.map(|a| a.0)))
+            .collect();
+        assert_eq!(received, vec![(Some(1), Some(400)), (Some(1), Some(600))]);
+    }
+
+    #[tokio::test]
+    async fn ignores_transactions_without_runes() {
+        let entries = pending(&transaction(
+            vec![(tx_id(840000, 1), 0)],
+            vec![owner_script(2)],
+        ))
+        .await;
+        assert!(entries.is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+//! Mint progress of runes with open terms, for projects watching a mint. Confirmed mints come from the supply totals the
+//! indexer keeps, pending mints from the `pending_ledger` table filled by the mempool runloop.
+
+use std::collections::HashMap;
+
+use chainhook_sdk::utils::Context;
+use ordinals::RuneId;
+
+use crate::db::{
+    models::{db_mint_status::DbMintStatus, db_rune::DbRune},
+    storage::{Storage, StorageTransaction},
+};
+
+/// Mint counters of a rune as seen from the block after the indexed chain tip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MintPressure {
+    pub rune_id: RuneId,
+    pub spaced_name: String,
+    pub terms_cap: Option<u128>,
+    pub confirmed_mints: u128,
+    /// Valid mints of unconfirmed transactions, `None` when mempool tracking is disabled.
+    pub pending_mints: Option<u128>,
+    /// Mints still allowed by the terms. `None` if the cap is unlimited, zero once the cap is reached or the height and
+    /// offset windows have ended.
+    pub remaining_mints: Option<u128>,
+    /// Status of mints included in the next block, `None` if the rune can never be minted.
+    pub status: Option<DbMintStatus>,
+    pub mint_start_height: Option<u64>,
+    pub mint_end_height: Option<u64>,
+}
+
+impl MintPressure {
+    /// Mints left once every pending mint confirms. Pending mints are only checked against confirmed mints, so this can
+    /// reach zero before the cap is actually hit on chain.
+    pub fn remaining_after_pending(&self) -> Option<u128> {
+        self.remaining_mints
+            .map(|remaining| remaining.saturating_sub(self.pending_mints.unwrap_or(0)))
+    }
+}
+
+/// Resolves the height and offset terms of `db_rune` into the absolute first and last block in which mints are valid.
+/// `None` means the window is unbounded on that side.
+pub fn mint_window(db_rune: &DbRune) -> (Option<u64>, Option<u64>) {
+    let etched_at = db_rune.block_height.0;
+    let start = [
+        db_rune.terms_height_start.map(|start| start.0),
+        db_rune
+            .terms_offset_start
+            .map(|offset| etched_at.saturating_add(offset.0)),
+    ]
+    .into_iter()
+    .flatten()
+    .max();
+    let end = [
+        db_rune.terms_height_end.map(|end| end.0),
+        db_rune
+            .terms_offset_end
+            .map(|offset| etched_at.saturating_add(offset.0)),
+    ]
+    .into_iter()
+    .flatten()
+    .min();
+    (start, end)
+}
+
+/// Classifies mints of `db_rune` included in the block at `block_height`, given the mints confirmed before it. Returns
+/// `None` for runes that can never be minted. Agrees with `is_rune_mintable`, which only holds for `DbMintStatus::Open`.
+pub fn mint_status(db_rune: &DbRune, total_mints: u128, block_height: u64) -> Option<DbMintStatus> {
+    if db_rune.cenotaph || db_rune.terms_amount.is_none() {
+        return None;
+    }
+    let (start, end) = mint_window(db_rune);
+    if db_rune.terms_cap.is_some_and(|cap| total_mints >= cap.0) {
+        Some(DbMintStatus::CapReached)
+    } else if end.is_some_and(|end| block_height > end) {
+        Some(DbMintStatus::WindowClosed)
+    } else if start.is_some_and(|start| block_height < start) {
+        Some(DbMintStatus::NotYetOpen)
+    } else {
+        Some(DbMintStatus::Open)
+    }
+}
+
+/// Computes the mint counters of `db_rune` for a mint included at `next_block_height`.
+pub fn mint_pressure(
+    db_rune: &DbRune,
+    confirmed_mints: u128,
+    pending_mints: Option<u128>,
+    next_block_height: u64,
+) -> MintPressure {
+    let status = mint_status(db_rune, confirmed_mints, next_block_height);
+    let closed = matches!(
+        status,
+        None | Some(DbMintStatus::CapReached) | Some(DbMintStatus::WindowClosed)
+    );
+    let terms_cap = db_rune.terms_cap.map(|cap| cap.0);
+    let (mint_start_height, mint_end_height) = mint_window(db_rune);
+    MintPressure {
+        rune_id: db_rune.rune_id(),
+        spaced_name: db_rune.spaced_name.clone(),
+        terms_cap,
+        confirmed_mints,
+        pending_mints,
+        remaining_mints: if closed {
+            Some(0)
+        } else {
+            terms_cap.map(|cap| cap.saturating_sub(confirmed_mints))
+        },
+        status,
+        mint_start_height,
+        mint_end_height,
+    }
+}
+
+/// Computes the mint counters of each of `rune_ids` from the indexed state. `pending_mints` holds the pending mint count of
+/// every rune that has any, or `None` when mempool tracking is disabled.
+pub async fn get_mint_pressure(
+    storage: &mut impl Storage,
+    rune_ids: &[RuneId],
+    pending_mints: Option<&HashMap<RuneId, u128>>,
+    ctx: &Context,
+) -> Result<Vec<MintPressure>, String> {
+    let Some(chain_tip) = storage.get_block_height(ctx).await else {
+        return Err("Nothing has been indexed yet".to_string());
+    };
+    let total_mints = storage.get_rune_total_mints(ctx).await;
+    let mut db_tx = storage.begin().await?;
+    let mut results = vec![];
+    for rune_id in rune_ids.iter() {
+        let Some(db_rune) = db_tx.get_rune_by_id(rune_id, ctx).await else {
+            return Err(format!("Rune {rune_id} not found"));
+        };
+        results.push(mint_pressure(
+            &db_rune,
+            total_mints.get(rune_id).copied().unwrap_or(0),
+            pending_mints.map(|mints| mints.get(rune_id).copied().unwrap_or(0)),
+            chain_tip + 1,
+        ));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, str::FromStr};
+
+    use chainhook_sdk::utils::Context;
+    use maplit::hashmap;
+    use ordinals::{Etching, Rune, RuneId, Runestone, Terms};
+    use test_case::test_case;
+
+    use crate::db::{
+        cache::utils::is_rune_mintable,
+        fixtures::{index_blocks, BlockBuilder, TxBuilder},
+        models::{
+            db_mint_status::DbMintStatus::{self, *},
+            db_rune::DbRune,
+        },
+        types::{pg_numeric_u128::PgNumericU128, pg_numeric_u64::PgNumericU64},
+    };
+
+    use super::{get_mint_pressure, mint_pressure, mint_status, mint_window};
+
+    const RUNE_ID: RuneId = RuneId {
+        block: 840000,
+        tx: 1,
+    };
+
+    /// A rune etched at block 840000 minting 100 units at a time, capped at 10 mints.
+    fn open_rune() -> DbRune {
+        let mut rune = DbRune::factory();
+        rune.terms_cap(Some(PgNumericU128(10)));
+        rune
+    }
+
+    #[test_case(0, 840150 => (Some(10), Some(Open)); "no mints")]
+    #[test_case(4, 840150 => (Some(6), Some(Open)); "some mints")]
+    #[test_case(10, 840150 => (Some(0), Some(CapReached)); "cap reached")]
+    #[test_case(10, 840201 => (Some(0), Some(CapReached)); "cap reached after window")]
+    #[test_case(4, 840201 => (Some(0), Some(WindowClosed)); "height window ended")]
+    #[test_case(4, 840200 => (Some(6), Some(Open)); "last block of height window")]
+    #[test_case(4, 840100 => (Some(6), Some(Open)); "first block of height window")]
+    #[test_case(4, 840050 => (Some(6), Some(NotYetOpen)); "height window not started")]
+    fn computes_remaining_mints(
+        confirmed_mints: u128,
+        next_block_height: u64,
+    ) -> (Option<u128>, Option<DbMintStatus>) {
+        let mut rune = open_rune();
+        rune.terms_height_start(Some(PgNumericU64(840100)));
+        rune.terms_height_end(Some(PgNumericU64(840200)));
+        let pressure = mint_pressure(&rune, confirmed_mints, None, next_block_height);
+        (pressure.remaining_mints, pressure.status)
+    }
+
+    #[test_case(Some(840100), Some(850000), None, None => (Some(840100), Some(850000)); "height terms")]
+    #[test_case(None, None, Some(10), Some(100) => (Some(840010), Some(840100)); "offset terms")]
+    #[test_case(Some(840100), Some(850000), Some(10), Some(100) => (Some(840100), Some(840100)); "narrowest window")]
+    #[test_case(None, Some(850000), None, Some(u64::MAX) => (None, Some(850000)); "offset overflow")]
+    #[test_case(None, None, None, None => (None, None); "unbounded")]
+    fn resolves_absolute_mint_window(
+        height_start: Option<u64>,
+        height_end: Option<u64>,
+        offset_start: Option<u64>,
+        offset_end: Option<u64>,
+    ) -> (Option<u64>, Option<u64>) {
+        let mut rune = open_rune();
+        rune.terms_height_start(height_start.map(PgNumericU64));
+        rune.terms_height_end(height_end.map(PgNumericU64));
+        rune.terms_offset_start(offset_start.map(PgNumericU64));
+        rune.terms_offset_end(offset_end.map(PgNumericU64));
+        mint_window(&rune)
+    }
+
+    #[test]
+    fn earliest_window_end_closes_mint() {
+        let mut rune = open_rune();
+        rune.terms_height_end(Some(PgNumericU64(850000)));
+        rune.terms_offset_end(Some(PgNumericU64(100)));
+        assert_eq!(
+            mint_pressure(&rune, 0, None, 840101).remaining_mints,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn runes_without_terms_have_no_status() {
+        let mut rune = open_rune();
+        rune.terms_amount = None;
+        assert_eq!(mint_status(&rune, 0, 840001), None);
+    }
+
+    #[test]
+    fn open_status_matches_is_rune_mintable() {
+        let mut rune = open_rune();
+        rune.terms_height_start(Some(PgNumericU64(840100)));
+        rune.terms_offset_end(Some(PgNumericU64(200)));
+        for total_mints in [0, 9, 10] {
+            for block_height in 840000..840300 {
+                assert_eq!(
+                    mint_status(&rune, total_mints, block_height) == Some(DbMintStatus::Open),
+                    is_rune_mintable(&rune, total_mints, block_height)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uncapped_mints_have_no_limit() {
+        let mut rune = open_rune();
+        rune.terms_cap(None);
+        assert_eq!(
+            mint_pressure(&rune, 1000, None, 840001).remaining_mints,
+            None
+        );
+    }
+
+    #[test_case(Some(3) => Some(3); "pending below remaining")]
+    #[test_case(Some(20) => Some(0); "pending above remaining")]
+    #[test_case(None => Some(6); "mempool disabled")]
+    fn subtracts_pending_mints(pending_mints: Option<u128>) -> Option<u128> {
+        mint_pressure(&open_rune(), 4, pending_mints, 840001).remaining_after_pending()
+    }
+
+    /// Block 840000 etching `RUNE_ID` with a cap of 10 mints.
+    fn etching_block() -> BlockBuilder {
+        BlockBuilder::new(840000).tx(TxBuilder::new().fund().runestone(&Runestone {
+            etching: Some(Etching {
+                divisibility: None,
+                premine: None,
+                rune: Some(Rune::from_str("ZZZZZFEHUZZZZZ").unwrap()),
+                spacers: None,
+                symbol: None,
+                terms: Some(Terms {
+                    amount: Some(100),
+                    cap: Some(10),
+                    height: (None, None),
+                    offset: (None, None),
+                }),
+                turbo: false,
+            }),
+            ..Default::default()
+        }))
+    }
+
+    #[tokio::test]
+    async fn reads_confirmed_mints_from_storage() {
+        let mint = Runestone {
+            mint: Some(RUNE_ID),
+            ..Default::default()
+        };
+        let (mut storage, _) = index_blocks(&[
+            etching_block(),
+            BlockBuilder::new(840001)
+                .tx(TxBuilder::new().fund().runestone(&mint).to(1))
+                .tx(TxBuilder::new().fund().runestone(&mint).to(2)),
+        ])
+        .await;
+        let pending: HashMap<RuneId, u128> = hashmap! { RUNE_ID => 5 };
+        let pressure =
+            get_mint_pressure(&mut storage, &[RUNE_ID], Some(&pending), &Context::empty())
+                .await
+                .unwrap();
+        assert_eq!(pressure[0].confirmed_mints, 2);
+        assert_eq!(pressure[0].pending_mints, Some(5));
+        assert_eq!(pressure[0].remaining_mints, Some(8));
+        assert_eq!(pressure[0].remaining_after_pending(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn counts_cenotaph_mints_towards_cap() {
+        // Cenotaph mints of `RUNE_ID`, flagged as cenotaphs by an unrecognized even tag.
+        let mut cenotaph_mints = BlockBuilder::new(840001);
+        for _ in 0..10 {
+            cenotaph_mints = cenotaph_mints.tx(TxBuilder::new()
+                .fund()
+                .payload(&[20, 840000, 20, 1, 126, 0])
+                .to(1));
+        }
+        let (mut storage, _) = index_blocks(&[etching_block(), cenotaph_mints]).await;
+        assert_eq!(
+            storage.tables.mint_statuses.get(&RUNE_ID.to_string()),
+            Some(&CapReached)
+        );
+        let pressure = get_mint_pressure(&mut storage, &[RUNE_ID], None, &Context::empty())
+            .await
+            .unwrap();
+        assert_eq!(pressure[0].confirmed_mints, 10);
+        assert_eq!(pressure[0].remaining_mints, Some(0));
+        assert_eq!(pressure[0].status, Some(CapReached));
+    }
+
+    #[tokio::test]
+    async fn fails_for_unknown_runes() {
+        let (mut storage, _) = index_blocks(&[etching_block()]).await;
+        let result = get_mint_pressure(
+            &mut storage,
+            &[RuneId::new(840000, 2).unwrap()],
+            None,
+            &Context::empty(),
+        )
+        .await;
+        assert_eq!(result, Err("Rune 840000:2 not found".to_string()));
+    }
+}
@@ -0,0 +1,308 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chainhook_sdk::utils::Context;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server,
+};
+use prometheus::{
+    core::{AtomicU64, GenericGauge},
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+use crate::{health::ServiceState, try_debug, try_info, try_warn};
+
+type UInt64Gauge = GenericGauge<AtomicU64>;
+
+/// Prometheus metrics reported by the indexer. Cloning this struct is cheap and all clones report to the same `Registry`.
+#[derive(Debug, Clone)]
+pub struct PrometheusMonitoring {
+    pub indexed_block_height: UInt64Gauge,
+    pub bitcoind_block_height: UInt64Gauge,
+    pub block_indexing_duration: Histogram,
+    pub rows_flushed: IntCounterVec,
+    pub cache_hits: IntCounterVec,
+    pub cache_misses: IntCounterVec,
+    pub rolled_back_blocks: IntCounter,
+    pub last_block_indexed_timestamp: UInt64Gauge,
+    pub service_state: UInt64Gauge,
+    pub registry: Registry,
+}
+
+impl Default for PrometheusMonitoring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrometheusMonitoring {
+    pub fn new() -> PrometheusMonitoring {
+        let registry = Registry::new();
+        let indexed_block_height = PrometheusMonitoring::create_and_register_uint64_gauge(
+            &registry,
+            "runehook_indexed_block_height",
+            "The highest Bitcoin block indexed for runes.",
+        );
+        let bitcoind_block_height = PrometheusMonitoring::create_and_register_uint64_gauge(
+            &registry,
+            "runehook_bitcoind_block_height",
+            "The highest Bitcoin block known to bitcoind.",
+        );
+        let block_indexing_duration = PrometheusMonitoring::create_and_register_histogram(
+            &registry,
+            "runehook_block_indexing_duration_seconds",
+            "Time spent indexing a single Bitcoin block, including the DB commit.",
+            vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0],
+        );
+        let rows_flushed = PrometheusMonitoring::create_and_register_int_counter_vec(
+            &registry,
+            "runehook_rows_flushed_total",
+            "Rows written to the DB, by table.",
+            &["table"],
+        );
+        let cache_hits = PrometheusMonitoring::create_and_register_int_counter_vec(
+            &registry,
+            "runehook_cache_hits_total",
+            "Index cache lookups answered from memory, by cache.",
+            &["cache"],
+        );
+        let cache_misses = PrometheusMonitoring::create_and_register_int_counter_vec(
+            &registry,
+            "runehook_cache_misses_total",
+            "Index cache lookups that had to fall back to the DB, by cache.",
+            &["cache"],
+        );
+        let rolled_back_blocks = PrometheusMonitoring::create_and_register_int_counter(
+            &registry,
+            "runehook_rolled_back_blocks_total",
+            "Bitcoin blocks rolled back because of re-orgs or maintenance operations.",
+        );
+        let last_block_indexed_timestamp = PrometheusMonitoring::create_and_register_uint64_gauge(
+            &registry,
+            "runehook_last_block_indexed_timestamp",
+            "The UNIX timestamp at which the indexer last finished indexing a Bitcoin block.",
+        );
+        let service_state = PrometheusMonitoring::create_and_register_uint64_gauge(
+            &registry,
+            "runehook_service_state",
+            "The current service state: 0 = starting, 1 = catching up, 2 = streaming, 3 = maintenance, 4 = shutting down.",
+        );
+        PrometheusMonitoring {
+            indexed_block_height,
+            bitcoind_block_height,
+            block_indexing_duration,
+            rows_flushed,
+            cache_hits,
+            cache_misses,
+            rolled_back_blocks,
+            last_block_indexed_timestamp,
+            service_state,
+            registry,
+        }
+    }
+
+    pub fn create_and_register_uint64_gauge(
+        registry: &Registry,
+        name: &str,
+        help: &str,
+    ) -> UInt64Gauge {
+        let g = UInt64Gauge::new(name, help).unwrap();
+        registry.register(Box::new(g.clone())).unwrap();
+        g
+    }
+
+    pub fn create_and_register_int_counter(
+        registry: &Registry,
+        name: &str,
+        help: &str,
+    ) -> IntCounter {
+        let c = IntCounter::new(name, help).unwrap();
+        registry.register(Box::new(c.clone())).unwrap();
+        c
+    }
+
+    pub fn create_and_register_int_counter_vec(
+        registry: &Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> IntCounterVec {
+        let c = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+        registry.register(Box::new(c.clone())).unwrap();
+        c
+    }
+
+    pub fn create_and_register_histogram(
+        registry: &Registry,
+        name: &str,
+        help: &str,
+        buckets: Vec<f64>,
+    ) -> Histogram {
+        let h = Histogram::with_opts(HistogramOpts::new(name, help).buckets(buckets)).unwrap();
+        registry.register(Box::new(h.clone())).unwrap();
+        h
+    }
+
+    pub fn metrics_block_indexed(&self, block_height: u64, elapsed_secs: f64) {
+        self.indexed_block_height.set(block_height);
+        self.block_indexing_duration.observe(elapsed_secs);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.last_block_indexed_timestamp.set(now);
+    }
+
+    pub fn metrics_set_indexed_block_height(&self, block_height: u64) {
+        self.indexed_block_height.set(block_height);
+    }
+
+    pub fn metrics_set_service_state(&self, state: ServiceState) {
+        self.service_state.set(state as u64);
+    }
+
+    pub fn metrics_block_rolled_back(&self, block_height: u64) {
+        self.indexed_block_height
+            .set(block_height.saturating_sub(1));
+        self.rolled_back_blocks.inc();
+    }
+
+    pub fn metrics_set_bitcoind_block_height(&self, block_height: u64) {
+        self.bitcoind_block_height.set(block_height);
+    }
+
+    pub fn metrics_rows_flushed(&self, table: &str, count: usize) {
+        self.rows_flushed
+            .with_label_values(&[table])
+            .inc_by(count as u64);
+    }
+
+    pub fn metrics_cache_hit(&self, cache: &str) {
+        self.cache_hits.with_label_values(&[cache]).inc();
+    }
+
+    pub fn metrics_cache_miss(&self, cache: &str) {
+        self.cache_misses.with_label_values(&[cache]).inc();
+    }
+}
+
+async fn serve_req(
+    req: Request<Body>,
+    registry: Registry,
+    ctx: Context,
+) -> Result<Response<Body>, hyper::Error> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            try_debug!(ctx, "Prometheus monitoring: responding to metrics request");
+            let encoder = TextEncoder::new();
+            let metric_families = registry.gather();
+            let mut buffer = vec![];
+            let response = match encoder.encode(&metric_families, &mut buffer) {
+                Ok(_) => Response::builder()
+                    .status(200)
+                    .header(CONTENT_TYPE, encoder.format_type())
+                    .body(Body::from(buffer))
+                    .unwrap(),
+                Err(e) => {
+                    try_warn!(
+                        ctx,
+                        "Prometheus monitoring: failed to encode metrics: {}",
+                        e.to_string()
+                    );
+                    Response::builder().status(500).body(Body::empty()).unwrap()
+                }
+            };
+            Ok(response)
+        }
+        (_, _) => {
+            try_debug!(
+                ctx,
+                "Prometheus monitoring: received request with invalid method/route: {}/{}",
+                req.method(),
+                req.uri().path()
+            );
+            Ok(Response::builder().status(404).body(Body::empty()).unwrap())
+        }
+    }
+}
+
+#[cfg_attr(test, mutants::skip)]
+pub async fn start_serving_prometheus_metrics(port: u16, registry: Registry, ctx: Context) {
+    let addr = ([0, 0, 0, 0], port).into();
+    let ctx_clone = ctx.clone();
+    let make_svc = make_service_fn(|_| {
+        let registry = registry.clone();
+        let ctx_clone = ctx_clone.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |r| {
+                serve_req(r, registry.clone(), ctx_clone.clone())
+            }))
+        }
+    });
+    let serve_future = Server::bind(&addr).serve(make_svc);
+    try_info!(ctx, "Prometheus monitoring: listening on port {}", port);
+    if let Err(err) = serve_future.await {
+        try_warn!(ctx, "Prometheus monitoring: server error: {}", err);
+    }
+}
+
+/// Starts the Prometheus metrics server in its own thread.
+#[cfg_attr(test, mutants::skip)]
+pub fn start_metrics_server_runloop(port: u16, monitoring: &PrometheusMonitoring, ctx: &Context) {
+    let registry = monitoring.registry.clone();
+    let ctx = ctx.clone();
+    let _ = hiro_system_kit::thread_named("Prometheus Metrics Runloop").spawn(move || {
+        hiro_system_kit::nestable_block_on(start_serving_prometheus_metrics(port, registry, ctx));
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use prometheus::{Encoder, TextEncoder};
+
+    use super::PrometheusMonitoring;
+
+    #[test]
+    fn tracks_indexed_and_rolled_back_blocks() {
+        let monitoring = PrometheusMonitoring::new();
+        monitoring.metrics_block_indexed(840001, 0.5);
+        assert_eq!(monitoring.indexed_block_height.get(), 840001);
+        assert_eq!(monitoring.block_indexing_duration.get_sample_count(), 1);
+
+        monitoring.metrics_block_rolled_back(840001);
+        assert_eq!(monitoring.indexed_block_height.get(), 840000);
+        assert_eq!(monitoring.rolled_back_blocks.get(), 1);
+    }
+
+    #[test]
+    fn tracks_labeled_counters() {
+        let monitoring = PrometheusMonitoring::new();
+        monitoring.metrics_rows_flushed("ledger", 10);
+        monitoring.metrics_rows_flushed("ledger", 5);
+        monitoring.metrics_cache_hit("rune");
+        monitoring.metrics_cache_miss("rune");
+        monitoring.metrics_cache_miss("rune");
+        assert_eq!(
+            monitoring.rows_flushed.with_label_values(&["ledger"]).get(),
+            15
+        );
+        assert_eq!(monitoring.cache_hits.with_label_values(&["rune"]).get(), 1);
+        assert_eq!(
+            monitoring.cache_misses.with_label_values(&["rune"]).get(),
+            2
+        );
+    }
+
+    #[test]
+    fn encodes_registry() {
+        let monitoring = PrometheusMonitoring::new();
+        monitoring.metrics_set_bitcoind_block_height(850000);
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&monitoring.registry.gather(), &mut buffer)
+            .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        assert!(output.contains("runehook_bitcoind_block_height 850000"));
+    }
+}
@@ -2,6 +2,8 @@ use crate::bitcoind::bitcoind_get_block_height;
 use crate::config::Config;
 use crate::db::cache::index_cache::IndexCache;
 use crate::db::index::{index_block, roll_back_block};
+use crate::db::storage::Storage;
+use crate::shutdown::ShutdownSignal;
 use crate::{try_error, try_info};
 use chainhook_sdk::chainhooks::bitcoin::{
     evaluate_bitcoin_chainhooks_on_chain_event, handle_bitcoin_hook_action,
@@ -18,19 +20,26 @@ use chainhook_sdk::types::{
 };
 use chainhook_sdk::utils::{file_append, send_request, BlockHeights, Context};
 use std::collections::HashMap;
-use tokio_postgres::Client;
 
-pub async fn drop_blocks(start_block: u64, end_block: u64, pg_client: &mut Client, ctx: &Context) {
+pub async fn drop_blocks(
+    start_block: u64,
+    end_block: u64,
+    storage: &mut impl Storage,
+    index_cache: &mut IndexCache,
+    ctx: &Context,
+) -> Result<(), String> {
     for block in start_block..=end_block {
-        roll_back_block(pg_client, block, ctx).await;
+        roll_back_block(storage, index_cache, block, ctx).await?;
     }
+    Ok(())
 }
 
 pub async fn scan_blocks(
     blocks: Vec<u64>,
     config: &Config,
-    pg_client: &mut Client,
+    storage: &mut impl Storage,
     index_cache: &mut IndexCache,
+    shutdown: &ShutdownSignal,
     ctx: &Context,
 ) -> Result<(), String> {
     let predicate = BitcoinChainhookSpecification {
@@ -56,8 +65,9 @@ pub async fn scan_blocks(
         &predicate,
         &config,
         None,
-        pg_client,
+        storage,
         index_cache,
+        shutdown,
         &ctx,
     )
     .await?;
@@ -68,8 +78,9 @@ pub async fn scan_bitcoin_chainstate_via_rpc_using_predicate(
     predicate_spec: &BitcoinChainhookSpecification,
     config: &Config,
     event_observer_config_override: Option<&EventObserverConfig>,
-    pg_client: &mut Client,
+    storage: &mut impl Storage,
     index_cache: &mut IndexCache,
+    shutdown: &ShutdownSignal,
     ctx: &Context,
 ) -> Result<(), String> {
     let mut floating_end_block = false;
@@ -87,7 +98,13 @@ pub async fn scan_bitcoin_chainstate_via_rpc_using_predicate(
         };
         let (end_block, update_end_block) = match predicate_spec.end_block {
             Some(end_block) => (end_block, false),
-            None => (bitcoind_get_block_height(config, ctx), true),
+            None => {
+                let bitcoind_tip = bitcoind_get_block_height(config, ctx);
+                index_cache
+                    .monitoring
+                    .metrics_set_bitcoind_block_height(bitcoind_tip);
+                (bitcoind_tip, true)
+            }
         };
         floating_end_block = update_end_block;
         BlockHeights::BlockRange(start_block, end_block).get_sorted_entries()
@@ -129,7 +146,7 @@ pub async fn scan_bitcoin_chainstate_via_rpc_using_predicate(
             standardize_bitcoin_block(raw_block, &config.event_observer.bitcoin_network, ctx)
                 .unwrap();
 
-        index_block(pg_client, index_cache, &mut block, ctx).await;
+        index_block(storage, index_cache, &mut block, ctx).await?;
 
         match process_block_with_predicates(
             block,
@@ -143,9 +160,21 @@ pub async fn scan_bitcoin_chainstate_via_rpc_using_predicate(
             Err(_) => _err_count += 1,
         }
 
+        // Stop at a block boundary so the last indexed block is fully committed and its actions are delivered.
+        if shutdown.is_requested() {
+            try_info!(
+                ctx,
+                "Shutdown requested, stopping scan after block {current_block_height}"
+            );
+            break;
+        }
+
         // If we configured a "floating" end block, update the scan range with newer blocks that might have arrived to bitcoind.
         if block_heights_to_scan.is_empty() && floating_end_block {
             let bitcoind_tip = bitcoind_get_block_height(config, ctx);
+            index_cache
+                .monitoring
+                .metrics_set_bitcoind_block_height(bitcoind_tip);
             let new_tip = match predicate_spec.end_block {
                 Some(end_block) => {
                     if end_block > bitcoind_tip {
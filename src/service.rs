@@ -1,11 +1,16 @@
+use std::process;
 use std::sync::mpsc::channel;
 
 use crate::bitcoind::bitcoind_get_block_height;
 use crate::config::Config;
 use crate::db::cache::index_cache::IndexCache;
-use crate::db::index::{get_rune_genesis_block_height, index_block, roll_back_block};
-use crate::db::{pg_connect, pg_get_block_height};
+use crate::db::index::{index_block, roll_back_block};
+use crate::db::storage::ConnectStorage;
+use crate::health::ServiceState;
+use crate::mempool::start_mempool_runloop;
+use crate::monitoring::PrometheusMonitoring;
 use crate::scan::bitcoin::scan_blocks;
+use crate::shutdown::ShutdownSignal;
 use crate::{try_error, try_info};
 use chainhook_sdk::observer::BitcoinBlockDataCached;
 use chainhook_sdk::types::BlockIdentifier;
@@ -15,15 +20,29 @@ use chainhook_sdk::{
 };
 use crossbeam_channel::select;
 
-pub async fn start_service(config: &Config, ctx: &Context) -> Result<(), String> {
+pub async fn start_service<S: ConnectStorage + 'static>(
+    config: &Config,
+    monitoring: &PrometheusMonitoring,
+    shutdown: &ShutdownSignal,
+    ctx: &Context,
+) -> Result<(), String> {
     {
-        let mut pg_client = pg_connect(&config, true, ctx).await;
-        let mut index_cache = IndexCache::new(config, &mut pg_client, ctx).await;
+        let mut storage = S::connect(config, true, ctx).await;
+        let mut index_cache = IndexCache::new(config, &mut storage, monitoring, ctx).await;
+        monitoring.metrics_set_service_state(ServiceState::CatchingUp);
         loop {
-            let chain_tip = pg_get_block_height(&mut pg_client, ctx)
+            if shutdown.is_requested() {
+                monitoring.metrics_set_service_state(ServiceState::ShuttingDown);
+                try_info!(ctx, "Shutdown complete");
+                return Ok(());
+            }
+            let chain_tip = storage
+                .get_block_height(ctx)
                 .await
-                .unwrap_or(get_rune_genesis_block_height(config.get_bitcoin_network()) - 1);
+                .unwrap_or(config.runes.genesis_block_height.saturating_sub(1));
+            monitoring.metrics_set_indexed_block_height(chain_tip);
             let bitcoind_chain_tip = bitcoind_get_block_height(config, ctx);
+            monitoring.metrics_set_bitcoind_block_height(bitcoind_chain_tip);
             if bitcoind_chain_tip < chain_tip {
                 try_info!(
                     ctx,
@@ -42,8 +61,9 @@ pub async fn start_service(config: &Config, ctx: &Context) -> Result<(), String>
                 scan_blocks(
                     ((chain_tip + 1)..=bitcoind_chain_tip).collect(),
                     config,
-                    &mut pg_client,
+                    &mut storage,
                     &mut index_cache,
+                    shutdown,
                     ctx,
                 )
                 .await?;
@@ -54,19 +74,20 @@ pub async fn start_service(config: &Config, ctx: &Context) -> Result<(), String>
         }
     }
 
+    if config.mempool.enabled {
+        start_mempool_runloop(config, shutdown, ctx)?;
+    }
+
     // Start chainhook event observer, we're at chain tip.
     let (observer_cmd_tx, observer_cmd_rx) = channel();
     let (observer_event_tx, observer_event_rx) = crossbeam_channel::unbounded();
-    let observer_sidecar = set_up_observer_sidecar_runloop(config, ctx)
+    let observer_sidecar = set_up_observer_sidecar_runloop::<S>(config, monitoring, shutdown, ctx)
         .await
         .expect("unable to set up observer sidecar");
     let event_observer_config = config.event_observer.clone();
-    let context = if config.event_observer.display_logs {
-        ctx.clone()
-    } else {
-        Context::empty()
-    };
+    let context = config.logs.chainhook_context();
     let observer_cmd_tx_moved = observer_cmd_tx.clone();
+    shutdown.register_observer(observer_cmd_tx);
 
     let _ = std::thread::spawn(move || {
         start_event_observer(
@@ -80,6 +101,7 @@ pub async fn start_service(config: &Config, ctx: &Context) -> Result<(), String>
         )
         .expect("unable to start Stacks chain observer");
     });
+    monitoring.metrics_set_service_state(ServiceState::Streaming);
     try_info!(ctx, "Listening for new blocks via Chainhook SDK");
 
     loop {
@@ -98,12 +120,21 @@ pub async fn start_service(config: &Config, ctx: &Context) -> Result<(), String>
             _ => {}
         }
     }
+    // Wait for the block currently being indexed by the sidecar, if any, to be committed.
+    monitoring.metrics_set_service_state(ServiceState::ShuttingDown);
+    let _indexing_guard = shutdown.lock_indexing();
+    try_info!(ctx, "Shutdown complete");
     Ok(())
 }
 
 #[cfg_attr(test, mutants::skip)]
-pub async fn set_up_observer_sidecar_runloop(
+// The indexing lock is only ever contended by the service thread waiting for shutdown, never by another task on the sidecar
+// runtime, so holding it across awaits cannot deadlock.
+#[allow(clippy::await_holding_lock)]
+pub async fn set_up_observer_sidecar_runloop<S: ConnectStorage + 'static>(
     config: &Config,
+    monitoring: &PrometheusMonitoring,
+    shutdown: &ShutdownSignal,
     ctx: &Context,
 ) -> Result<ObserverSidecar, String> {
     // Sidecar will be receiving blocks to mutate
@@ -118,22 +149,40 @@ pub async fn set_up_observer_sidecar_runloop(
     };
     let ctx = ctx.clone();
     let config = config.clone();
+    let monitoring = monitoring.clone();
+    let shutdown = shutdown.clone();
 
     let _ = hiro_system_kit::thread_named("Observer Sidecar Runloop").spawn(move || {
         hiro_system_kit::nestable_block_on(async {
-            let mut index_cache =
-                IndexCache::new(&config, &mut pg_connect(&config, false, &ctx).await, &ctx).await;
+            let mut index_cache = IndexCache::new(
+                &config,
+                &mut S::connect(&config, false, &ctx).await,
+                &monitoring,
+                &ctx,
+            )
+            .await;
             loop {
                 select! {
                     recv(block_mutator_in_rx) -> msg => {
                         if let Ok((mut blocks_to_mutate, blocks_ids_to_rollback)) = msg {
-                            chainhook_sidecar_mutate_blocks(
+                            let _indexing_guard = shutdown.lock_indexing();
+                            if shutdown.is_requested() {
+                                try_info!(ctx, "Shutdown requested, ignoring blocks from Chainhook SDK");
+                                let _ = block_mutator_out_tx.send(blocks_to_mutate);
+                                continue;
+                            }
+                            // A block that failed to commit can't be skipped, the next one would be indexed on top of it.
+                            if let Err(e) = chainhook_sidecar_mutate_blocks::<S>(
                                 &mut index_cache,
                                 &mut blocks_to_mutate,
                                 &blocks_ids_to_rollback,
                                 &config,
                                 &ctx,
-                            ).await;
+                            ).await {
+                                try_error!(ctx, "Error mutating blocks, exiting: {}", e);
+                                shutdown.request();
+                                process::exit(1);
+                            }
                             let _ = block_mutator_out_tx.send(blocks_to_mutate);
                         }
                     }
@@ -150,22 +199,27 @@ pub async fn set_up_observer_sidecar_runloop(
     Ok(observer_sidecar)
 }
 
-pub async fn chainhook_sidecar_mutate_blocks(
+pub async fn chainhook_sidecar_mutate_blocks<S: ConnectStorage>(
     index_cache: &mut IndexCache,
     blocks_to_mutate: &mut Vec<BitcoinBlockDataCached>,
     block_ids_to_rollback: &Vec<BlockIdentifier>,
     config: &Config,
     ctx: &Context,
-) {
+) -> Result<(), String> {
     try_info!(ctx, "Received mutate blocks message from Chainhook SDK");
-    let mut pg_client = pg_connect(&config, false, &ctx).await;
+    let mut storage = S::connect(config, false, ctx).await;
     for block_id in block_ids_to_rollback.iter() {
-        roll_back_block(&mut pg_client, block_id.index, ctx).await;
+        roll_back_block(&mut storage, index_cache, block_id.index, ctx).await?;
     }
     for cache in blocks_to_mutate.iter_mut() {
+        // Blocks streamed by the observer are at the bitcoind chain tip.
+        index_cache
+            .monitoring
+            .metrics_set_bitcoind_block_height(cache.block.block_identifier.index);
         if !cache.processed_by_sidecar {
-            index_block(&mut pg_client, index_cache, &mut cache.block, ctx).await;
+            index_block(&mut storage, index_cache, &mut cache.block, ctx).await?;
             cache.processed_by_sidecar = true;
         }
     }
+    Ok(())
 }
@@ -0,0 +1,128 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::Sender,
+    Arc, Mutex, MutexGuard,
+};
+
+use chainhook_sdk::{observer::ObserverCommand, utils::Context};
+
+use crate::{try_info, try_warn};
+
+/// Tracks termination requests (SIGINT / SIGTERM) so long running commands can stop at a clean block boundary instead of being
+/// killed in the middle of a DB transaction. Cloning this struct is cheap and all clones share the same state.
+#[derive(Clone, Default)]
+pub struct ShutdownSignal {
+    requested: Arc<AtomicBool>,
+    /// Command channel of the Chainhook SDK event observer, if one is running. Used to stop it on shutdown.
+    observer_cmd_tx: Arc<Mutex<Option<Sender<ObserverCommand>>>>,
+    /// Held while a block is being indexed or rolled back so shutdown can wait for its DB transaction to commit.
+    indexing_lock: Arc<Mutex<()>>,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        ShutdownSignal::default()
+    }
+
+    /// Installs the process signal handler. A second signal received while shutting down exits immediately.
+    #[cfg_attr(test, mutants::skip)]
+    pub fn install_handler(&self, ctx: &Context) -> Result<(), String> {
+        let signal = self.clone();
+        let ctx = ctx.clone();
+        ctrlc::set_handler(move || {
+            if signal.is_requested() {
+                try_warn!(
+                    ctx,
+                    "Termination signal received again, exiting immediately"
+                );
+                std::process::exit(130);
+            }
+            try_info!(
+                ctx,
+                "Termination signal received, shutting down after the current block"
+            );
+            signal.request();
+        })
+        .map_err(|e| format!("unable to install signal handler: {e}"))
+    }
+
+    /// Marks shutdown as requested and asks the event observer, if any, to terminate. The flag is set while holding the
+    /// observer lock so a concurrent `register_observer` either sees it or is seen here.
+    pub fn request(&self) {
+        let observer_cmd_tx = self.observer_cmd_tx.lock().unwrap();
+        self.requested.store(true, Ordering::SeqCst);
+        if let Some(tx) = observer_cmd_tx.as_ref() {
+            let _ = tx.send(ObserverCommand::Terminate);
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Registers the event observer command channel so it can be terminated on shutdown.
+    pub fn register_observer(&self, observer_cmd_tx: Sender<ObserverCommand>) {
+        let mut registered = self.observer_cmd_tx.lock().unwrap();
+        if self.is_requested() {
+            let _ = observer_cmd_tx.send(ObserverCommand::Terminate);
+        }
+        *registered = Some(observer_cmd_tx);
+    }
+
+    /// Blocks until no block is being indexed and keeps indexing paused while the returned guard is alive.
+    pub fn lock_indexing(&self) -> MutexGuard<'_, ()> {
+        self.indexing_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::channel;
+
+    use chainhook_sdk::observer::ObserverCommand;
+
+    use super::ShutdownSignal;
+
+    #[test]
+    fn request_is_shared_across_clones() {
+        let signal = ShutdownSignal::new();
+        let clone = signal.clone();
+        assert!(!clone.is_requested());
+        signal.request();
+        assert!(clone.is_requested());
+    }
+
+    #[test]
+    fn terminates_registered_observer() {
+        let signal = ShutdownSignal::new();
+        let (tx, rx) = channel();
+        signal.register_observer(tx);
+        assert!(rx.try_recv().is_err());
+        signal.request();
+        assert!(matches!(rx.try_recv(), Ok(ObserverCommand::Terminate)));
+    }
+
+    #[test]
+    fn terminates_observer_registered_after_request() {
+        let signal = ShutdownSignal::new();
+        signal.request();
+        let (tx, rx) = channel();
+        signal.register_observer(tx);
+        assert!(matches!(rx.try_recv(), Ok(ObserverCommand::Terminate)));
+    }
+
+    #[test]
+    fn terminates_observer_registered_concurrently() {
+        for _ in 0..100 {
+            let signal = ShutdownSignal::new();
+            let (tx, rx) = channel();
+            let requester = signal.clone();
+            let handle = std::thread::spawn(move || requester.request());
+            signal.register_observer(tx);
+            handle.join().unwrap();
+            assert!(matches!(rx.try_recv(), Ok(ObserverCommand::Terminate)));
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use std::io::{BufReader, Read, Write};
+
+use bytes::Bytes;
+use chainhook_sdk::utils::Context;
+use futures_util::{pin_mut, SinkExt, StreamExt};
+use sha2::{Digest, Sha256};
+use tokio_postgres::{Client, GenericClient, IsolationLevel};
+
+use crate::{db::types::pg_numeric_u64::PgNumericU64, try_info};
+
+/// Written uncompressed at the start of every snapshot so foreign files are rejected early.
+const MAGIC: &[u8; 8] = b"RUNEHOOK";
+
+/// Version of the archive layout. Bump it whenever the framing or the manifest changes in a non backwards compatible way.
+pub const SNAPSHOT_FORMAT_VERSION: u16 = 1;
+
+const MANIFEST_SECTION: &str = "manifest";
+
+/// Describes the contents of a snapshot. Stored as the last section of the archive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub format_version: u16,
+    pub migration_version: i32,
+    pub network: String,
+    pub block_height: u64,
+    pub block_hash: String,
+    pub tables: Vec<SnapshotTable>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotTable {
+    pub name: String,
+    pub rows: u64,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// Writes a snapshot archive: the magic bytes and format version followed by a zstd stream of named sections. Each
+/// section is a sequence of length prefixed chunks ending with an empty chunk, and the archive ends with an empty name.
+struct SnapshotWriter<W: Write> {
+    out: zstd::Encoder<'static, W>,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    fn new(mut inner: W) -> Result<Self, String> {
+        inner
+            .write_all(MAGIC)
+            .and_then(|_| inner.write_all(&SNAPSHOT_FORMAT_VERSION.to_be_bytes()))
+            .map_err(|e| format!("unable to write snapshot header: {e}"))?;
+        let out = zstd::Encoder::new(inner, 0)
+            .map_err(|e| format!("unable to start snapshot compression: {e}"))?;
+        Ok(SnapshotWriter { out })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        self.out
+            .write_all(data)
+            .map_err(|e| format!("unable to write snapshot: {e}"))
+    }
+
+    fn begin_section(&mut self, name: &str) -> Result<(), String> {
+        self.write(&(name.len() as u16).to_be_bytes())?;
+        self.write(name.as_bytes())
+    }
+
+    fn write_chunk(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.write(&(data.len() as u32).to_be_bytes())?;
+        self.write(data)
+    }
+
+    fn end_section(&mut self) -> Result<(), String> {
+        self.write(&0u32.to_be_bytes())
+    }
+
+    fn finish(mut self) -> Result<W, String> {
+        self.begin_section("")?;
+        self.out
+            .finish()
+            .map_err(|e| format!("unable to finish snapshot: {e}"))
+    }
+}
+
+struct SnapshotReader<R: Read> {
+    input: zstd::Decoder<'static, BufReader<R>>,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    fn new(mut inner: R) -> Result<Self, String> {
+        let mut header = [0u8; 10];
+        inner
+            .read_exact(&mut header)
+            .map_err(|e| format!("unable to read snapshot header: {e}"))?;
+        if &header[..8] != MAGIC {
+            return Err("not a runehook snapshot".to_string());
+        }
+        let version = u16::from_be_bytes([header[8], header[9]]);
+        if version != SNAPSHOT_FORMAT_VERSION {
+            return Err(format!(
+                "unsupported snapshot format version {version}, expected {SNAPSHOT_FORMAT_VERSION}"
+            ));
+        }
+        let input = zstd::Decoder::new(inner)
+            .map_err(|e| format!("unable to start snapshot decompression: {e}"))?;
+        Ok(SnapshotReader { input })
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        self.input
+            .read_exact(buf)
+            .map_err(|e| format!("unable to read snapshot, the archive may be truncated: {e}"))
+    }
+
+    /// Returns the name of the next section, or `None` once the end of the archive is reached.
+    fn next_section(&mut self) -> Result<Option<String>, String> {
+        let mut len = [0u8; 2];
+        self.read(&mut len)?;
+        let mut name = vec![0u8; u16::from_be_bytes(len) as usize];
+        if name.is_empty() {
+            return Ok(None);
+        }
+        self.read(&mut name)?;
+        String::from_utf8(name)
+            .map(Some)
+            .map_err(|_| "invalid snapshot section name".to_string())
+    }
+
+    /// Returns the next chunk of the current section, or `None` at the end of the section.
+    fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let mut len = [0u8; 4];
+        self.read(&mut len)?;
+        let mut chunk = vec![0u8; u32::from_be_bytes(len) as usize];
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+        self.read(&mut chunk)?;
+        Ok(Some(chunk))
+    }
+}
+
+/// Every table of the current schema except the migrations history, which `snapshot restore` recreates by running
+/// migrations, and the short-lived `pending_ledger`.
+async fn pg_get_snapshot_tables<T: GenericClient>(client: &T) -> Result<Vec<String>, String> {
+    let rows = client
+        .query(
+            "SELECT table_name::TEXT FROM information_schema.tables
+            WHERE table_schema = current_schema() AND table_type = 'BASE TABLE'
+                AND table_name NOT IN ('pgmigrations', 'pending_ledger')
+            ORDER BY table_name",
+            &[],
+        )
+        .await
+        .map_err(|e| format!("error listing tables: {e}"))?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Returns the height and hash of the highest block with ledger activity, which is where indexing resumes.
+async fn pg_get_indexed_tip<T: GenericClient>(client: &T) -> Result<Option<(u64, String)>, String> {
+    let row = client
+        .query_opt(
+            "SELECT block_height, block_hash FROM ledger ORDER BY block_height DESC LIMIT 1",
+            &[],
+        )
+        .await
+        .map_err(|e| format!("error reading indexed tip: {e}"))?;
+    Ok(row.map(|row| {
+        let height: PgNumericU64 = row.get("block_height");
+        (height.0, row.get("block_hash"))
+    }))
+}
+
+/// Dumps every table into `out` from a single consistent view of the database.
+pub async fn create_snapshot<W: Write>(
+    client: &mut Client,
+    migration_version: i32,
+    network: &str,
+    out: W,
+    ctx: &Context,
+) -> Result<SnapshotManifest, String> {
+    let db_tx = client
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .await
+        .map_err(|e| format!("unable to begin transaction: {e}"))?;
+    let Some((block_height, block_hash)) = pg_get_indexed_tip(&db_tx).await? else {
+        return Err("Nothing has been indexed yet".to_string());
+    };
+    let mut manifest = SnapshotManifest {
+        format_version: SNAPSHOT_FORMAT_VERSION,
+        migration_version,
+        network: network.to_string(),
+        block_height,
+        block_hash,
+        tables: vec![],
+    };
+
+    let mut writer = SnapshotWriter::new(out)?;
+    for table in pg_get_snapshot_tables(&db_tx).await? {
+        let rows: i64 = db_tx
+            .query_one(&format!("SELECT COUNT(*) FROM {table}"), &[])
+            .await
+            .map_err(|e| format!("error counting {table}: {e}"))?
+            .get(0);
+        let stream = db_tx
+            .copy_out(&format!("COPY {table} TO STDOUT (FORMAT binary)"))
+            .await
+            .map_err(|e| format!("error dumping {table}: {e}"))?;
+        pin_mut!(stream);
+        writer.begin_section(&table)?;
+        let mut hasher = Sha256::new();
+        let mut bytes = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("error dumping {table}: {e}"))?;
+            hasher.update(&chunk);
+            bytes += chunk.len() as u64;
+            writer.write_chunk(&chunk)?;
+        }
+        writer.end_section()?;
+        try_info!(ctx, "Dumped {} rows of {}", rows, table);
+        manifest.tables.push(SnapshotTable {
+            name: table,
+            rows: rows as u64,
+            bytes,
+            sha256: hex::encode(hasher.finalize()),
+        });
+    }
+    db_tx
+        .commit()
+        .await
+        .map_err(|e| format!("unable to close transaction: {e}"))?;
+
+    let json = serde_json::to_vec(&manifest)
+        .map_err(|e| format!("unable to serialize snapshot manifest: {e}"))?;
+    writer.begin_section(MANIFEST_SECTION)?;
+    writer.write_chunk(&json)?;
+    writer.end_section()?;
+    writer
+        .finish()?
+        .flush()
+        .map_err(|e| format!("unable to write snapshot: {e}"))?;
+    Ok(manifest)
+}
+
+/// Reads the whole archive, checks every table against the checksums of the manifest and returns the manifest.
+pub fn verify_snapshot<R: Read>(input: R) -> Result<SnapshotManifest, String> {
+    let mut reader = SnapshotReader::new(input)?;
+    let mut checksums = vec![];
+    let mut manifest: Option<SnapshotManifest> = None;
+    while let Some(section) = reader.next_section()? {
+        if manifest.is_some() {
+            return Err(format!("unexpected section {section} after the manifest"));
+        }
+        if section == MANIFEST_SECTION {
+            let mut json = vec![];
+            while let Some(chunk) = reader.next_chunk()? {
+                json.extend(chunk);
+            }
+            manifest = Some(
+                serde_json::from_slice(&json)
+                    .map_err(|e| format!("invalid snapshot manifest: {e}"))?,
+            );
+            continue;
+        }
+        let mut hasher = Sha256::new();
+        let mut bytes = 0;
+        while let Some(chunk) = reader.next_chunk()? {
+            hasher.update(&chunk);
+            bytes += chunk.len() as u64;
+        }
+        checksums.push((section, bytes, hex::encode(hasher.finalize())));
+    }
+    let manifest = manifest.ok_or("snapshot has no manifest")?;
+    if manifest.tables.len() != checksums.len() {
+        return Err("snapshot tables do not match its manifest".to_string());
+    }
+    for (table, (name, bytes, sha256)) in manifest.tables.iter().zip(checksums) {
+        if table.name != name || table.bytes != bytes || table.sha256 != sha256 {
+            return Err(format!("checksum mismatch for table {name}"));
+        }
+    }
+    Ok(manifest)
+}
+
+/// Loads a snapshot previously checked with `verify_snapshot` into a migrated database without indexed blocks. All
+/// tables are replaced in a single transaction, which is only committed if the restored tip matches the manifest.
+pub async fn restore_snapshot<R: Read>(
+    client: &mut Client,
+    manifest: &SnapshotManifest,
+    input: R,
+    ctx: &Context,
+) -> Result<(), String> {
+    if pg_get_indexed_tip(client).await?.is_some() {
+        return Err("Snapshots can only be restored into an empty database".to_string());
+    }
+    let mut reader = SnapshotReader::new(input)?;
+    let db_tx = client
+        .transaction()
+        .await
+        .map_err(|e| format!("unable to begin transaction: {e}"))?;
+    for table in manifest.tables.iter() {
+        let name = &table.name;
+        if reader.next_section()?.as_ref() != Some(name) {
+            return Err(format!("snapshot section {name} is missing"));
+        }
+        db_tx
+            .batch_execute(&format!("TRUNCATE {name}"))
+            .await
+            .map_err(|e| format!("error truncating {name}: {e}"))?;
+        let sink = db_tx
+            .copy_in(&format!("COPY {name} FROM STDIN (FORMAT binary)"))
+            .await
+            .map_err(|e| format!("error restoring {name}: {e}"))?;
+        pin_mut!(sink);
+        while let Some(chunk) = reader.next_chunk()? {
+            sink.send(Bytes::from(chunk))
+                .await
+                .map_err(|e| format!("error restoring {name}: {e}"))?;
+        }
+        let rows = sink
+            .as_mut()
+            .finish()
+            .await
+            .map_err(|e| format!("error restoring {name}: {e}"))?;
+        if rows != table.rows {
+            return Err(format!(
+                "restored {rows} rows into {name}, expected {}",
+                table.rows
+            ));
+        }
+        try_info!(ctx, "Restored {} rows of {}", rows, name);
+    }
+
+    let tip = pg_get_indexed_tip(&db_tx).await?;
+    if tip != Some((manifest.block_height, manifest.block_hash.clone())) {
+        return Err(format!(
+            "restored tip {tip:?} does not match the snapshot tip {} {}",
+            manifest.block_height, manifest.block_hash
+        ));
+    }
+    db_tx
+        .commit()
+        .await
+        .map_err(|e| format!("unable to commit restored snapshot: {e}"))
+}
+
+#[cfg(test)]
+mod test {
+    use chainhook_sdk::utils::Context;
+
+    use crate::db::{pg_get_block_height, pg_test_drop_schema, pg_test_schema_client};
+
+    use super::{
+        create_snapshot, restore_snapshot, verify_snapshot, SnapshotReader, SnapshotWriter,
+    };
+
+    #[test]
+    fn round_trips_sections() {
+        let mut writer = SnapshotWriter::new(vec![]).unwrap();
+        writer.begin_section("ledger").unwrap();
+        writer.write_chunk(b"abc").unwrap();
+        writer.write_chunk(b"").unwrap();
+        writer.write_chunk(b"de").unwrap();
+        writer.end_section().unwrap();
+        let archive = writer.finish().unwrap();
+
+        let mut reader = SnapshotReader::new(archive.as_slice()).unwrap();
+        assert_eq!(reader.next_section().unwrap(), Some("ledger".to_string()));
+        assert_eq!(reader.next_chunk().unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(reader.next_chunk().unwrap(), Some(b"de".to_vec()));
+        assert_eq!(reader.next_chunk().unwrap(), None);
+        assert_eq!(reader.next_section().unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_foreign_and_truncated_files() {
+        assert!(SnapshotReader::new(b"PAR1PAR1PAR1".as_slice()).is_err());
+
+        let mut writer = SnapshotWriter::new(vec![]).unwrap();
+        writer.begin_section("ledger").unwrap();
+        writer.write_chunk(&[7; 1000]).unwrap();
+        writer.end_section().unwrap();
+        let archive = writer.finish().unwrap();
+        assert!(verify_snapshot(&archive[..archive.len() / 2]).is_err());
+    }
+
+    #[tokio::test]
+    async fn restores_snapshot_into_empty_database() {
+        let ctx = Context::empty();
+        let mut source = pg_test_schema_client("test_snapshot_source", &ctx).await;
+        source
+            .batch_execute(
+                "INSERT INTO ledger (rune_id, block_hash, block_height, tx_index, event_index, tx_id, output, address,
+                    amount, operation, timestamp)
+                VALUES
+                    ('1:0', 'h1', 840001, 0, 0, 't1', NULL, NULL, 340282366920938463463374607431768211455, 'mint', 1),
+                    ('1:0', 'h2', 840002, 0, 1, 't2', 0, 'a', 100, 'receive', 2);
+                INSERT INTO balance_changes VALUES ('1:0', 840002, 'a', 100, 1);",
+            )
+            .await
+            .unwrap();
+        let mut archive = vec![];
+        let manifest = create_snapshot(&mut source, 4, "mainnet", &mut archive, &ctx)
+            .await
+            .unwrap();
+        pg_test_drop_schema(&source, "test_snapshot_source").await;
+
+        assert_eq!(manifest.block_height, 840002);
+        assert_eq!(manifest.block_hash, "h2");
+        assert_eq!(verify_snapshot(archive.as_slice()).unwrap(), manifest);
+        let mut corrupted = archive.clone();
+        let last = corrupted.len() - 20;
+        corrupted[last] ^= 0xff;
+        assert!(verify_snapshot(corrupted.as_slice()).is_err());
+
+        let mut target = pg_test_schema_client("test_snapshot_target", &ctx).await;
+        restore_snapshot(&mut target, &manifest, archive.as_slice(), &ctx)
+            .await
+            .unwrap();
+        let tip = pg_get_block_height(&mut target, &ctx).await;
+        let balances: i64 = target
+            .query_one("SELECT COUNT(*) FROM balance_changes", &[])
+            .await
+            .unwrap()
+            .get(0);
+        let restore_again =
+            restore_snapshot(&mut target, &manifest, archive.as_slice(), &ctx).await;
+        pg_test_drop_schema(&target, "test_snapshot_target").await;
+
+        assert_eq!(tip, Some(840002));
+        assert_eq!(balances, 1);
+        assert!(restore_again.is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! Detection of rune sales made through PSBT marketplaces. A seller lists runes by signing the input that holds them with
+//! `SIGHASH_SINGLE | SIGHASH_ANYONECANPAY`, which only commits to the output at the same index, the seller's payment. Any
+//! buyer can then complete the transaction with their own inputs and outputs. The ledger only records these as `send` and
+//! `receive` operations, so each transaction is checked for listed inputs while it is indexed.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use ordinals::RuneId;
+
+use crate::db::{
+    cache::input_rune_balance::InputRuneBalance,
+    models::{
+        db_ledger_entry::DbLedgerEntry, db_ledger_operation::DbLedgerOperation, db_trade::DbTrade,
+    },
+    types::{pg_numeric_u128::PgNumericU128, pg_numeric_u64::PgNumericU64},
+};
+
+/// `SIGHASH_SINGLE | SIGHASH_ANYONECANPAY`.
+const LISTING_SIGHASH: u8 = 0x83;
+
+/// A transaction input signed with `LISTING_SIGHASH` that holds runes.
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    /// Rune balances held by the input.
+    pub balances: HashMap<RuneId, Vec<InputRuneBalance>>,
+    /// Sats paid by the output at the input's index, `None` if the transaction has no such output.
+    pub payment: Option<u64>,
+    /// Address of the output at the input's index, `None` if it has no address.
+    pub payment_address: Option<String>,
+}
+
+/// Returns true if `witness` (hex encoded items) spends a taproot key path or P2WPKH input with a `LISTING_SIGHASH`
+/// signature. Script path spends are not recognized, since their signatures can't be told apart from other pushes.
+pub fn is_listing_input(witness: &[String]) -> bool {
+    let Ok(items) = witness
+        .iter()
+        .map(|item| hex::decode(item.strip_prefix("0x").unwrap_or(item)))
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return false;
+    };
+    let signature = match items.as_slice() {
+        // Taproot key path, 64 byte signatures use `SIGHASH_DEFAULT`.
+        [signature] if signature.len() == 65 => signature,
+        // P2WPKH, native or nested in P2SH.
+        [signature, pubkey] if pubkey.len() == 33 => signature,
+        _ => return false,
+    };
+    signature.last() == Some(&LISTING_SIGHASH)
+}
+
+/// Sats paid per whole rune when `amount` base units of a rune with `divisibility` are sold for `price` sats.
+pub fn price_per_unit(price: u64, amount: u128, divisibility: u8) -> f64 {
+    price as f64 * 10f64.powi(divisibility as i32) / amount as f64
+}
+
+/// Labels the likely sales of a transaction, given its listed inputs and ledger `entries`. Each listing must hold a single
+/// rune owned by a single address, the seller, since its payment can't be split otherwise, and must pay that same address.
+/// A rune is considered sold when the seller sends it to exactly one other address, the buyer, and is paid for it. Only
+/// the listed balances count as sold, other runes the seller moves in the same transaction are not part of the sale.
+/// `divisibilities` holds the divisibility of every rune in `listings`, keyed by rune id.
+pub fn detect_trades(
+    listings: &[Listing],
+    entries: &[DbLedgerEntry],
+    divisibilities: &HashMap<String, u8>,
+) -> Vec<DbTrade> {
+    // Sats paid and runes listed for each `(rune_id, seller)`, summed over every listing of the same balance.
+    let mut prices: BTreeMap<(String, String), (u64, u128)> = BTreeMap::new();
+    for listing in listings.iter() {
+        let Some(payment) = listing.payment else {
+            continue;
+        };
+        let sellers = listing
+            .balances
+            .iter()
+            .flat_map(|(rune_id, balances)| {
+                balances
+                    .iter()
+                    .map(move |balance| (rune_id.to_string(), balance.address.clone()))
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let [(rune_id, Some(seller))] = sellers.as_slice() else {
+            continue;
+        };
+        if listing.payment_address.as_ref() != Some(seller) {
+            continue;
+        }
+        let listed: u128 = listing
+            .balances
+            .values()
+            .flatten()
+            .map(|balance| balance.amount)
+            .sum();
+        let (price, amount) = prices.entry((rune_id.clone(), seller.clone())).or_default();
+        *price += payment;
+        *amount += listed;
+    }
+
+    let mut trades = vec![];
+    for ((rune_id, seller), (price, listed)) in prices.into_iter() {
+        let sends = entries
+            .iter()
+            .filter(|entry| {
+                entry.operation == DbLedgerOperation::Send
+                    && entry.rune_id == rune_id
+                    && entry.address.as_ref() == Some(&seller)
+                    && entry
+                        .receiver_address
+                        .as_ref()
+                        .is_some_and(|receiver| receiver != &seller)
+            })
+            .collect::<Vec<_>>();
+        let buyers = sends
+            .iter()
+            .filter_map(|entry| entry.receiver_address.as_ref())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        // Runes split between several addresses are more likely a distribution than a sale.
+        let ([buyer], Some(first)) = (buyers.as_slice(), sends.first()) else {
+            continue;
+        };
+        let amount = sends
+            .iter()
+            .filter_map(|entry| entry.amount.map(|amount| amount.0))
+            .sum::<u128>()
+            .min(listed);
+        if amount == 0 || price == 0 {
+            continue;
+        }
+        let divisibility = divisibilities.get(&rune_id).copied().unwrap_or(0);
+        trades.push(DbTrade {
+            rune_id,
+            block_hash: first.block_hash.clone(),
+            block_height: first.block_height,
+            tx_index: first.tx_index,
+            tx_id: first.tx_id.clone(),
+            seller_address: seller,
+            buyer_address: (*buyer).clone(),
+            amount: PgNumericU128(amount),
+            price: PgNumericU64(price),
+            price_per_unit: price_per_unit(price, amount, divisibility),
+            timestamp: first.timestamp,
+        });
+    }
+    trades
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use maplit::hashmap;
+    use ordinals::RuneId;
+    use test_case::test_case;
+
+    use crate::db::{
+        cache::input_rune_balance::InputRuneBalance,
+        models::{db_ledger_entry::DbLedgerEntry, db_ledger_operation::DbLedgerOperation},
+        types::pg_numeric_u128::PgNumericU128,
+    };
+
+    use super::{detect_trades, is_listing_input, price_per_unit, Listing};
+
+    const RUNE_ID: RuneId = RuneId {
+        block: 840000,
+        tx: 1,
+    };
+
+    fn send(seller: &str, buyer: &str, amount: u128) -> DbLedgerEntry {
+        DbLedgerEntry {
+            rune_id: RUNE_ID.to_string(),
+            operation: DbLedgerOperation::Send,
+            address: Some(seller.to_string()),
+            receiver_address: Some(buyer.to_string()),
+            amount: Some(PgNumericU128(amount)),
+            ..Default::default()
+        }
+    }
+
+    fn listing(sellers: &[&str], payment: Option<u64>) -> Listing {
+        paid_listing(sellers, payment, sellers.first().copied())
+    }
+
+    fn paid_listing(sellers: &[&str], payment: Option<u64>, payee: Option<&str>) -> Listing {
+        Listing {
+            balances: hashmap! {
+                RUNE_ID => sellers
+                    .iter()
+                    .map(|seller| InputRuneBalance {
+                        address: Some(seller.to_string()),
+                        script_pubkey: None,
+                        amount: 1000,
+                    })
+                    .collect(),
+            },
+            payment,
+            payment_address: payee.map(|payee| payee.to_string()),
+        }
+    }
+
+    #[test_case(vec![format!("{}83", "01".repeat(64))] => true; "taproot key path")]
+    #[test_case(vec![format!("0x{}83", "30".repeat(71)), "02".repeat(33)] => true; "p2wpkh")]
+    #[test_case(vec!["01".repeat(64)] => false; "taproot default sighash")]
+    #[test_case(vec![format!("{}01", "01".repeat(64))] => false; "taproot sighash all")]
+    #[test_case(vec![format!("{}01", "30".repeat(71)), "02".repeat(33)] => false; "p2wpkh sighash all")]
+    #[test_case(vec![format!("{}83", "01".repeat(64)), "c0".to_string(), "51".to_string()] => false; "script path")]
+    #[test_case(vec![] => false; "no witness")]
+    #[test_case(vec!["zz".to_string()] => false; "undecodable witness")]
+    fn detects_listing_inputs(witness: Vec<String>) -> bool {
+        is_listing_input(&witness)
+    }
+
+    #[test_case(10_000, 1000, 0 => 10.0; "indivisible")]
+    #[test_case(10_000, 1000, 2 => 1000.0; "divisible")]
+    fn prices_whole_units(price: u64, amount: u128, divisibility: u8) -> f64 {
+        price_per_unit(price, amount, divisibility)
+    }
+
+    #[test]
+    fn detects_sale() {
+        let trades = detect_trades(
+            &[listing(&["seller"], Some(10_000))],
+            &[send("seller", "buyer", 600), send("seller", "buyer", 400)],
+            &hashmap! { RUNE_ID.to_string() => 2 },
+        );
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].seller_address, "seller");
+        assert_eq!(trades[0].buyer_address, "buyer");
+        assert_eq!(trades[0].amount.0, 1000);
+        assert_eq!(trades[0].price.0, 10_000);
+        assert_eq!(trades[0].price_per_unit, 1000.0);
+    }
+
+    #[test]
+    fn only_counts_listed_runes() {
+        let trades = detect_trades(
+            &[listing(&["seller"], Some(10_000))],
+            &[send("seller", "buyer", 1000), send("seller", "buyer", 5000)],
+            &HashMap::new(),
+        );
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].amount.0, 1000);
+        assert_eq!(trades[0].price_per_unit, 10.0);
+    }
+
+    #[test]
+    fn sums_listings_of_the_same_seller() {
+        let trades = detect_trades(
+            &[
+                listing(&["seller"], Some(10_000)),
+                listing(&["seller"], Some(5_000)),
+            ],
+            &[send("seller", "buyer", 1000)],
+            &HashMap::new(),
+        );
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price.0, 15_000);
+        assert_eq!(trades[0].amount.0, 1000);
+    }
+
+    #[test_case(vec![listing(&["seller"], None)], vec![send("seller", "buyer", 1000)]; "no payment output")]
+    #[test_case(vec![listing(&["seller"], Some(0))], vec![send("seller", "buyer", 1000)]; "zero payment")]
+    #[test_case(
+        vec![paid_listing(&["seller"], Some(10_000), Some("other"))],
+        vec![send("seller", "buyer", 1000)];
+        "payment to someone else"
+    )]
+    #[test_case(
+        vec![paid_listing(&["seller"], Some(10_000), None)],
+        vec![send("seller", "buyer", 1000)];
+        "payment without address"
+    )]
+    #[test_case(vec![listing(&["seller", "other"], Some(10_000))], vec![send("seller", "buyer", 1000)]; "several sellers")]
+    #[test_case(vec![listing(&["seller"], Some(10_000))], vec![send("seller", "seller", 1000)]; "self transfer")]
+    #[test_case(vec![listing(&["seller"], Some(10_000))], vec![send("other", "buyer", 1000)]; "sent by someone else")]
+    #[test_case(
+        vec![listing(&["seller"], Some(10_000))],
+        vec![send("seller", "buyer", 500), send("seller", "other", 500)];
+        "several receivers"
+    )]
+    #[test_case(vec![], vec![send("seller", "buyer", 1000)]; "not listed")]
+    fn ignores_non_sales(listings: Vec<Listing>, entries: Vec<DbLedgerEntry>) {
+        assert!(detect_trades(&listings, &entries, &HashMap::new()).is_empty());
+    }
+}